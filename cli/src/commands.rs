@@ -0,0 +1,455 @@
+use crate::output::{ChannelRecord, GuildRecord, MessageRecord, Printer, SentRecord};
+
+use crust_client::{
+    content::{self, ContentStore, ContentType},
+    error::{ClientError, ClientResult},
+    message::{harmony_messages_to_ui_messages, Attachment, Message},
+    output::OutputFuture,
+    permission, Client, InnerClient, Output, Session, Url,
+};
+use harmony_rust_sdk::{
+    api::chat::{
+        event::{ChannelCreated, Event, GuildAddedToList, GuildRemovedFromList},
+        GetGuildListRequest,
+    },
+    client::api::{
+        chat::{
+            channel::{
+                get_channel_messages, get_guild_channels, GetChannelMessages,
+                GetChannelMessagesSelfBuilder,
+            },
+            guild::{get_guild, get_guild_list},
+            profile::get_user,
+            EventSource, GuildId, UserId,
+        },
+        rest::{upload_extract_id, FileId},
+    },
+};
+use std::{convert::TryFrom, path::PathBuf, sync::Arc};
+
+/// A client restored from a session of the GUI.
+///
+/// Only the session and the local cache of the account are read. Nothing is written to the
+/// cache, so this can be used while the GUI is running.
+pub struct Cli {
+    client: Client,
+    printer: Printer,
+    /// Whether the guild list was fetched from the homeserver.
+    guild_list_fetched: bool,
+}
+
+impl Cli {
+    /// Restores the session of an account, see [`Session::find_file`].
+    pub async fn restore(account: Option<u64>, printer: Printer) -> ClientResult<Self> {
+        let content_store = ContentStore::default();
+        content_store.create_req_dirs()?;
+
+        let session_file = Session::find_file(&content_store, account).await?;
+        let client = Client::restore_session(session_file, Arc::new(content_store)).await?;
+        Ok(Self {
+            client,
+            printer,
+            guild_list_fetched: false,
+        })
+    }
+
+    /// Fetches the guild list, which tells us which guilds are hosted on other homeservers.
+    async fn fetch_guild_list(&mut self) -> ClientResult<()> {
+        if self.guild_list_fetched {
+            return Ok(());
+        }
+
+        let guilds = get_guild_list(self.client.inner(), GetGuildListRequest {})
+            .await?
+            .guilds;
+        // Guilds we left may still be in the cache
+        let left = self
+            .client
+            .guilds
+            .keys()
+            .filter(|guild_id| !guilds.iter().any(|guild| guild.guild_id == **guild_id))
+            .copied()
+            .collect::<Vec<_>>();
+        for guild_id in left {
+            self.client
+                .process_event(Event::GuildRemovedFromList(GuildRemovedFromList {
+                    guild_id,
+                    ..Default::default()
+                }));
+        }
+        for guild in guilds {
+            // Post processing is done as needed, see `Cli::inner_for_guild`
+            self.client
+                .process_event(Event::GuildAddedToList(GuildAddedToList {
+                    guild_id: guild.guild_id,
+                    homeserver: guild.host,
+                }));
+        }
+
+        self.guild_list_fetched = true;
+        Ok(())
+    }
+
+    /// Logs into a homeserver that hosts some of our guilds.
+    async fn federate(&mut self, homeserver: Url) -> ClientResult<()> {
+        if let Some(federating) = self.client.federate(homeserver) {
+            match federating.await {
+                Output::Federated {
+                    homeserver,
+                    foreign,
+                } => {
+                    self.client.federation_complete(homeserver, *foreign);
+                }
+                Output::FederationFailed { homeserver, err } => {
+                    self.client.federation_failed(&homeserver);
+                    return Err(*err);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the client that requests about a guild should be made with, logging into the
+    /// homeserver of the guild if needed.
+    async fn inner_for_guild(&mut self, guild_id: u64) -> ClientResult<InnerClient> {
+        self.fetch_guild_list().await?;
+
+        let homeserver = self
+            .client
+            .guilds
+            .get(&guild_id)
+            .ok_or_else(|| ClientError::Custom(format!("Not in a guild with ID {}", guild_id)))?
+            .homeserver
+            .clone();
+        if let Some(homeserver) = homeserver {
+            self.federate(homeserver).await?;
+        }
+
+        Ok(self.client.inner_for_guild(guild_id).clone())
+    }
+
+    /// Returns the name a message is shown with, fetching the profile of its sender if needed.
+    async fn sender_name(&mut self, inner: &InnerClient, message: &Message) -> String {
+        if let Some(overrides) = &message.overrides {
+            return overrides.name.clone();
+        }
+
+        if let Some(member) = self
+            .client
+            .members
+            .get(&message.sender)
+            .filter(|member| !member.username.is_empty())
+        {
+            return member.username.clone();
+        }
+
+        match get_user(inner, UserId::new(message.sender)).await {
+            Ok(profile) => {
+                self.client
+                    .members
+                    .entry(message.sender)
+                    .or_default()
+                    .username = profile.user_name.clone();
+                profile.user_name
+            }
+            Err(err) => {
+                tracing::warn!("couldn't fetch profile of {}: {}", message.sender, err);
+                message.sender.to_string()
+            }
+        }
+    }
+
+    async fn print_message(
+        &mut self,
+        inner: &InnerClient,
+        guild_id: u64,
+        channel_id: u64,
+        message: Message,
+    ) -> ClientResult<()> {
+        let sender_name = self.sender_name(inner, &message).await;
+        self.printer.print(&MessageRecord::new(
+            guild_id,
+            channel_id,
+            message,
+            sender_name,
+        ))
+    }
+
+    /// Prints the guilds we are in.
+    pub async fn guilds(&mut self) -> ClientResult<()> {
+        self.fetch_guild_list().await?;
+
+        let mut guild_ids = self.client.guilds.keys().copied().collect::<Vec<_>>();
+        guild_ids.sort_unstable();
+        for guild_id in guild_ids {
+            let name = match self.inner_for_guild(guild_id).await {
+                Ok(inner) => get_guild(&inner, GuildId::new(guild_id))
+                    .await
+                    .map(|guild| guild.guild_name)
+                    .map_err(ClientError::from),
+                Err(err) => Err(err),
+            };
+            let guild = &self.client.guilds[&guild_id];
+            let name = name.unwrap_or_else(|err| {
+                // Fall back to the cached name, one unreachable homeserver shouldn't stop us
+                tracing::warn!("couldn't fetch guild {}: {}", guild_id, err);
+                guild.name.clone()
+            });
+
+            self.printer.print(&GuildRecord {
+                guild_id,
+                name,
+                homeserver: guild.homeserver.as_ref().map(Url::to_string),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the channels of a guild.
+    pub async fn channels(&mut self, guild_id: u64) -> ClientResult<()> {
+        let inner = self.inner_for_guild(guild_id).await?;
+
+        let channels = get_guild_channels(&inner, GuildId::new(guild_id))
+            .await?
+            .channels;
+        for channel in channels {
+            self.printer.print(&ChannelRecord {
+                guild_id,
+                channel_id: channel.channel_id,
+                name: channel.channel_name,
+                is_category: channel.is_category,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the latest `count` messages of a channel, oldest first.
+    pub async fn history(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        count: usize,
+    ) -> ClientResult<()> {
+        let inner = self.inner_for_guild(guild_id).await?;
+
+        let mut messages = Vec::with_capacity(count);
+        let mut before = 0;
+        while messages.len() < count {
+            let response = get_channel_messages(
+                &inner,
+                GetChannelMessages::new(guild_id, channel_id).before_message(before),
+            )
+            .await?;
+            let reached_top = response.reached_top || response.messages.is_empty();
+
+            // Messages come newest first
+            messages.extend(response.messages);
+            match messages.last() {
+                Some(oldest) if !reached_top => before = oldest.message_id,
+                _ => break,
+            }
+        }
+        messages.truncate(count);
+
+        for message in harmony_messages_to_ui_messages(messages) {
+            self.print_message(&inner, guild_id, channel_id, message)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints messages of a guild as they are sent, only those of `channel_id` if it's given.
+    ///
+    /// Runs until the event stream is closed.
+    pub async fn tail(&mut self, guild_id: u64, channel_id: Option<u64>) -> ClientResult<()> {
+        let inner = self.inner_for_guild(guild_id).await?;
+
+        let mut socket = inner
+            .subscribe_events(vec![EventSource::Guild(guild_id)])
+            .await?;
+        while let Some(event) = socket.get_event().await {
+            let message = match event? {
+                Event::SentMessage(message_sent) => message_sent.message,
+                _ => None,
+            };
+
+            if let Some(message) = message.filter(|message| {
+                message.guild_id == guild_id
+                    && channel_id.map_or(true, |channel_id| message.channel_id == channel_id)
+            }) {
+                let message_channel_id = message.channel_id;
+                self.print_message(&inner, guild_id, message_channel_id, Message::from(message))
+                    .await?;
+            }
+        }
+
+        Err(ClientError::Custom(
+            "The event stream was closed".to_string(),
+        ))
+    }
+
+    /// Sends a message and prints its ID.
+    pub async fn send(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        content: String,
+    ) -> ClientResult<()> {
+        if content.trim().is_empty() {
+            return Err(ClientError::Custom("There is nothing to send".to_string()));
+        }
+
+        self.check_can_send(guild_id, channel_id).await?;
+        self.send_message(
+            guild_id,
+            channel_id,
+            Message {
+                content,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Uploads files, sends them in a message and prints its ID.
+    pub async fn upload(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        files: Vec<PathBuf>,
+        content: Option<String>,
+    ) -> ClientResult<()> {
+        // Don't upload anything if the message can't be sent
+        self.check_can_send(guild_id, channel_id).await?;
+        let inner = self.inner_for_guild(guild_id).await?;
+
+        let mut attachments = Vec::with_capacity(files.len());
+        for path in files {
+            let size = u32::try_from(tokio::fs::metadata(&path).await?.len()).map_err(|_| {
+                ClientError::Custom(format!("{} is too big to upload", path.display()))
+            })?;
+            let data = tokio::fs::read(&path).await?;
+            let mimetype = content::infer_type_from_bytes(&data);
+            let name = content::get_filename(&path);
+
+            let id = upload_extract_id(&inner, name.clone(), mimetype.clone(), data).await?;
+            attachments.push(Attachment {
+                id: FileId::Hmc(inner.make_hmc(id)),
+                kind: ContentType::new(&mimetype),
+                name,
+                size,
+            });
+        }
+
+        self.send_message(
+            guild_id,
+            channel_id,
+            Message {
+                content: content.unwrap_or_default(),
+                attachments,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Makes sure we are allowed to send messages in a channel.
+    ///
+    /// Channels that aren't in the cache are added to the client, so it can keep track of what
+    /// we send in them.
+    async fn check_can_send(&mut self, guild_id: u64, channel_id: u64) -> ClientResult<()> {
+        self.inner_for_guild(guild_id).await?;
+        if self.client.get_channel(guild_id, channel_id).is_none() {
+            self.client
+                .process_event(Event::CreatedChannel(ChannelCreated {
+                    guild_id,
+                    channel_id,
+                    ..Default::default()
+                }));
+        }
+
+        match self
+            .client
+            .fetch_permissions(guild_id, vec![channel_id])
+            .await
+        {
+            Output::Events(events) => {
+                for event in events {
+                    self.client.process_event(event);
+                }
+            }
+            Output::Error(err) => return Err(*err),
+            _ => {}
+        }
+
+        let can_send = self
+            .client
+            .get_channel(guild_id, channel_id)
+            .map_or(false, |channel| {
+                channel.has_perm(permission::SEND_MESSAGES) != Some(false)
+            });
+        if can_send {
+            Ok(())
+        } else {
+            Err(ClientError::Custom(format!(
+                "You aren't allowed to send messages in channel {}",
+                channel_id
+            )))
+        }
+    }
+
+    /// Sends a message through the outbox of the client, retrying like the GUI does, and prints
+    /// its ID.
+    ///
+    /// The outbox isn't saved, so a message that couldn't be sent is lost.
+    async fn send_message(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message: Message,
+    ) -> ClientResult<()> {
+        let mut attempt: OutputFuture = match self.client.send_msg(guild_id, channel_id, message) {
+            Some(sending) => Box::pin(sending),
+            None => {
+                return Err(ClientError::Custom(format!(
+                    "Not in a channel with ID {}",
+                    channel_id
+                )))
+            }
+        };
+
+        loop {
+            match attempt.await {
+                Output::MessageSent {
+                    message_id,
+                    transaction_id,
+                    ..
+                } => {
+                    self.client.outbox_done(transaction_id);
+                    return self.printer.print(&SentRecord {
+                        guild_id,
+                        channel_id,
+                        message_id,
+                    });
+                }
+                Output::OutboxAttemptFailed {
+                    transaction_id,
+                    err,
+                } => match self.client.outbox_attempt_failed(transaction_id) {
+                    Some(next) => attempt = Box::pin(next),
+                    None => return Err(*err),
+                },
+                Output::Error(err) => return Err(*err),
+                _ => {
+                    return Err(ClientError::Custom(
+                        "The message couldn't be sent".to_string(),
+                    ))
+                }
+            }
+        }
+    }
+}
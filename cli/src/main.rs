@@ -0,0 +1,136 @@
+//! Command line companion of Crust, for scripting against an account that was logged into
+//! with Crust.
+//!
+//! Results are printed one per line, as plain text or JSON lines with `--json`. Errors are
+//! printed to stderr, and make the process exit with a non-zero code.
+
+use commands::Cli;
+use output::{Format, Printer};
+
+use crust_client::error::{ClientError, ClientResult};
+use std::{io::ErrorKind, path::PathBuf, process};
+use structopt::StructOpt;
+use tokio::io::AsyncReadExt;
+use tracing_subscriber::EnvFilter;
+
+mod commands;
+mod output;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "crust-cli",
+    about = "Script against a Harmony account that was logged into with Crust."
+)]
+struct Args {
+    /// User ID of the account to use, needed if you are logged into more than one account
+    #[structopt(long, global = true)]
+    account: Option<u64>,
+    /// Print results as JSON lines instead of plain text
+    #[structopt(long, global = true)]
+    json: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// List the guilds you are in
+    Guilds,
+    /// List the channels of a guild
+    Channels { guild_id: u64 },
+    /// Send a message, read from stdin if no text is given
+    Send {
+        guild_id: u64,
+        channel_id: u64,
+        text: Vec<String>,
+    },
+    /// Upload files and send them in a message
+    Upload {
+        guild_id: u64,
+        channel_id: u64,
+        #[structopt(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+        /// Text to send along with the files
+        #[structopt(short, long)]
+        message: Option<String>,
+    },
+    /// Print the latest messages of a channel, oldest first
+    History {
+        guild_id: u64,
+        channel_id: u64,
+        /// How many messages to print
+        #[structopt(short = "n", long, default_value = "50")]
+        count: usize,
+    },
+    /// Print messages of a guild as they are sent, only of a channel if one is given
+    Tail {
+        guild_id: u64,
+        channel_id: Option<u64>,
+    },
+}
+
+async fn run(args: Args) -> ClientResult<()> {
+    let format = if args.json {
+        Format::Json
+    } else {
+        Format::Text
+    };
+    let mut cli = Cli::restore(args.account, Printer::new(format)).await?;
+
+    match args.command {
+        Command::Guilds => cli.guilds().await,
+        Command::Channels { guild_id } => cli.channels(guild_id).await,
+        Command::Send {
+            guild_id,
+            channel_id,
+            text,
+        } => {
+            let content = if text.is_empty() {
+                let mut content = String::new();
+                tokio::io::stdin().read_to_string(&mut content).await?;
+                content.trim_end_matches(&['\r', '\n'][..]).to_string()
+            } else {
+                text.join(" ")
+            };
+            cli.send(guild_id, channel_id, content).await
+        }
+        Command::Upload {
+            guild_id,
+            channel_id,
+            files,
+            message,
+        } => cli.upload(guild_id, channel_id, files, message).await,
+        Command::History {
+            guild_id,
+            channel_id,
+            count,
+        } => cli.history(guild_id, channel_id, count).await,
+        Command::Tail {
+            guild_id,
+            channel_id,
+        } => cli.tail(guild_id, channel_id).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::from_args();
+
+    // Logs go to stderr, so they don't get mixed with the results
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    match run(args).await {
+        Ok(()) => {}
+        // Whatever we print to went away, like `head` after reading enough lines
+        Err(ClientError::IOError(err)) if err.kind() == ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
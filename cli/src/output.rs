@@ -0,0 +1,178 @@
+//! Printing results as plain text or JSON lines.
+//!
+//! Every result is printed on its own line. Plain text lines are tab separated, with tabs and
+//! newlines in values escaped, so they can be processed with tools like `cut` and `awk`.
+
+use crust_client::{
+    content::{file_id_to_string, ContentType},
+    error::ClientResult,
+    message::Message,
+};
+
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Something that can be printed as a line.
+pub trait Record: Serialize {
+    /// Returns the plain text form of this record.
+    fn text(&self) -> String;
+}
+
+#[derive(Debug)]
+pub struct Printer {
+    format: Format,
+}
+
+impl Printer {
+    pub fn new(format: Format) -> Self {
+        Self { format }
+    }
+
+    /// Prints a record and flushes it, so consumers get it right away.
+    pub fn print(&self, record: &impl Record) -> ClientResult<()> {
+        let line = match self.format {
+            Format::Text => record.text(),
+            Format::Json => serde_json::to_string(record)?,
+        };
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Escapes a value for a plain text line.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[derive(Debug, Serialize)]
+pub struct GuildRecord {
+    pub guild_id: u64,
+    pub name: String,
+    /// Homeserver the guild is hosted on, `None` if it's our own homeserver.
+    pub homeserver: Option<String>,
+}
+
+impl Record for GuildRecord {
+    fn text(&self) -> String {
+        match &self.homeserver {
+            Some(homeserver) => format!(
+                "{}\t{}\t{}",
+                self.guild_id,
+                escape(&self.name),
+                escape(homeserver)
+            ),
+            None => format!("{}\t{}", self.guild_id, escape(&self.name)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelRecord {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub name: String,
+    pub is_category: bool,
+}
+
+impl Record for ChannelRecord {
+    fn text(&self) -> String {
+        // Same prefixes as the channel list of the GUI
+        let prefix = if self.is_category { "+" } else { "#" };
+        format!("{}\t{}{}", self.channel_id, prefix, escape(&self.name))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttachmentRecord {
+    pub id: String,
+    pub name: String,
+    pub kind: ContentType,
+    pub size: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageRecord {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub sender_id: u64,
+    pub sender_name: String,
+    /// UTC time the message was sent at, in RFC 3339 format.
+    pub timestamp: String,
+    pub content: String,
+    pub reply_to: Option<u64>,
+    pub attachments: Vec<AttachmentRecord>,
+}
+
+impl MessageRecord {
+    pub fn new(guild_id: u64, channel_id: u64, message: Message, sender_name: String) -> Self {
+        Self {
+            guild_id,
+            channel_id,
+            message_id: message.id.id().unwrap_or_default(),
+            sender_id: message.sender,
+            sender_name,
+            timestamp: message.timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            content: message.content,
+            reply_to: message.reply_to,
+            attachments: message
+                .attachments
+                .into_iter()
+                .map(|attachment| AttachmentRecord {
+                    id: file_id_to_string(&attachment.id),
+                    name: attachment.name,
+                    kind: attachment.kind,
+                    size: attachment.size,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Record for MessageRecord {
+    fn text(&self) -> String {
+        let mut content = self.content.clone();
+        for attachment in &self.attachments {
+            if !content.is_empty() {
+                content.push(' ');
+            }
+            content.push_str(&format!("[{}]({})", attachment.name, attachment.id));
+        }
+
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.message_id,
+            self.timestamp,
+            escape(&self.sender_name),
+            escape(&content)
+        )
+    }
+}
+
+/// A message we sent.
+#[derive(Debug, Serialize)]
+pub struct SentRecord {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+}
+
+impl Record for SentRecord {
+    fn text(&self) -> String {
+        self.message_id.to_string()
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use crust_client::{
+    channel::Channel,
+    message::{Message, MessageId, Messages},
+};
+
+/// How many messages the channels in these benchmarks have.
+const CHANNEL_SIZE: u64 = 20_000;
+/// How many messages the homeserver returns in a page of history.
+const PAGE_SIZE: u64 = 50;
+
+fn message(id: u64) -> Message {
+    Message {
+        id: MessageId::Ack(id),
+        content: format!("message {}", id),
+        timestamp: NaiveDateTime::from_timestamp(1_600_000_000 + id as i64, 0),
+        ..Default::default()
+    }
+}
+
+fn page(newest: u64) -> Vec<Message> {
+    (newest.saturating_sub(PAGE_SIZE)..newest)
+        .map(message)
+        .collect()
+}
+
+fn channel() -> Channel {
+    Channel {
+        messages: Messages::from((0..CHANNEL_SIZE).map(message).collect::<Vec<_>>()),
+        ..Default::default()
+    }
+}
+
+/// Loading a whole channel, one page at a time like scrolling up does, and from the cache.
+fn bulk_history_load(c: &mut Criterion) {
+    c.bench_function("load history page by page", |b| {
+        b.iter(|| {
+            let mut messages = Messages::default();
+            let mut newest = CHANNEL_SIZE;
+            while newest > 0 {
+                messages.merge(page(newest));
+                newest = newest.saturating_sub(PAGE_SIZE);
+            }
+            black_box(messages)
+        })
+    });
+
+    c.bench_function("load history from cache", |b| {
+        b.iter_batched(
+            || (0..CHANNEL_SIZE).rev().map(message).collect::<Vec<_>>(),
+            |cached| black_box(Messages::from(cached)),
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("backfill overlapping the latest messages", |b| {
+        b.iter_batched(
+            channel,
+            |mut channel| {
+                let backfill = (CHANNEL_SIZE - PAGE_SIZE * 2..CHANNEL_SIZE + PAGE_SIZE)
+                    .map(message)
+                    .collect();
+                black_box(channel.merge_messages(backfill))
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+/// Many edits, deletions and acknowledgements spread over a large channel.
+fn edit_storm(c: &mut Criterion) {
+    let edited = (0..CHANNEL_SIZE).step_by(7).collect::<Vec<_>>();
+
+    c.bench_function("edit messages in place", |b| {
+        b.iter_batched(
+            channel,
+            |mut channel| {
+                for id in &edited {
+                    if let Some(message) = channel.messages.by_id_mut(MessageId::Ack(*id)) {
+                        message.content.push_str(" (edited)");
+                    }
+                }
+                black_box(channel)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("delete messages", |b| {
+        b.iter_batched(
+            channel,
+            |mut channel| {
+                for id in &edited {
+                    channel.messages.remove(MessageId::Ack(*id));
+                }
+                black_box(channel)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("send and acknowledge messages", |b| {
+        b.iter_batched(
+            channel,
+            |mut channel| {
+                for transaction_id in 0..PAGE_SIZE {
+                    let message = Message {
+                        id: MessageId::Unack(transaction_id),
+                        ..message(CHANNEL_SIZE + transaction_id)
+                    };
+                    channel.merge_messages(vec![message]);
+                }
+                for transaction_id in 0..PAGE_SIZE {
+                    channel.ack_message(transaction_id, CHANNEL_SIZE + transaction_id);
+                }
+                black_box(channel)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+/// Finding where messages are and getting a range of messages, like rendering and jumping to a
+/// message do.
+fn timeline_lookups(c: &mut Criterion) {
+    let channel = channel();
+    let messages = &channel.messages;
+    let len = messages.len();
+
+    c.bench_function("position of the latest message", |b| {
+        b.iter(|| black_box(messages.position(MessageId::Ack(CHANNEL_SIZE - 1))))
+    });
+
+    c.bench_function("position of the oldest message", |b| {
+        b.iter(|| black_box(messages.position(MessageId::Ack(0))))
+    });
+
+    c.bench_function("slice the latest page", |b| {
+        b.iter(|| black_box(messages.slice(len - PAGE_SIZE as usize..len)))
+    });
+
+    c.bench_function("slice a page in the middle", |b| {
+        b.iter(|| black_box(messages.slice(len / 2..len / 2 + PAGE_SIZE as usize)))
+    });
+}
+
+criterion_group!(benches, bulk_history_load, edit_storm, timeline_lookups);
+criterion_main!(benches);
@@ -0,0 +1,338 @@
+//! Local on-disk cache of guilds, channels, members and messages.
+//!
+//! Guilds (with their channels and members) and member profiles are stored in one state file,
+//! while every channel gets its own file for messages, so only channels that changed need to be
+//! rewritten. Pending sends and edits are kept in a separate outbox file, and notification rules
+//! in a file of their own. Everything is stored as JSON under [`ContentStore::cache_dir`].
+//!
+//! [`ContentStore::cache_dir`]: super::content::ContentStore::cache_dir
+
+use super::{
+    channel::{Channel, Channels},
+    content::file_id_to_string,
+    error::ClientResult,
+    guild::Guild,
+    member::Member,
+    message::Message,
+    outbox::OutboxEntry,
+    post_thumbnails, Client, PostProcessEvent,
+};
+
+use harmony_rust_sdk::{
+    api::chat::event::{Event, MessageDeleted},
+    client::api::rest::FileId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct CachedState {
+    guilds: Vec<CachedGuild>,
+    members: Vec<CachedMember>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedGuild {
+    guild_id: u64,
+    name: String,
+    picture: Option<String>,
+    #[serde(default)]
+    homeserver: Option<String>,
+    channels: Vec<CachedChannel>,
+    members: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedChannel {
+    channel_id: u64,
+    name: String,
+    is_category: bool,
+    // Harmony has no read marker API, so read markers only live in the local cache
+    #[serde(default)]
+    last_read: Option<u64>,
+    #[serde(default)]
+    unread: usize,
+    #[serde(default)]
+    mentions: usize,
+    #[serde(default)]
+    reached_top: bool,
+    #[serde(default)]
+    gaps: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedMember {
+    user_id: u64,
+    username: String,
+    avatar_url: Option<String>,
+}
+
+impl Client {
+    /// Marks the parts of the cache that are affected by an event as dirty.
+    pub(super) fn mark_cache_dirty(&mut self, event: &Event) {
+        match event {
+            Event::SentMessage(message_sent) => {
+                if let Some(message) = &message_sent.message {
+                    self.dirty_channels
+                        .insert((message.guild_id, message.channel_id));
+                }
+            }
+            Event::EditedMessage(message_updated) => {
+                self.dirty_channels
+                    .insert((message_updated.guild_id, message_updated.channel_id));
+            }
+            Event::ReactionAdded(reaction_added) => {
+                self.dirty_channels
+                    .insert((reaction_added.guild_id, reaction_added.channel_id));
+            }
+            Event::ReactionRemoved(reaction_removed) => {
+                self.dirty_channels
+                    .insert((reaction_removed.guild_id, reaction_removed.channel_id));
+            }
+            Event::DeletedMessage(MessageDeleted {
+                guild_id,
+                channel_id,
+                ..
+            }) => {
+                self.dirty_channels.insert((*guild_id, *channel_id));
+            }
+            // Roles and permissions aren't cached, they are fetched again when we connect
+            Event::Typing(_)
+            | Event::ActionPerformed(_)
+            | Event::RoleCreated(_)
+            | Event::RoleUpdated(_)
+            | Event::RoleDeleted(_)
+            | Event::RoleMoved(_)
+            | Event::RolePermsUpdated(_)
+            | Event::UserRolesUpdated(_)
+            | Event::PermissionUpdated(_) => {}
+            _ => self.state_dirty = true,
+        }
+    }
+
+    /// Serializes the parts of the cache that changed since the last call.
+    ///
+    /// Returns the files that need to be written, which can be done with [`write_cache_files`].
+    pub fn take_dirty_cache(&mut self) -> ClientResult<Vec<(PathBuf, Vec<u8>)>> {
+        let mut files = Vec::with_capacity(self.dirty_channels.len() + 1);
+
+        if self.state_dirty {
+            let state = CachedState {
+                guilds: self
+                    .guilds
+                    .iter()
+                    .map(|(guild_id, guild)| CachedGuild {
+                        guild_id: *guild_id,
+                        name: guild.name.clone(),
+                        picture: guild.picture.as_ref().map(file_id_to_string),
+                        homeserver: guild.homeserver.as_ref().map(|url| url.to_string()),
+                        channels: guild
+                            .channels
+                            .iter()
+                            .map(|(channel_id, channel)| CachedChannel {
+                                channel_id: *channel_id,
+                                name: channel.name.clone(),
+                                is_category: channel.is_category,
+                                last_read: channel.last_read,
+                                unread: channel.unread,
+                                mentions: channel.mentions,
+                                reached_top: channel.reached_top,
+                                gaps: channel.gaps.clone(),
+                            })
+                            .collect(),
+                        members: guild.members.iter().copied().collect(),
+                    })
+                    .collect(),
+                members: self
+                    .members
+                    .iter()
+                    .map(|(user_id, member)| CachedMember {
+                        user_id: *user_id,
+                        username: member.username.clone(),
+                        avatar_url: member.avatar_url.as_ref().map(file_id_to_string),
+                    })
+                    .collect(),
+            };
+            files.push((
+                self.content_store.state_cache_file(),
+                serde_json::to_vec(&state)?,
+            ));
+            self.state_dirty = false;
+        }
+
+        for (guild_id, channel_id) in self.dirty_channels.drain() {
+            if let Some(channel) = self
+                .guilds
+                .get(&guild_id)
+                .map(|guild| guild.channels.get(&channel_id))
+                .flatten()
+            {
+                // Unacknowledged messages aren't cached, they are either sent or thrown away
+                let messages = channel
+                    .messages
+                    .iter()
+                    .filter(|message| message.id.is_ack())
+                    .collect::<Vec<_>>();
+                files.push((
+                    self.content_store.channel_cache_file(guild_id, channel_id),
+                    serde_json::to_vec(&messages)?,
+                ));
+            }
+        }
+
+        if let Some(outbox) = self.take_dirty_outbox()? {
+            files.push(outbox);
+        }
+
+        if let Some(rules) = self.take_dirty_rules()? {
+            files.push(rules);
+        }
+
+        Ok(files)
+    }
+
+    /// Restores guilds, channels, members, messages, the outbox and the notification rules from
+    /// the local cache.
+    ///
+    /// Does nothing if there is no cache yet.
+    pub async fn restore_cache(&mut self) -> ClientResult<()> {
+        let state: CachedState = read_json(&self.content_store.state_cache_file())
+            .await?
+            .unwrap_or_default();
+
+        for member in state.members {
+            self.members.insert(
+                member.user_id,
+                Member {
+                    username: member.username,
+                    avatar_url: member
+                        .avatar_url
+                        .map(|id| FileId::from_str(&id).ok())
+                        .flatten(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        for guild in state.guilds {
+            let mut channels = Channels::with_capacity(guild.channels.len());
+
+            for channel in guild.channels {
+                let path = self
+                    .content_store
+                    .channel_cache_file(guild.guild_id, channel.channel_id);
+                let messages: Vec<Message> = match read_json(&path).await {
+                    Ok(messages) => messages.unwrap_or_default(),
+                    Err(err) => {
+                        tracing::warn!("couldn't read cached messages from {:?}: {}", path, err);
+                        Vec::new()
+                    }
+                };
+
+                for message in &messages {
+                    self.search_index
+                        .insert(guild.guild_id, channel.channel_id, message);
+                }
+
+                let mut restored = Channel {
+                    name: channel.name,
+                    is_category: channel.is_category,
+                    last_read: channel.last_read,
+                    unread: channel.unread,
+                    mentions: channel.mentions,
+                    reached_top: channel.reached_top,
+                    gaps: channel.gaps,
+                    ..Default::default()
+                };
+                // Older versions could cache messages out of order or more than once
+                restored.merge_messages(messages);
+                channels.insert(channel.channel_id, restored);
+            }
+
+            self.guilds.insert(
+                guild.guild_id,
+                Guild {
+                    name: guild.name,
+                    picture: guild.picture.map(|id| FileId::from_str(&id).ok()).flatten(),
+                    homeserver: guild.homeserver.map(|url| url.parse().ok()).flatten(),
+                    channels,
+                    members: guild.members.into_iter().collect(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let outbox: Vec<(u64, OutboxEntry)> = read_json(&self.content_store.outbox_file())
+            .await?
+            .unwrap_or_default();
+        self.restore_outbox(outbox);
+
+        let rules = read_json(&self.content_store.notification_rules_file())
+            .await?
+            .unwrap_or_default();
+        self.restore_rules(rules);
+
+        Ok(())
+    }
+
+    /// Returns the thumbnails needed to display what was restored from the cache, looking at the
+    /// last `shown_messages` messages of each channel.
+    pub fn cached_thumbnails(&self, shown_messages: usize) -> Vec<PostProcessEvent> {
+        let mut post = Vec::new();
+
+        for (guild_id, guild) in &self.guilds {
+            if let Some(id) = guild.picture.clone() {
+                post.push(PostProcessEvent::FetchThumbnail {
+                    guild_id: Some(*guild_id),
+                    id,
+                });
+            }
+
+            for channel in guild.channels.values() {
+                let len = channel.messages.len();
+                for message in channel
+                    .messages
+                    .slice(len.saturating_sub(shown_messages)..len)
+                {
+                    post_thumbnails(&mut post, *guild_id, message);
+                }
+            }
+        }
+
+        for member in self.members.values() {
+            if let Some(id) = member.avatar_url.clone() {
+                post.push(PostProcessEvent::FetchThumbnail { guild_id: None, id });
+            }
+        }
+
+        post
+    }
+}
+
+/// Writes the cache files returned by [`Client::take_dirty_cache`].
+pub async fn write_cache_files(files: Vec<(PathBuf, Vec<u8>)>) -> ClientResult<()> {
+    for (path, data) in files {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Write to a temporary file first, so we never leave a half written cache file behind
+        let temp_path = path.with_extension("tmp");
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(temp_path, path).await?;
+    }
+
+    Ok(())
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> ClientResult<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
@@ -0,0 +1,87 @@
+use super::message::{Message, MessageId, Messages};
+use ahash::AHashMap;
+use indexmap::IndexMap;
+
+pub type Channels = IndexMap<u64, Channel>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Channel {
+    pub name: String,
+    pub is_category: bool,
+    pub messages: Messages,
+    pub loading_messages_history: bool,
+    /// Whether we have the first message of this channel, so there is no older history to load.
+    pub reached_top: bool,
+    /// IDs of pinned messages, `None` if they weren't fetched yet.
+    pub pinned_messages: Option<Vec<u64>>,
+    /// Permissions of the current user in this channel, keyed by permission query.
+    pub permissions: AHashMap<String, bool>,
+    /// Whether the latest messages were fetched from the homeserver in this session.
+    ///
+    /// Channels restored from the local cache start out as not fetched, and channels stay not
+    /// fetched while they have gaps, so the gaps are fetched the next time they are opened.
+    pub fetched: bool,
+    /// IDs of messages that older messages may be missing right before.
+    ///
+    /// Missed messages are fetched a few pages at a time, see [`Client::fetch_channel`].
+    ///
+    /// [`Client::fetch_channel`]: super::Client::fetch_channel
+    pub gaps: Vec<u64>,
+    /// ID of the last message that was read in this channel, `None` if nothing was read yet.
+    pub last_read: Option<u64>,
+    /// How many messages were received since the last read message.
+    pub unread: usize,
+    /// How many of the unread messages mention the current user.
+    pub mentions: usize,
+}
+
+impl Channel {
+    /// Merges messages into the timeline, see [`Messages::merge`].
+    ///
+    /// Returns how many of the messages are new.
+    pub fn merge_messages(&mut self, messages: Vec<Message>) -> usize {
+        self.messages.merge(messages)
+    }
+
+    /// Marks a message we sent as acknowledged by the homeserver, moving it among the other
+    /// acknowledged messages.
+    pub fn ack_message(&mut self, transaction_id: u64, message_id: u64) {
+        let mut message = match self.messages.remove(MessageId::Unack(transaction_id)) {
+            Some(message) => message,
+            None => return,
+        };
+
+        // The homeserver may have sent us the message already
+        if !self.messages.contains(MessageId::Ack(message_id)) {
+            message.id = MessageId::Ack(message_id);
+            self.messages.merge(vec![message]);
+        }
+    }
+
+    /// Returns whether we have a permission in this channel, or `None` if it wasn't queried yet.
+    pub fn has_perm(&self, query: &str) -> Option<bool> {
+        self.permissions.get(query).copied()
+    }
+
+    /// Marks every message in this channel as read.
+    ///
+    /// Returns whether the read marker or counts changed.
+    pub fn mark_read(&mut self) -> bool {
+        let last_id = self
+            .messages
+            .iter()
+            .rev()
+            .find_map(|message| message.id.id());
+        let changed = self.unread != 0
+            || self.mentions != 0
+            || (last_id.is_some() && last_id != self.last_read);
+
+        if last_id.is_some() {
+            self.last_read = last_id;
+        }
+        self.unread = 0;
+        self.mentions = 0;
+
+        changed
+    }
+}
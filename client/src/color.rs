@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// An RGBA color, which frontends convert to their own color type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+#[serde(from = "[u8; 4]", into = "[u8; 4]")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn from_rgb8(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// Converts a color sent by the homeserver (`0xRRGGBB`).
+    pub const fn from_rgb_i32(color: i32) -> Self {
+        Self::from_rgb8(
+            ((color >> 16) & 255) as u8,
+            ((color >> 8) & 255) as u8,
+            (color & 255) as u8,
+        )
+    }
+}
+
+impl From<[u8; 4]> for Color {
+    fn from([r, g, b, a]: [u8; 4]) -> Self {
+        Self { r, g, b, a }
+    }
+}
+
+impl From<Color> for [u8; 4] {
+    fn from(color: Color) -> Self {
+        [color.r, color.g, color.b, color.a]
+    }
+}
@@ -0,0 +1,216 @@
+use super::ClientError;
+use harmony_rust_sdk::{api::exports::hrpc::url::Url, client::api::rest::FileId};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const SESSION_FILENAME: &str = "session";
+pub const SESSIONS_DIR_NAME: &str = "sessions";
+pub const LOG_FILENAME: &str = "log";
+pub const CONTENT_DIR_NAME: &str = "content";
+pub const CACHE_DIR_NAME: &str = "cache";
+pub const STATE_CACHE_FILENAME: &str = "state";
+pub const OUTBOX_FILENAME: &str = "outbox";
+pub const NOTIFICATION_RULES_FILENAME: &str = "notification_rules";
+
+pub fn infer_type_from_bytes(data: &[u8]) -> String {
+    infer::get(&data)
+        .map(|filetype| filetype.mime_type().to_string())
+        .unwrap_or_else(|| String::from("application/octet-stream"))
+}
+
+pub fn get_filename<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref()
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+pub fn file_id_to_string(id: &FileId) -> String {
+    match id {
+        FileId::External(uri) => uri.to_string(),
+        FileId::Hmc(hmc) => hmc.to_string(),
+        FileId::Id(id) => id.to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentStore {
+    session_file: PathBuf,
+    sessions_dir: PathBuf,
+    log_file: PathBuf,
+    content_dir: PathBuf,
+    cache_dir: PathBuf,
+}
+
+impl Default for ContentStore {
+    fn default() -> Self {
+        match directories_next::ProjectDirs::from("nodomain", "yusdacra", "crust") {
+            Some(app_dirs) => Self::in_dir(app_dirs.data_dir()),
+            // Fallback to current working directory if no HOME is present
+            None => Self::in_dir(""),
+        }
+    }
+}
+
+impl ContentStore {
+    /// Returns a store that keeps everything in the given directory.
+    pub fn in_dir(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        Self {
+            session_file: dir.join(SESSION_FILENAME),
+            sessions_dir: dir.join(SESSIONS_DIR_NAME),
+            log_file: dir.join(LOG_FILENAME),
+            content_dir: dir.join(CONTENT_DIR_NAME),
+            cache_dir: dir.join(CACHE_DIR_NAME),
+        }
+    }
+
+    /// Returns the store of an account, which has its own session file and cache.
+    ///
+    /// Downloaded content and the log file are shared between all accounts.
+    pub fn for_account(&self, homeserver: &Url, user_id: u64) -> Self {
+        let account = format!(
+            "{}_{}_{}",
+            homeserver.host_str().unwrap_or("unknown"),
+            homeserver.port_or_known_default().unwrap_or_default(),
+            user_id
+        )
+        .replace(|c| [' ', '/', '\\', '.', ':'].contains(&c), "_");
+
+        Self {
+            session_file: self.sessions_dir.join(&account),
+            sessions_dir: self.sessions_dir.clone(),
+            log_file: self.log_file.clone(),
+            content_dir: self.content_dir.clone(),
+            cache_dir: self.cache_dir.join(account),
+        }
+    }
+
+    pub fn content_path(&self, id: &FileId) -> PathBuf {
+        let normalized_id = match id {
+            FileId::External(uri) => uri.to_string(),
+            FileId::Hmc(hmc) => hmc.to_string(),
+            FileId::Id(id) => id.to_string(),
+        }
+        .replace(|c| [' ', '/', '\\', '.', ':'].contains(&c), "_");
+        self.content_dir().join(normalized_id)
+    }
+
+    pub fn content_mimetype(&self, id: &FileId) -> String {
+        infer::get_from_path(self.content_path(id))
+            .ok()
+            .flatten()
+            .map(|filetype| filetype.mime_type().to_string())
+            .unwrap_or_else(|| String::from("application/octet-stream"))
+    }
+
+    pub fn content_exists(&self, id: &FileId) -> bool {
+        self.content_path(id).exists()
+    }
+
+    pub fn create_req_dirs(&self) -> Result<(), ClientError> {
+        use std::fs::create_dir_all;
+
+        create_dir_all(self.content_dir())?;
+        create_dir_all(self.cache_dir())?;
+        create_dir_all(self.sessions_dir())?;
+        create_dir_all(self.session_file().parent().unwrap_or(&Path::new(".")))?;
+        create_dir_all(self.log_file().parent().unwrap_or(&Path::new(".")))?;
+
+        Ok(())
+    }
+
+    pub fn content_dir(&self) -> &Path {
+        self.content_dir.as_path()
+    }
+
+    pub fn session_file(&self) -> &Path {
+        self.session_file.as_path()
+    }
+
+    /// Returns the session files of all accounts we are logged into, sorted by path.
+    pub fn session_files(&self) -> Vec<PathBuf> {
+        let mut session_files = std::fs::read_dir(self.sessions_dir())
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        // Older versions only stored a single session
+        if self.session_file().exists() {
+            session_files.push(self.session_file().to_path_buf());
+        }
+        session_files.sort();
+        session_files
+    }
+
+    /// Directory that has the session files of all accounts.
+    pub fn sessions_dir(&self) -> &Path {
+        self.sessions_dir.as_path()
+    }
+
+    pub fn log_file(&self) -> &Path {
+        self.log_file.as_path()
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        self.cache_dir.as_path()
+    }
+
+    /// Path of the file that stores cached guilds, channels and members.
+    pub fn state_cache_file(&self) -> PathBuf {
+        self.cache_dir().join(STATE_CACHE_FILENAME)
+    }
+
+    /// Path of the file that stores the outbox.
+    pub fn outbox_file(&self) -> PathBuf {
+        self.cache_dir().join(OUTBOX_FILENAME)
+    }
+
+    /// Path of the file that stores the notification rules.
+    pub fn notification_rules_file(&self) -> PathBuf {
+        self.cache_dir().join(NOTIFICATION_RULES_FILENAME)
+    }
+
+    /// Path of the file that stores cached messages of a channel.
+    pub fn channel_cache_file(&self, guild_id: u64, channel_id: u64) -> PathBuf {
+        self.cache_dir()
+            .join(format!("channel_{}_{}", guild_id, channel_id))
+    }
+}
+
+pub const MAX_THUMB_SIZE: u64 = 1000 * 500; // 500kb
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ContentType {
+    Image,
+    Audio,
+    Video,
+    Other,
+}
+
+impl ContentType {
+    pub fn new(mimetype: &str) -> Self {
+        use ContentType::*;
+
+        if let Some(filetype) = mimetype.split('/').next() {
+            match filetype {
+                "image" => Image,
+                "audio" => Audio,
+                "video" => Video,
+                _ => Other,
+            }
+        } else {
+            Other
+        }
+    }
+}
+
+impl From<&str> for ContentType {
+    fn from(other: &str) -> Self {
+        ContentType::new(other)
+    }
+}
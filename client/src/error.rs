@@ -16,6 +16,8 @@ pub enum ClientError {
     HmcParse(Url, HmcParseError),
     /// Error occurred in the Harmony client library.
     Internal(InnerClientError),
+    /// Error occurred while (de)serializing the local cache.
+    Cache(serde_json::Error),
     /// The user is already logged in.
     AlreadyLoggedIn,
     /// Not all required login information was provided.
@@ -43,6 +45,12 @@ impl From<std::io::Error> for ClientError {
     }
 }
 
+impl From<serde_json::Error> for ClientError {
+    fn from(other: serde_json::Error) -> Self {
+        Self::Cache(other)
+    }
+}
+
 impl From<InnerClientError> for ClientError {
     fn from(other: InnerClientError) -> Self {
         Self::Internal(other)
@@ -78,6 +86,7 @@ impl Display for ClientError {
                 }
             }
             ClientError::IOError(err) => write!(fmt, "An IO error occurred: {}", err),
+            ClientError::Cache(err) => write!(fmt, "Local cache is corrupted: {}", err),
             ClientError::AlreadyLoggedIn => write!(fmt, "Already logged in with another user."),
             ClientError::MissingLoginInfo => {
                 write!(fmt, "Missing required login information, can't login.")
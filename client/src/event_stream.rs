@@ -0,0 +1,374 @@
+//! Supervised event streams of our own homeserver and of the foreign homeservers that host
+//! some of our guilds.
+//!
+//! A background task owns the [`EventsSocket`]. New sources are added to it as soon as they are
+//! requested. When the stream stays quiet for too long, the homeserver is asked for something
+//! cheap to check that the connection is still alive, and the stream is replaced in case it
+//! died on its own. When the stream breaks, the task reconnects with a jittered exponential
+//! backoff and subscribes to every source again. Events and changes of the
+//! [`ConnectionState`] reach the frontend through [`EventStreamUpdates`].
+
+use super::{output::OutputFuture, Client, InnerClient, Output, Url};
+
+use ahash::AHashSet;
+use harmony_rust_sdk::{
+    api::chat::{event::Event, GetGuildListRequest},
+    client::{
+        api::chat::{guild::get_guild_list, EventSource},
+        EventsSocket,
+    },
+};
+use rand::Rng;
+use std::{
+    fmt::{self, Display, Formatter},
+    future::Future,
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time::timeout,
+};
+
+/// How long the stream can be quiet before we check that the connection is alive.
+const QUIET_STREAM_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the homeserver can take to answer the liveness check, or to open the replacement
+/// of a quiet stream.
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(10);
+const BASE_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+/// How many reconnects in a row can fail before we consider ourselves offline.
+const OFFLINE_AFTER_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The stream wasn't opened yet, we keep trying until we are considered offline.
+    Connecting,
+    Connected,
+    /// The stream broke, and we will try opening it again in `retry_in`.
+    Reconnecting {
+        attempt: u32,
+        retry_in: Duration,
+    },
+    /// Reconnecting failed too many times, but we keep trying every `retry_in`.
+    Offline {
+        retry_in: Duration,
+    },
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        ConnectionState::Connecting
+    }
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connecting => write!(f, "Connecting..."),
+            ConnectionState::Connected => write!(f, "Connected"),
+            ConnectionState::Reconnecting { attempt, retry_in } => write!(
+                f,
+                "Connection lost, reconnecting in {}s (attempt {})",
+                retry_in.as_secs().max(1),
+                attempt
+            ),
+            ConnectionState::Offline { retry_in } => write!(
+                f,
+                "Can't reach the homeserver, showing cached messages (retrying in {}s)",
+                retry_in.as_secs().max(1)
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StreamUpdate {
+    Event(Event),
+    State(ConnectionState),
+}
+
+/// Handle of the supervisor task, which is stopped when this is dropped.
+#[derive(Debug)]
+pub struct EventStream {
+    sources: UnboundedSender<EventSource>,
+    /// Guilds the stream is subscribed to, so none is subscribed to twice.
+    guilds: AHashSet<u64>,
+    task: JoinHandle<()>,
+}
+
+impl EventStream {
+    /// Starts supervising the stream of `inner`, which is the client of `homeserver` if it's a
+    /// foreign homeserver.
+    pub(super) fn start(
+        inner: InnerClient,
+        sources: Vec<EventSource>,
+        homeserver: Option<Url>,
+    ) -> (Self, EventStreamUpdates) {
+        let guilds = sources
+            .iter()
+            .filter_map(|source| match source {
+                EventSource::Guild(guild_id) => Some(*guild_id),
+                _ => None,
+            })
+            .collect();
+        let (sources_tx, sources_rx) = mpsc::unbounded_channel();
+        let (updates_tx, updates_rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(supervise(inner, sources, sources_rx, updates_tx));
+
+        (
+            Self {
+                sources: sources_tx,
+                guilds,
+                task,
+            },
+            EventStreamUpdates {
+                homeserver,
+                updates: updates_rx,
+            },
+        )
+    }
+
+    /// Subscribes to a guild right away, or when the stream is opened again if it's broken.
+    pub(super) fn add_guild(&mut self, guild_id: u64) {
+        if self.guilds.insert(guild_id) {
+            // Only fails if the task stopped, and then nothing listens to the stream anyway
+            let _ = self.sources.send(EventSource::Guild(guild_id));
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Receives what happens on a stream, see [`Client::connect_events`] and
+/// [`Client::connect_foreign_events`].
+#[derive(Debug)]
+pub struct EventStreamUpdates {
+    homeserver: Option<Url>,
+    updates: UnboundedReceiver<StreamUpdate>,
+}
+
+impl EventStreamUpdates {
+    /// Returns the foreign homeserver of the stream, or `None` if it's the stream of our
+    /// homeserver.
+    pub fn homeserver(&self) -> Option<&Url> {
+        self.homeserver.as_ref()
+    }
+
+    /// Returns the future that waits for the next update.
+    ///
+    /// Resolves to [`Output::EventStream`], which hands the receiver back to wait on it again.
+    /// There is no update once the stream was replaced or the client was dropped.
+    pub fn next(mut self: Box<Self>) -> impl Future<Output = Output> + Send + 'static {
+        async move {
+            let update = self.updates.recv().await;
+            Output::EventStream {
+                updates: self,
+                update,
+            }
+        }
+    }
+}
+
+/// How long to wait before reconnect `attempt`, picked randomly from the upper half of the
+/// backoff so clients that lost their connection together don't come back together.
+fn reconnect_delay(attempt: u32) -> Duration {
+    let backoff = BASE_RECONNECT_DELAY
+        .checked_mul(1 << attempt.saturating_sub(1).min(16))
+        .map_or(MAX_RECONNECT_DELAY, |delay| delay.min(MAX_RECONNECT_DELAY));
+    let half = backoff / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+/// Runs until the [`EventStream`] or the [`EventStreamUpdates`] are dropped.
+async fn supervise(
+    inner: InnerClient,
+    mut sources: Vec<EventSource>,
+    mut new_sources: UnboundedReceiver<EventSource>,
+    updates: UnboundedSender<StreamUpdate>,
+) {
+    let mut attempt = 0;
+    // Failing to connect isn't losing the connection, until we were connected once
+    let mut was_connected = false;
+    loop {
+        if attempt > 0 {
+            let retry_in = reconnect_delay(attempt);
+            let state = if attempt > OFFLINE_AFTER_ATTEMPTS {
+                ConnectionState::Offline { retry_in }
+            } else if was_connected {
+                ConnectionState::Reconnecting { attempt, retry_in }
+            } else {
+                ConnectionState::Connecting
+            };
+            if updates.send(StreamUpdate::State(state)).is_err() {
+                return;
+            }
+
+            // Sources requested in the meantime are subscribed to when we are back
+            let sleep = tokio::time::sleep(retry_in);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    source = new_sources.recv() => match source {
+                        Some(source) => sources.push(source),
+                        None => return,
+                    },
+                }
+            }
+        }
+
+        let socket = match inner.subscribe_events(sources.clone()).await {
+            Ok(socket) => socket,
+            Err(err) => {
+                tracing::error!(
+                    "couldn't open the event stream of {}: {}",
+                    inner.homeserver_url().as_str(),
+                    err
+                );
+                attempt += 1;
+                continue;
+            }
+        };
+        if updates
+            .send(StreamUpdate::State(ConnectionState::Connected))
+            .is_err()
+        {
+            return;
+        }
+        was_connected = true;
+
+        match run(&inner, socket, &mut sources, &mut new_sources, &updates).await {
+            Some(reason) => {
+                tracing::warn!("lost the event stream: {}", reason);
+                // Start over with a short backoff, since we were connected until now
+                attempt = 1;
+            }
+            None => return,
+        }
+    }
+}
+
+/// Forwards events of `socket` until it breaks, returning why.
+///
+/// A quiet socket is only considered broken if the homeserver doesn't answer a request either.
+/// Otherwise it's replaced with a new one before it's dropped, in case it died without the
+/// connection breaking. Nothing was lost if it was only quiet, so the frontend isn't told.
+///
+/// Returns `None` if the frontend is gone, so there is no need to reconnect.
+async fn run(
+    inner: &InnerClient,
+    mut socket: EventsSocket,
+    sources: &mut Vec<EventSource>,
+    new_sources: &mut UnboundedReceiver<EventSource>,
+    updates: &UnboundedSender<StreamUpdate>,
+) -> Option<String> {
+    loop {
+        tokio::select! {
+            source = new_sources.recv() => {
+                let source = source?;
+                sources.push(source.clone());
+                if let Err(err) = socket.add_source(source).await {
+                    return Some(err.to_string());
+                }
+            }
+            event = timeout(QUIET_STREAM_TIMEOUT, socket.get_event()) => match event {
+                Ok(Some(Ok(event))) => updates.send(StreamUpdate::Event(event)).ok()?,
+                Ok(Some(Err(err))) => return Some(err.to_string()),
+                Ok(None) => return Some("the homeserver closed the stream".to_string()),
+                Err(_) => {
+                    match timeout(LIVENESS_TIMEOUT, get_guild_list(inner, GetGuildListRequest {}))
+                        .await
+                    {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(err)) => return Some(format!("liveness check failed: {}", err)),
+                        Err(_) => return Some("liveness check timed out".to_string()),
+                    }
+                    match timeout(LIVENESS_TIMEOUT, inner.subscribe_events(sources.clone())).await
+                    {
+                        Ok(Ok(new_socket)) => socket = new_socket,
+                        Ok(Err(err)) => {
+                            return Some(format!("replacing a quiet stream failed: {}", err))
+                        }
+                        Err(_) => return Some("replacing a quiet stream timed out".to_string()),
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl Client {
+    /// Opens the event stream of our homeserver for the guilds we are in, replacing the stream
+    /// we had.
+    ///
+    /// Returns the future that waits for the first update of the stream, see
+    /// [`EventStreamUpdates::next`]. Hand the updates to [`Client::stream_state_changed`] and
+    /// [`Client::process_event`]. The guild list is fetched once the stream is connected.
+    pub fn connect_events(&mut self) -> impl Future<Output = Output> + Send + 'static {
+        let (stream, updates) = EventStream::start(self.inner.clone(), self.subscribe_to(), None);
+        self.event_stream = Some(stream);
+        self.connection = ConnectionState::Connecting;
+        Box::new(updates).next()
+    }
+
+    /// Subscribes to the events of a guild, on the event stream of the homeserver that hosts
+    /// it.
+    ///
+    /// Guilds on a homeserver we didn't log into yet are subscribed to when its stream is
+    /// opened, see [`Client::connect_foreign_events`].
+    pub fn subscribe_guild(&mut self, guild_id: u64) {
+        let homeserver = self
+            .guilds
+            .get(&guild_id)
+            .map(|guild| guild.homeserver.as_ref())
+            .flatten();
+        let stream = match homeserver {
+            Some(homeserver) => self
+                .foreign_homeservers
+                .get_mut(homeserver)
+                .map(|foreign| foreign.event_stream.as_mut())
+                .flatten(),
+            None => self.event_stream.as_mut(),
+        };
+        if let Some(stream) = stream {
+            stream.add_guild(guild_id);
+        }
+    }
+
+    /// Records the state of an event stream, the one of our homeserver if `homeserver` is
+    /// `None`. Only the stream of our homeserver tells whether we are offline.
+    ///
+    /// Returns the futures that catch up with what we missed on the guilds of the stream if it
+    /// reconnected after it broke, or if it's our stream and it connected for the first time,
+    /// see [`Client::resync`].
+    pub fn stream_state_changed(
+        &mut self,
+        homeserver: Option<&Url>,
+        state: ConnectionState,
+    ) -> Vec<OutputFuture> {
+        let previous = match homeserver {
+            Some(homeserver) => match self.foreign_homeservers.get_mut(homeserver) {
+                Some(foreign) => std::mem::replace(&mut foreign.connection, state),
+                None => return Vec::new(),
+            },
+            None => {
+                self.offline = matches!(state, ConnectionState::Offline { .. });
+                std::mem::replace(&mut self.connection, state)
+            }
+        };
+
+        // Guilds of a foreign homeserver are fetched when we log into it, before its stream is
+        // opened for the first time
+        let first_foreign = homeserver.is_some() && previous == ConnectionState::Connecting;
+        if state == ConnectionState::Connected && previous != state && !first_foreign {
+            self.resync(homeserver)
+        } else {
+            Vec::new()
+        }
+    }
+}
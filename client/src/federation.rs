@@ -0,0 +1,215 @@
+//! Guilds hosted on other homeservers.
+//!
+//! Requests for such guilds can't go through our own homeserver. Instead we log into the
+//! foreign homeserver with a token from the federation handshake, and keep a client and an
+//! event stream for every foreign homeserver we have guilds on. Their event streams are
+//! supervised like the one of our homeserver, see [`event_stream`](super::event_stream).
+
+use super::{
+    error::ClientError,
+    event_stream::{ConnectionState, EventStream},
+    Client, InnerClient, InnerSession, Output,
+};
+
+use harmony_rust_sdk::{
+    api::{
+        auth::{FederateRequest, LoginFederatedRequest},
+        exports::hrpc::url::Url,
+    },
+    client::api::{
+        auth::{federate, login_federated},
+        chat::EventSource,
+    },
+};
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+};
+
+pub struct ForeignHomeserver {
+    inner: InnerClient,
+    /// State of the event stream of this homeserver.
+    pub connection: ConnectionState,
+    event_stream: Option<EventStream>,
+}
+
+impl ForeignHomeserver {
+    #[inline(always)]
+    pub fn inner(&self) -> &InnerClient {
+        &self.inner
+    }
+}
+
+impl Debug for ForeignHomeserver {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("ForeignHomeserver")
+            .field("homeserver", &self.inner.homeserver_url().to_string())
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
+
+impl Client {
+    /// Returns the client that requests about a guild should be made with.
+    ///
+    /// Falls back to our own homeserver if the guild is hosted there, or if we didn't log into
+    /// its homeserver yet. Guilds, channels and history aren't fetched in the latter case, see
+    /// [`Client::awaiting_federation`].
+    pub fn inner_for_guild(&self, guild_id: u64) -> &InnerClient {
+        self.guilds
+            .get(&guild_id)
+            .map(|guild| guild.homeserver.as_ref())
+            .flatten()
+            .map(|homeserver| self.foreign_homeservers.get(homeserver))
+            .flatten()
+            .map_or(&self.inner, ForeignHomeserver::inner)
+    }
+
+    /// Returns whether a guild is hosted on a homeserver we didn't log into yet.
+    ///
+    /// Our own homeserver can't answer requests about such a guild, so fetching it waits until
+    /// [`Client::federation_complete`].
+    pub fn awaiting_federation(&self, guild_id: u64) -> bool {
+        self.guilds
+            .get(&guild_id)
+            .map(|guild| guild.homeserver.as_ref())
+            .flatten()
+            .map_or(false, |homeserver| {
+                !self.foreign_homeservers.contains_key(homeserver)
+            })
+    }
+
+    /// Returns the homeserver of a guild, or `None` if it's hosted on our homeserver.
+    pub(super) fn parse_guild_homeserver(&self, homeserver: &str) -> Option<Url> {
+        if homeserver.is_empty() {
+            return None;
+        }
+
+        match homeserver.parse::<Url>() {
+            Ok(url) if url.host_str() == self.inner.homeserver_url().host_str() => None,
+            Ok(url) => Some(url),
+            Err(err) => {
+                tracing::error!("invalid guild homeserver {}: {}", homeserver, err);
+                None
+            }
+        }
+    }
+
+    /// Returns the future that logs into a foreign homeserver through our homeserver, or
+    /// `None` if we are already logged in or logging in.
+    pub fn federate(
+        &mut self,
+        homeserver: Url,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        if self.foreign_homeservers.contains_key(&homeserver)
+            || !self.federating.insert(homeserver.clone())
+        {
+            return None;
+        }
+
+        let local = self.inner().clone();
+        Some(async move {
+            let result = async {
+                let token = federate(
+                    &local,
+                    FederateRequest {
+                        target: homeserver.to_string(),
+                    },
+                )
+                .await?
+                .token;
+                let domain = local
+                    .homeserver_url()
+                    .host_str()
+                    .unwrap_or_default()
+                    .to_string();
+
+                let foreign = InnerClient::new(homeserver.clone(), None).await?;
+                let session = login_federated(
+                    &foreign,
+                    LoginFederatedRequest {
+                        auth_token: token,
+                        domain,
+                    },
+                )
+                .await?;
+                let session = InnerSession {
+                    user_id: session.user_id,
+                    session_token: session.session_token,
+                };
+
+                Ok::<_, ClientError>(InnerClient::new(homeserver.clone(), Some(session)).await?)
+            }
+            .await;
+
+            match result {
+                Ok(inner) => Output::Federated {
+                    homeserver,
+                    foreign: Box::new(ForeignHomeserver {
+                        inner,
+                        connection: ConnectionState::default(),
+                        event_stream: None,
+                    }),
+                },
+                Err(err) => Output::FederationFailed {
+                    homeserver,
+                    err: Box::new(err),
+                },
+            }
+        })
+    }
+
+    /// Stores the client of a foreign homeserver.
+    ///
+    /// Returns the guilds hosted on it, which need to be fetched again since requests about
+    /// them went to our own homeserver until now.
+    pub fn federation_complete(&mut self, homeserver: Url, foreign: ForeignHomeserver) -> Vec<u64> {
+        self.federating.remove(&homeserver);
+        self.foreign_homeservers.insert(homeserver.clone(), foreign);
+
+        self.guilds
+            .iter_mut()
+            .filter(|(_, guild)| guild.homeserver.as_ref() == Some(&homeserver))
+            .map(|(guild_id, guild)| {
+                guild.fetched = false;
+                *guild_id
+            })
+            .collect()
+    }
+
+    /// Allows trying to log into a foreign homeserver again later.
+    pub fn federation_failed(&mut self, homeserver: &Url) {
+        self.federating.remove(homeserver);
+    }
+
+    /// Opens the event stream of a foreign homeserver for all guilds we have on it, replacing
+    /// the stream it had, or returns `None` if we didn't log into it.
+    ///
+    /// Returns the future that waits for the first update of the stream, which is handled like
+    /// the updates of our own stream, see [`Client::connect_events`].
+    pub fn connect_foreign_events(
+        &mut self,
+        homeserver: Url,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let sources = self
+            .guilds
+            .iter()
+            .filter(|(_, guild)| guild.homeserver.as_ref() == Some(&homeserver))
+            .map(|(guild_id, _)| EventSource::Guild(*guild_id))
+            .collect::<Vec<_>>();
+        let foreign = self.foreign_homeservers.get_mut(&homeserver)?;
+
+        let (stream, updates) =
+            EventStream::start(foreign.inner.clone(), sources, Some(homeserver));
+        foreign.event_stream = Some(stream);
+        foreign.connection = ConnectionState::Connecting;
+        Some(Box::new(updates).next())
+    }
+
+    /// Returns the foreign homeservers we logged into, with the state of their event streams.
+    pub fn foreign_connections(&self) -> impl Iterator<Item = (&Url, ConnectionState)> + '_ {
+        self.foreign_homeservers
+            .iter()
+            .map(|(homeserver, foreign)| (homeserver, foreign.connection))
+    }
+}
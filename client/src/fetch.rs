@@ -0,0 +1,552 @@
+//! Fetching guilds, channels, messages and profiles from the homeserver.
+//!
+//! Guilds and channels are marked as fetched (or loading) as soon as their future is created,
+//! so the same data isn't fetched twice while a request is in flight.
+
+use super::{
+    error::{ClientError, ClientResult},
+    message::{Message, MessageId},
+    Client, InnerClient, Output,
+};
+
+use ahash::AHashSet;
+use chrono::NaiveDateTime;
+use futures::{stream, StreamExt};
+use harmony_rust_sdk::{
+    api::chat::{
+        event::{
+            ChannelCreated, ChannelDeleted, Event, GuildAddedToList, GuildRemovedFromList,
+            GuildUpdated, MemberJoined, MemberLeft, ProfileUpdated, RoleCreated, RoleDeleted,
+            UserRolesUpdated,
+        },
+        GetGuildListRequest,
+    },
+    api::harmonytypes::Message as HarmonyMessage,
+    client::api::chat::{
+        channel::{
+            get_channel_messages, get_guild_channels, GetChannelMessages,
+            GetChannelMessagesSelfBuilder,
+        },
+        guild::{get_guild, get_guild_list, get_guild_members},
+        message::{get_pinned_messages, GetPinnedMessagesRequest},
+        permissions::{get_guild_roles, get_user_roles, GetUserRolesRequest},
+        profile::{get_user, get_user_bulk},
+        GuildId, UserId,
+    },
+};
+use std::future::Future;
+
+/// How many role requests are in flight at once while fetching a guild.
+const USER_ROLES_CONCURRENCY: usize = 8;
+
+/// How many pages of messages are fetched at most each time a channel is backfilled.
+///
+/// Whatever isn't fetched by then is kept as a gap, and fetched the next time.
+const BACKFILL_PAGE_LIMIT: usize = 10;
+
+/// ID and timestamp of the message we have right before messages that may be missing.
+type Anchor = (u64, NaiveDateTime);
+
+fn events_output(result: ClientResult<Vec<Event>>) -> Output {
+    result.map_or_else(|err| Output::Error(Box::new(err)), Output::Events)
+}
+
+/// Fetches the messages before `before`, or the latest messages if it's 0, until they reach
+/// `anchor`, fetching at most `pages` pages.
+///
+/// Messages older than the anchor also reach it, so an anchor that was deleted meanwhile
+/// doesn't make us page through the whole channel. Returns the messages newer than the anchor
+/// from the newest to the oldest, whether nothing is missing between them and the anchor, and
+/// whether they reach back to the first message of the channel.
+async fn fetch_down_to(
+    inner: &InnerClient,
+    guild_id: u64,
+    channel_id: u64,
+    mut before: u64,
+    anchor: Anchor,
+    pages: &mut usize,
+) -> ClientResult<(Vec<HarmonyMessage>, bool, bool)> {
+    let mut messages = Vec::new();
+    while *pages > 0 {
+        *pages -= 1;
+        let response = get_channel_messages(
+            inner,
+            GetChannelMessages::new(guild_id, channel_id).before_message(before),
+        )
+        .await?;
+        let mut page = response.messages;
+
+        // Messages are returned from the newest to the oldest
+        if let Some(pos) = page.iter().position(|message| reaches(message, anchor)) {
+            page.truncate(pos);
+            messages.append(&mut page);
+            return Ok((messages, true, false));
+        }
+
+        before = match page.last() {
+            Some(message) => message.message_id,
+            None => return Ok((messages, true, response.reached_top)),
+        };
+        messages.append(&mut page);
+        if response.reached_top {
+            return Ok((messages, true, true));
+        }
+    }
+
+    Ok((messages, false, false))
+}
+
+fn reaches(message: &HarmonyMessage, (anchor_id, anchor_timestamp): Anchor) -> bool {
+    message.message_id == anchor_id
+        || message.created_at.as_ref().map_or(false, |t| {
+            NaiveDateTime::from_timestamp(t.seconds, t.nanos as u32) < anchor_timestamp
+        })
+}
+
+impl Client {
+    /// Returns the future that fetches the guild list and our own profile, which should be done
+    /// every time we connect.
+    ///
+    /// Guilds we left while we were away are removed.
+    pub fn fetch_guild_list(&self) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let inner = self.inner().clone();
+        let self_id = self.user_id?;
+        let cached_guilds = self.guilds.keys().copied().collect::<Vec<_>>();
+
+        Some(async move {
+            let result = async {
+                let self_profile = get_user(&inner, UserId::new(self_id)).await?;
+                let guilds = get_guild_list(&inner, GetGuildListRequest {}).await?.guilds;
+                // Guilds we left while we were away may still be in the cache
+                let mut events = cached_guilds
+                    .into_iter()
+                    .filter(|guild_id| !guilds.iter().any(|guild| guild.guild_id == *guild_id))
+                    .map(|guild_id| {
+                        Event::GuildRemovedFromList(GuildRemovedFromList {
+                            guild_id,
+                            ..Default::default()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                events.extend(guilds.into_iter().map(|guild| {
+                    Event::GuildAddedToList(GuildAddedToList {
+                        guild_id: guild.guild_id,
+                        homeserver: guild.host,
+                    })
+                }));
+                events.push(Event::ProfileUpdated(ProfileUpdated {
+                    update_avatar: true,
+                    update_is_bot: true,
+                    update_status: true,
+                    update_username: true,
+                    is_bot: self_profile.is_bot,
+                    new_avatar: self_profile.user_avatar,
+                    new_status: self_profile.user_status,
+                    new_username: self_profile.user_name,
+                    user_id: self_id,
+                }));
+                Ok::<_, ClientError>(events)
+            }
+            .await;
+
+            events_output(result)
+        })
+    }
+
+    /// Returns the future that fetches the name and picture of a guild, or `None` if we are
+    /// still logging into its homeserver.
+    pub fn fetch_guild_data(
+        &self,
+        guild_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        if self.awaiting_federation(guild_id) {
+            return None;
+        }
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        Some(async move {
+            let result = get_guild(&inner, GuildId::new(guild_id))
+                .await
+                .map(|guild_data| {
+                    vec![Event::EditedGuild(GuildUpdated {
+                        guild_id,
+                        metadata: guild_data.metadata,
+                        name: guild_data.guild_name,
+                        picture: guild_data.guild_picture,
+                        update_name: true,
+                        update_picture: true,
+                        update_metadata: true,
+                    })]
+                });
+
+            events_output(result.map_err(Into::into))
+        })
+    }
+
+    /// Returns the future that fetches the channels, members and roles of a guild, or `None` if
+    /// they were already fetched in this session, we are offline or we are still logging into
+    /// the homeserver of the guild.
+    ///
+    /// Channels, members and roles we know of that are gone from the homeserver are removed. The
+    /// roles of each member are fetched the first time, after that only the roles of members
+    /// that joined meanwhile and our own roles are, since the event stream keeps the others up
+    /// to date. This saves a request per member when we reconnect, at the cost of missing role
+    /// changes of other members made while the event stream was broken. The future resolves to
+    /// [`Output::FetchFailed`] if fetching fails.
+    pub fn fetch_guild(
+        &mut self,
+        guild_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let offline = self.offline;
+        let awaiting_federation = self.awaiting_federation(guild_id);
+        let our_id = self.user_id;
+        let guild = self.get_guild(guild_id)?;
+        if guild.fetched || offline || awaiting_federation {
+            return None;
+        }
+        guild.fetched = true;
+        let known_channels = guild.channels.keys().copied().collect::<Vec<_>>();
+        let known_members = guild.members.iter().copied().collect::<Vec<_>>();
+        let known_roles = guild.roles.keys().copied().collect::<Vec<_>>();
+        let known_member_roles = guild.member_roles.keys().copied().collect::<AHashSet<_>>();
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        Some(async move {
+            let result = async {
+                let guildid = GuildId::new(guild_id);
+                let channels_list = get_guild_channels(&inner, guildid).await?.channels;
+                let mut events = known_channels
+                    .into_iter()
+                    .filter(|channel_id| {
+                        !channels_list
+                            .iter()
+                            .any(|channel| channel.channel_id == *channel_id)
+                    })
+                    .map(|channel_id| {
+                        Event::DeletedChannel(ChannelDeleted {
+                            guild_id,
+                            channel_id,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                events.reserve(channels_list.len());
+                for channel in channels_list {
+                    events.push(Event::CreatedChannel(ChannelCreated {
+                        guild_id,
+                        channel_id: channel.channel_id,
+                        is_category: channel.is_category,
+                        name: channel.channel_name,
+                        metadata: channel.metadata,
+                        ..Default::default()
+                    }));
+                }
+
+                let members = get_guild_members(&inner, guildid).await?.members;
+                events.extend(
+                    known_members
+                        .into_iter()
+                        .filter(|member_id| !members.contains(member_id))
+                        .map(|member_id| {
+                            Event::LeftMember(MemberLeft {
+                                guild_id,
+                                member_id,
+                                ..Default::default()
+                            })
+                        }),
+                );
+                events.reserve(members.len());
+                for member_id in &members {
+                    events.push(Event::JoinedMember(MemberJoined {
+                        guild_id,
+                        member_id: *member_id,
+                    }));
+                }
+
+                // Roles are returned from the highest to the lowest
+                let roles = get_guild_roles(&inner, guildid).await?.roles;
+                events.extend(
+                    known_roles
+                        .into_iter()
+                        .filter(|role_id| !roles.iter().any(|role| role.role_id == *role_id))
+                        .map(|role_id| Event::RoleDeleted(RoleDeleted { guild_id, role_id })),
+                );
+                events.reserve(roles.len() + members.len());
+                for role in roles {
+                    events.push(Event::RoleCreated(RoleCreated {
+                        guild_id,
+                        role_id: role.role_id,
+                        name: role.name,
+                        color: role.color,
+                        hoist: role.hoist,
+                        pingable: role.pingable,
+                    }));
+                }
+
+                // Members we couldn't get the roles of keep the roles we know of
+                let inner = &inner;
+                let roles_to_fetch = members.into_iter().filter(|user_id| {
+                    Some(*user_id) == our_id || !known_member_roles.contains(user_id)
+                });
+                let mut user_roles = stream::iter(roles_to_fetch)
+                    .map(|user_id| async move {
+                        let request = GetUserRolesRequest { guild_id, user_id };
+                        (user_id, get_user_roles(inner, request).await)
+                    })
+                    .buffer_unordered(USER_ROLES_CONCURRENCY);
+                while let Some((user_id, result)) = user_roles.next().await {
+                    match result {
+                        Ok(response) => events.push(Event::UserRolesUpdated(UserRolesUpdated {
+                            guild_id,
+                            user_id,
+                            role_ids: response.roles,
+                        })),
+                        Err(err) => tracing::warn!(
+                            "couldn't fetch the roles of user {} in guild {}: {}",
+                            user_id,
+                            guild_id,
+                            err
+                        ),
+                    }
+                }
+
+                Ok::<_, ClientError>(events)
+            }
+            .await;
+
+            result.map_or_else(
+                |err| Output::FetchFailed {
+                    guild_id,
+                    channel_id: None,
+                    err: Box::new(err),
+                },
+                Output::Events,
+            )
+        })
+    }
+
+    /// Returns the future that fetches the latest messages of a channel, or `None` if they were
+    /// already fetched in this session, we are offline or we are still logging into the
+    /// homeserver of the guild.
+    ///
+    /// Channels restored from the local cache, or loaded before we lost the connection, may be
+    /// missing messages. These are fetched back to the newest message we have, then the gaps
+    /// left by earlier backfills are fetched, all within [`BACKFILL_PAGE_LIMIT`] pages. What
+    /// couldn't be fetched is kept as a gap, and everything is merged in order with
+    /// [`Client::process_backfill`]. The future resolves to [`Output::FetchFailed`] if fetching
+    /// the latest messages fails.
+    ///
+    /// [`Client::process_backfill`]: super::Client::process_backfill
+    pub fn fetch_channel(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let offline = self.offline;
+        let awaiting_federation = self.awaiting_federation(guild_id);
+        let channel = self.get_channel(guild_id, channel_id)?;
+        if channel.fetched || offline || awaiting_federation {
+            return None;
+        }
+        channel.fetched = true;
+        let latest = channel
+            .messages
+            .iter()
+            .rev()
+            .find_map(|message| Some((message.id.id()?, message.timestamp)));
+        // A gap right at the oldest message we have is history that wasn't loaded yet
+        let old_gaps = channel
+            .gaps
+            .iter()
+            .filter_map(|gap| {
+                let anchor = channel.messages.before(MessageId::Ack(*gap))?;
+                Some((*gap, (anchor.id.id()?, anchor.timestamp)))
+            })
+            .collect::<Vec<_>>();
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        Some(async move {
+            let result = async {
+                let mut pages = BACKFILL_PAGE_LIMIT;
+                let (mut messages, closed, mut reached_top) = match latest {
+                    Some(anchor) => {
+                        fetch_down_to(&inner, guild_id, channel_id, 0, anchor, &mut pages).await?
+                    }
+                    // Without a message to connect to, the latest messages are enough
+                    None => {
+                        let response = get_channel_messages(
+                            &inner,
+                            GetChannelMessages::new(guild_id, channel_id),
+                        )
+                        .await?;
+                        (response.messages, true, response.reached_top)
+                    }
+                };
+                let mut gaps = Vec::with_capacity(old_gaps.len() + 1);
+                if !closed {
+                    gaps.extend(messages.last().map(|message| message.message_id));
+                }
+
+                for (gap, anchor) in old_gaps {
+                    match fetch_down_to(&inner, guild_id, channel_id, gap, anchor, &mut pages).await
+                    {
+                        Ok((mut filled, closed, top)) => {
+                            if !closed {
+                                gaps.push(filled.last().map_or(gap, |message| message.message_id));
+                            }
+                            reached_top |= top;
+                            messages.append(&mut filled);
+                        }
+                        Err(err) => {
+                            tracing::warn!("couldn't fetch missed messages: {}", err);
+                            gaps.push(gap);
+                            pages = 0;
+                        }
+                    }
+                }
+                Ok::<_, ClientError>((messages, gaps, reached_top))
+            }
+            .await;
+
+            result.map_or_else(
+                |err| Output::FetchFailed {
+                    guild_id,
+                    channel_id: Some(channel_id),
+                    err: Box::new(err),
+                },
+                |(messages, gaps, reached_top)| Output::Backfill {
+                    guild_id,
+                    channel_id,
+                    messages,
+                    gaps,
+                    reached_top,
+                },
+            )
+        })
+    }
+
+    /// Records that fetching a guild, or the latest messages of a channel, failed.
+    ///
+    /// It's fetched again when it's opened, or when we reconnect, see [`Client::resync`].
+    ///
+    /// [`Client::resync`]: super::Client::resync
+    pub fn fetch_failed(&mut self, guild_id: u64, channel_id: Option<u64>) {
+        let fetched = match channel_id {
+            Some(channel_id) => self
+                .get_channel(guild_id, channel_id)
+                .map(|channel| &mut channel.fetched),
+            None => self.get_guild(guild_id).map(|guild| &mut guild.fetched),
+        };
+        if let Some(fetched) = fetched {
+            *fetched = false;
+            self.failed_fetches
+                .insert((guild_id, channel_id.unwrap_or(0)));
+        }
+    }
+
+    /// Returns the future that loads older messages of a channel.
+    ///
+    /// Returns `None` if they are already being loaded, if we have the first message of the
+    /// channel, if we are offline, or if we are still logging into the homeserver of the guild.
+    pub fn fetch_history(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let offline = self.offline;
+        let awaiting_federation = self.awaiting_federation(guild_id);
+        let channel = self.get_channel(guild_id, channel_id)?;
+        if channel.loading_messages_history || channel.reached_top || offline || awaiting_federation
+        {
+            return None;
+        }
+        channel.loading_messages_history = true;
+
+        let oldest_msg_id = channel.messages.first().map(|m| m.id.id()).flatten();
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        Some(async move {
+            get_channel_messages(
+                &inner,
+                GetChannelMessages::new(guild_id, channel_id)
+                    .before_message(oldest_msg_id.unwrap_or_default()),
+            )
+            .await
+            .map_or_else(
+                |err| Output::Error(Box::new(err.into())),
+                |response| Output::History {
+                    messages: response.messages,
+                    reached_top: response.reached_top,
+                    guild_id,
+                    channel_id,
+                },
+            )
+        })
+    }
+
+    /// Returns the future that fetches the pinned messages of a channel, see
+    /// [`Client::set_pinned_messages`].
+    ///
+    /// [`Client::set_pinned_messages`]: super::Client::set_pinned_messages
+    pub fn fetch_pinned_messages(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+    ) -> impl Future<Output = Output> + Send + 'static {
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        async move {
+            get_pinned_messages(
+                &inner,
+                GetPinnedMessagesRequest {
+                    guild_id,
+                    channel_id,
+                },
+            )
+            .await
+            .map_or_else(
+                |err| Output::Error(Box::new(err.into())),
+                |response| Output::PinnedMessages {
+                    guild_id,
+                    channel_id,
+                    messages: response.messages.into_iter().map(Message::from).collect(),
+                },
+            )
+        }
+    }
+
+    /// Returns the future that fetches the profiles of members of a guild.
+    pub fn fetch_profiles(
+        &self,
+        guild_id: u64,
+        user_ids: Vec<u64>,
+    ) -> impl Future<Output = Output> + Send + 'static {
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        async move {
+            let result = get_user_bulk(&inner, user_ids.clone())
+                .await
+                .map(|profiles| {
+                    profiles
+                        .users
+                        .into_iter()
+                        .zip(user_ids.into_iter())
+                        .map(|(profile, user_id)| {
+                            Event::ProfileUpdated(ProfileUpdated {
+                                user_id,
+                                new_avatar: profile.user_avatar,
+                                new_status: profile.user_status,
+                                new_username: profile.user_name,
+                                is_bot: profile.is_bot,
+                                update_is_bot: true,
+                                update_status: true,
+                                update_avatar: true,
+                                update_username: true,
+                            })
+                        })
+                        .collect()
+                });
+
+            events_output(result.map_err(Into::into))
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use ahash::{AHashMap, AHashSet};
+use harmony_rust_sdk::{api::exports::hrpc::url::Url, client::api::rest::FileId};
+use indexmap::IndexMap;
+
+use super::{
+    channel::Channels,
+    color::Color,
+    role::{Role, Roles},
+};
+
+pub type Guilds = AHashMap<u64, Guild>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Guild {
+    pub name: String,
+    pub picture: Option<FileId>,
+    /// Homeserver the guild is hosted on, `None` if it's our own homeserver.
+    pub homeserver: Option<Url>,
+    pub channels: Channels,
+    pub members: AHashSet<u64>,
+    pub roles: Roles,
+    /// Role IDs of members, keyed by user ID.
+    pub member_roles: AHashMap<u64, Vec<u64>>,
+    /// Guild wide permissions of the current user, keyed by permission query.
+    pub permissions: AHashMap<String, bool>,
+    /// Whether channels and members were fetched from the homeserver in this session.
+    ///
+    /// Guilds restored from the local cache start out as not fetched.
+    pub fetched: bool,
+}
+
+impl Guild {
+    /// Returns whether this guild is a conversation between us and one other user.
+    ///
+    /// Harmony has no direct messages of its own, guilds with two members are used instead.
+    pub fn is_direct_message(&self) -> bool {
+        self.members.len() == 2
+    }
+
+    /// Returns whether we have a guild wide permission, or `None` if it wasn't queried yet.
+    pub fn has_perm(&self, query: &str) -> Option<bool> {
+        self.permissions.get(query).copied()
+    }
+
+    /// Returns the roles of a member, from the highest to the lowest.
+    pub fn member_roles(&self, user_id: u64) -> impl Iterator<Item = (&u64, &Role)> + '_ {
+        let role_ids = self.member_roles.get(&user_id);
+        self.roles.iter().filter(move |(role_id, _)| {
+            role_ids.map_or(false, |role_ids| role_ids.contains(role_id))
+        })
+    }
+
+    /// Returns the color of a member's name, which is the color of their highest colored role.
+    pub fn member_color(&self, user_id: u64) -> Option<Color> {
+        self.member_roles(user_id).find_map(|(_, role)| role.color)
+    }
+
+    /// Returns the highest role of a member that is shown separately in the member list.
+    pub fn member_hoisted_role(&self, user_id: u64) -> Option<u64> {
+        self.member_roles(user_id)
+            .find(|(_, role)| role.hoist)
+            .map(|(role_id, _)| *role_id)
+    }
+
+    /// Returns the unread messages and mentions in all channels of this guild.
+    pub fn unread_counts(&self) -> (usize, usize) {
+        self.channels
+            .values()
+            .fold((0, 0), |(unread, mentions), channel| {
+                (unread + channel.unread, mentions + channel.mentions)
+            })
+    }
+
+    pub fn update_channel_order(&mut self, previous_id: u64, next_id: u64, channel_id: u64) {
+        update_order(&mut self.channels, previous_id, next_id, channel_id);
+    }
+
+    pub fn update_role_order(&mut self, previous_id: u64, next_id: u64, role_id: u64) {
+        update_order(&mut self.roles, previous_id, next_id, role_id);
+    }
+}
+
+fn update_order<V>(map: &mut IndexMap<u64, V>, previous_id: u64, next_id: u64, id: u64) {
+    if let Some(item_pos) = map.keys().position(|k| k == &id) {
+        let prev_pos = map.keys().position(|k| *k == previous_id);
+        let next_pos = map.keys().position(|k| *k == next_id);
+
+        if let Some(pos) = prev_pos {
+            if pos != item_pos {
+                map.swap_indices(pos + 1, item_pos);
+            }
+        } else if let Some(pos) = next_pos {
+            if pos != 0 {
+                map.swap_indices(pos - 1, item_pos);
+            } else {
+                let (k, v) = map.pop().unwrap();
+                map.reverse();
+                map.insert(k, v);
+                map.reverse();
+            }
+        }
+    }
+}
@@ -0,0 +1,1133 @@
+//! State handling of Crust, independent of any UI.
+//!
+//! [`Client`] keeps the guilds, channels, members and messages of an account up to date from
+//! the events of its homeserver. Operations that talk to a homeserver return futures resolving
+//! to an [`Output`], so bots, tools and the GUI can drive them with any executor.
+
+#![allow(clippy::field_reassign_with_default)]
+
+pub mod cache;
+pub mod channel;
+pub mod color;
+pub mod content;
+pub mod error;
+pub mod event_stream;
+pub mod federation;
+pub mod fetch;
+pub mod guild;
+pub mod member;
+pub mod message;
+pub mod notification;
+pub mod notification_rules;
+pub mod outbox;
+pub mod output;
+pub mod permission;
+pub mod quick_switch;
+pub mod resync;
+pub mod role;
+pub mod search;
+
+use channel::Channel;
+use guild::Guild;
+pub use harmony_rust_sdk::{
+    api::exports::hrpc::url::Url,
+    client::{api::auth::Session as InnerSession, AuthStatus, Client as InnerClient, EventsSocket},
+};
+use harmony_rust_sdk::{
+    api::{
+        chat::event::*,
+        harmonytypes::{Message as HarmonyMessage, UserStatus},
+    },
+    client::api::{
+        chat::{
+            message::{
+                add_reaction, delete_message, pin_message, remove_reaction, unpin_message,
+                AddReaction, DeleteMessageRequest, PinMessageRequest, RemoveReaction,
+                UnpinMessageRequest,
+            },
+            EventSource,
+        },
+        rest::FileId,
+    },
+};
+
+use ahash::{AHashMap, AHashSet};
+use content::ContentStore;
+use error::{ClientError, ClientResult};
+use event_stream::{ConnectionState, EventStream};
+use federation::ForeignHomeserver;
+use member::{Member, Members};
+use message::{harmony_messages_to_ui_messages, Attachment, Embed, MessageId, Override};
+use notification::{Notification, Notifications};
+use notification_rules::NotificationRules;
+use outbox::{Outbox, OutboxAction, OutboxEntry};
+pub use output::Output;
+use role::Role;
+use search::SearchIndex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
+
+use self::{
+    guild::Guilds,
+    message::{EmbedHeading, Message},
+};
+
+/// A sesssion struct with our requirements (unlike the `InnerSession` type)
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Session {
+    pub session_token: String,
+    pub user_id: String,
+    pub homeserver: String,
+}
+
+impl Debug for Session {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("user_id", &self.user_id)
+            .field("homeserver", &self.homeserver)
+            .finish()
+    }
+}
+
+impl Session {
+    /// Reads a session stored in a session file.
+    pub async fn from_file(session_file: &Path) -> ClientResult<Self> {
+        let session_raw = tokio::fs::read(session_file).await?;
+        toml::de::from_slice(&session_raw).map_err(|_| ClientError::MissingLoginInfo)
+    }
+
+    /// Finds the session file of an account.
+    ///
+    /// `account` is the user ID of the account, which can be left out if we are logged into
+    /// only one account.
+    pub async fn find_file(
+        content_store: &ContentStore,
+        account: Option<u64>,
+    ) -> ClientResult<PathBuf> {
+        let mut sessions = Vec::new();
+        for session_file in content_store.session_files() {
+            match Session::from_file(&session_file).await {
+                Ok(session) => sessions.push((session.user_id, session_file)),
+                Err(err) => tracing::warn!(
+                    "couldn't read session file {}: {}",
+                    session_file.to_string_lossy(),
+                    err
+                ),
+            }
+        }
+
+        match account {
+            Some(user_id) => sessions
+                .into_iter()
+                .find(|(id, _)| id.parse::<u64>().ok() == Some(user_id))
+                .map(|(_, session_file)| session_file)
+                .ok_or_else(|| {
+                    ClientError::Custom(format!("Not logged into an account with ID {}", user_id))
+                }),
+            None if sessions.len() == 1 => Ok(sessions.remove(0).1),
+            None if sessions.is_empty() => Err(ClientError::Custom(
+                "Not logged into any account, log in with Crust first".to_string(),
+            )),
+            None => {
+                let ids = sessions
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Err(ClientError::Custom(format!(
+                    "Logged into more than one account, pick one with --account: {}",
+                    ids
+                )))
+            }
+        }
+    }
+}
+
+impl Into<InnerSession> for Session {
+    fn into(self) -> InnerSession {
+        InnerSession {
+            user_id: self.user_id.parse().unwrap(),
+            session_token: self.session_token,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PostProcessEvent {
+    /// Fetch the profile of a member of a guild, through the homeserver of the guild.
+    FetchProfile {
+        guild_id: u64,
+        user_id: u64,
+    },
+    FetchGuildData(u64),
+    /// Fetch a thumbnail through the homeserver of the guild it was seen in, or through our own
+    /// homeserver if `guild_id` is `None`.
+    FetchThumbnail {
+        guild_id: Option<u64>,
+        id: FileId,
+    },
+    /// New messages were added to the end of a channel, frontends that show the latest messages
+    /// of it should scroll to them.
+    GoToFirstMsgOnChannel(u64),
+    /// Log into a homeserver that hosts some of our guilds.
+    Federate(Url),
+    /// A message should notify the user, unless the UI shows it already.
+    Notify(Box<Notification>),
+    /// Query our permissions in a guild and the given channels of it again.
+    FetchPermissions {
+        guild_id: u64,
+        channel_ids: Vec<u64>,
+    },
+    Nothing,
+}
+
+pub struct Client {
+    inner: InnerClient,
+    pub guilds: Guilds,
+    pub members: Members,
+    pub user_id: Option<u64>,
+    /// Whether the homeserver is unreachable and we are only showing cached data.
+    pub offline: bool,
+    /// State of the event stream of our homeserver, see [`Client::connect_events`].
+    pub connection: ConnectionState,
+    event_stream: Option<EventStream>,
+    /// Sends and edits that weren't acknowledged by the homeserver yet, keyed by transaction ID.
+    pub outbox: Outbox,
+    /// Clients of other homeservers that host some of our guilds.
+    pub foreign_homeservers: AHashMap<Url, ForeignHomeserver>,
+    /// Homeservers we are logging into.
+    federating: AHashSet<Url>,
+    pub notifications: Notifications,
+    /// Which messages notify the user, change with [`Client::update_notification_rules`].
+    pub notification_rules: NotificationRules,
+    /// Every message we have seen, for searching.
+    search_index: SearchIndex,
+    content_store: Arc<ContentStore>,
+    dirty_channels: AHashSet<(u64, u64)>,
+    /// Guilds (with a channel ID of 0) and channels that couldn't be fetched, which are fetched
+    /// again when we reconnect.
+    failed_fetches: AHashSet<(u64, u64)>,
+    state_dirty: bool,
+    outbox_dirty: bool,
+    rules_dirty: bool,
+}
+
+impl Debug for Client {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Client")
+            .field(
+                "user_id",
+                &format!(
+                    "{:?}",
+                    self.auth_status().session().map_or(0, |s| s.user_id)
+                ),
+            )
+            .field("session_file", &self.content_store.session_file())
+            .finish()
+    }
+}
+
+impl Client {
+    pub async fn new(
+        homeserver_url: Url,
+        session: Option<InnerSession>,
+        content_store: Arc<ContentStore>,
+    ) -> ClientResult<Self> {
+        Ok(Self {
+            guilds: Guilds::new(),
+            members: Members::new(),
+            user_id: session.as_ref().map(|s| s.user_id),
+            offline: false,
+            connection: ConnectionState::default(),
+            event_stream: None,
+            outbox: Outbox::new(),
+            foreign_homeservers: AHashMap::new(),
+            federating: AHashSet::new(),
+            notifications: Notifications::default(),
+            notification_rules: NotificationRules::default(),
+            search_index: SearchIndex::default(),
+            content_store,
+            dirty_channels: AHashSet::new(),
+            failed_fetches: AHashSet::new(),
+            state_dirty: false,
+            outbox_dirty: false,
+            rules_dirty: false,
+            inner: InnerClient::new(homeserver_url, session).await?,
+        })
+    }
+
+    /// Creates a client from a stored session and restores its local cache.
+    ///
+    /// `content_store` is the store shared by all accounts. A session stored in its
+    /// [`ContentStore::session_file`] by an older version is moved to the session file of the
+    /// account.
+    pub async fn restore_session(
+        session_file: PathBuf,
+        content_store: Arc<ContentStore>,
+    ) -> ClientResult<Self> {
+        let session_raw = tokio::fs::read(&session_file).await?;
+        let session: Session =
+            toml::de::from_slice(&session_raw).map_err(|_| ClientError::MissingLoginInfo)?;
+        let homeserver = session
+            .homeserver
+            .parse::<Url>()
+            .map_err(|err| ClientError::URLParse(session.homeserver.clone(), err))?;
+        let user_id = session
+            .user_id
+            .parse::<u64>()
+            .map_err(|_| ClientError::MissingLoginInfo)?;
+
+        let account_store = content_store.for_account(&homeserver, user_id);
+        if account_store.session_file() != session_file {
+            tokio::fs::write(account_store.session_file(), &session_raw).await?;
+            tokio::fs::remove_file(&session_file).await?;
+        }
+
+        let mut client =
+            Client::new(homeserver, Some(session.into()), Arc::new(account_store)).await?;
+        if let Err(err) = client.restore_cache().await {
+            tracing::warn!("couldn't restore the local cache: {}", err);
+        }
+        Ok(client)
+    }
+
+    pub async fn logout(
+        _inner: InnerClient,
+        session_file: PathBuf,
+        cache_dir: PathBuf,
+    ) -> ClientResult<()> {
+        tokio::fs::remove_file(session_file).await?;
+        // The cache belongs to the session, so don't leave it behind
+        if let Err(err) = tokio::fs::remove_dir_all(cache_dir).await {
+            if err.kind() != ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn content_store(&self) -> &ContentStore {
+        &self.content_store
+    }
+
+    #[inline(always)]
+    pub fn content_store_arc(&self) -> Arc<ContentStore> {
+        self.content_store.clone()
+    }
+
+    /// Changes the store of this client, used once we know which account we logged into.
+    pub fn set_content_store(&mut self, content_store: Arc<ContentStore>) {
+        self.content_store = content_store;
+    }
+
+    #[inline(always)]
+    pub fn auth_status(&self) -> AuthStatus {
+        self.inner.auth_status()
+    }
+
+    #[inline(always)]
+    pub fn inner(&self) -> &InnerClient {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_guild(&mut self, guild_id: u64) -> Option<&mut Guild> {
+        self.guilds.get_mut(&guild_id)
+    }
+
+    pub fn get_channel(&mut self, guild_id: u64, channel_id: u64) -> Option<&mut Channel> {
+        self.get_guild(guild_id)
+            .map(|guild| guild.channels.get_mut(&channel_id))
+            .flatten()
+    }
+
+    pub fn get_message(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    ) -> Option<&mut Message> {
+        self.get_channel(guild_id, channel_id)
+            .map(|channel| channel.messages.by_id_mut(MessageId::Ack(message_id)))
+            .flatten()
+    }
+
+    #[inline(always)]
+    pub fn get_member(&mut self, user_id: u64) -> Option<&mut Member> {
+        self.members.get_mut(&user_id)
+    }
+
+    /// Moves the read marker of a channel to its latest message.
+    pub fn mark_channel_read(&mut self, guild_id: u64, channel_id: u64) {
+        if let Some(true) = self
+            .get_channel(guild_id, channel_id)
+            .map(Channel::mark_read)
+        {
+            self.state_dirty = true;
+        }
+    }
+
+    /// Returns whether a message mentions the current user, either by ID or by username.
+    fn mentions_us(&self, content: &str) -> bool {
+        self.user_id.map_or(false, |user_id| {
+            notification_rules::mentions_user(
+                content,
+                user_id,
+                self.members
+                    .get(&user_id)
+                    .map(|member| member.username.as_str()),
+            )
+        })
+    }
+
+    /// Queues a message to be sent and returns the future that sends it.
+    ///
+    /// The message is shown in the channel right away, and is kept in the outbox until the
+    /// homeserver acknowledges it. The outbox should be saved right away (see
+    /// [`Client::take_dirty_cache`]), so the message isn't lost if we crash before it's sent.
+    pub fn send_msg(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message: Message,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let transaction_id = message.id.transaction_id()?;
+        let channel = self.get_channel(guild_id, channel_id)?;
+        channel.merge_messages(vec![message.clone()]);
+
+        self.queue_outbox_entry(
+            transaction_id,
+            OutboxEntry::new(guild_id, channel_id, OutboxAction::Send(message)),
+        )
+    }
+
+    /// Queues an edit of a message and returns the future that sends it.
+    ///
+    /// Like with [`Client::send_msg`], the outbox should be saved right away.
+    pub fn edit_msg(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        new_content: String,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let transaction_id = MessageId::default().transaction_id()?;
+
+        self.queue_outbox_entry(
+            transaction_id,
+            OutboxEntry::new(
+                guild_id,
+                channel_id,
+                OutboxAction::Edit {
+                    message_id,
+                    new_content,
+                },
+            ),
+        )
+    }
+
+    /// Removes a message from its channel and returns the future that deletes it.
+    ///
+    /// The future resolves to [`Output::MessageDeleteFailed`] if the homeserver fails to delete
+    /// the message, so it can be put back.
+    pub fn delete_msg(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let channel = self.get_channel(guild_id, channel_id)?;
+        let message = channel.messages.remove(MessageId::Ack(message_id))?;
+        self.dirty_channels.insert((guild_id, channel_id));
+        self.search_index.remove(guild_id, channel_id, message_id);
+
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        Some(async move {
+            let result = delete_message(
+                &inner,
+                DeleteMessageRequest {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                },
+            )
+            .await;
+
+            result.map_or_else(
+                |err| Output::MessageDeleteFailed {
+                    guild_id,
+                    channel_id,
+                    message: Box::new(message),
+                    err: Box::new(err.into()),
+                },
+                |_| Output::Done,
+            )
+        })
+    }
+
+    /// Puts a message back into its channel, after deleting it failed.
+    pub fn restore_message(&mut self, guild_id: u64, channel_id: u64, message: Message) {
+        if let Some(channel) = self
+            .guilds
+            .get_mut(&guild_id)
+            .map(|guild| guild.channels.get_mut(&channel_id))
+            .flatten()
+        {
+            if channel.messages.contains(message.id) {
+                return;
+            }
+
+            self.search_index.insert(guild_id, channel_id, &message);
+            channel.merge_messages(vec![message]);
+            self.dirty_channels.insert((guild_id, channel_id));
+        }
+    }
+
+    /// Returns the future that pins or unpins a message.
+    pub fn pin_msg(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        pin: bool,
+    ) -> impl Future<Output = Output> + Send + 'static {
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        async move {
+            let result = if pin {
+                pin_message(
+                    &inner,
+                    PinMessageRequest {
+                        guild_id,
+                        channel_id,
+                        message_id,
+                    },
+                )
+                .await
+            } else {
+                unpin_message(
+                    &inner,
+                    UnpinMessageRequest {
+                        guild_id,
+                        channel_id,
+                        message_id,
+                    },
+                )
+                .await
+            };
+
+            result.map_or_else(
+                |err| Output::Error(Box::new(err.into())),
+                |_| Output::MessagePinned {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    pinned: pin,
+                },
+            )
+        }
+    }
+
+    /// Replaces what we know about the pinned messages of a channel.
+    pub fn set_pinned_messages(&mut self, guild_id: u64, channel_id: u64, messages: &[Message]) {
+        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+            channel.pinned_messages = Some(
+                messages
+                    .iter()
+                    .flat_map(|message| message.id.id())
+                    .collect(),
+            );
+        }
+    }
+
+    /// Updates whether a message is pinned, if we know the pinned messages of its channel.
+    pub fn set_message_pinned(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        pinned: bool,
+    ) {
+        if let Some(pinned_messages) = self
+            .get_channel(guild_id, channel_id)
+            .map(|channel| channel.pinned_messages.as_mut())
+            .flatten()
+        {
+            pinned_messages.retain(|id| *id != message_id);
+            if pinned {
+                pinned_messages.push(message_id);
+            }
+        }
+    }
+
+    /// Adds our reaction to a message if we didn't react with the emote yet, removes it
+    /// otherwise, and returns the future that tells the homeserver about it.
+    ///
+    /// The change is shown right away, and should be reverted if the future resolves to
+    /// [`Output::ReactionFailed`].
+    pub fn toggle_reaction(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        emote: String,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let message = self.get_message(guild_id, channel_id, message_id)?;
+        let add = !message
+            .reactions
+            .iter()
+            .any(|reaction| reaction.emote == emote && reaction.reacted);
+        if add {
+            message.add_reaction(&emote, true);
+        } else {
+            message.remove_reaction(&emote, true);
+        }
+
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        Some(async move {
+            let result = if add {
+                add_reaction(
+                    &inner,
+                    AddReaction::new(guild_id, channel_id, message_id, emote.clone()),
+                )
+                .await
+            } else {
+                remove_reaction(
+                    &inner,
+                    RemoveReaction::new(guild_id, channel_id, message_id, emote.clone()),
+                )
+                .await
+            };
+
+            result.map_or_else(
+                |err| Output::ReactionFailed {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    emote,
+                    added: add,
+                    err: Box::new(err.into()),
+                },
+                |_| Output::Done,
+            )
+        })
+    }
+
+    pub fn process_event(&mut self, event: Event) -> Vec<PostProcessEvent> {
+        let mut post = Vec::new();
+
+        self.mark_cache_dirty(&event);
+
+        match event {
+            Event::SentMessage(message_sent) => {
+                let echo_id = message_sent.echo_id;
+
+                // The homeserver got our message, even if we didn't get the response yet
+                if echo_id != 0 {
+                    self.outbox_done(echo_id);
+                }
+
+                if let Some(message) = message_sent.message {
+                    let guild_id = message.guild_id;
+                    let channel_id = message.channel_id;
+                    let message_id = message.message_id;
+
+                    let message = Message::from(message);
+                    let is_unread = self.user_id != Some(message.sender);
+                    // Every message of a direct conversation is meant for us
+                    let is_direct = self
+                        .guilds
+                        .get(&guild_id)
+                        .map_or(false, Guild::is_direct_message);
+                    let is_mention = is_unread && (is_direct || self.mentions_us(&message.content));
+                    let should_notify = is_unread
+                        && self.notifications.is_new(message.timestamp)
+                        && self.notification_rules.should_notify(
+                            guild_id,
+                            channel_id,
+                            &message.content,
+                            is_mention,
+                            chrono::Utc::now().naive_utc(),
+                        );
+                    let notification = if should_notify {
+                        Some(self.message_notification(guild_id, channel_id, message_id, &message))
+                    } else {
+                        None
+                    };
+                    let mut counted = false;
+
+                    self.search_index.insert(guild_id, channel_id, &message);
+
+                    if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                        post_thumbnails(&mut post, guild_id, &message);
+
+                        // Our own message is replaced by the one the homeserver has
+                        if echo_id != 0 {
+                            channel.messages.remove(MessageId::Unack(echo_id));
+                        }
+
+                        // Messages in the channel we are looking at are marked as read by the UI
+                        if channel.merge_messages(vec![message]) > 0 {
+                            if is_unread {
+                                channel.unread += 1;
+                                if is_mention {
+                                    channel.mentions += 1;
+                                }
+                                counted = true;
+                            }
+                            post.push(PostProcessEvent::GoToFirstMsgOnChannel(channel_id));
+                        }
+                    }
+
+                    if counted {
+                        self.state_dirty = true;
+                        if let Some(notification) = notification {
+                            post.push(PostProcessEvent::Notify(Box::new(notification)));
+                        }
+                    }
+                }
+            }
+            Event::DeletedMessage(MessageDeleted {
+                guild_id,
+                channel_id,
+                message_id,
+            }) => {
+                if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                    channel.messages.remove(MessageId::Ack(message_id));
+                }
+                self.search_index.remove(guild_id, channel_id, message_id);
+            }
+            Event::EditedMessage(message_updated) => {
+                let guild_id = message_updated.guild_id;
+                let channel_id = message_updated.channel_id;
+
+                if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                    if let Some(msg) = channel
+                        .messages
+                        .by_id_mut(MessageId::Ack(message_updated.message_id))
+                    {
+                        if message_updated.update_content {
+                            msg.content = message_updated.content;
+                        }
+                        if message_updated.update_attachments {
+                            msg.attachments = message_updated
+                                .attachments
+                                .into_iter()
+                                .flat_map(Attachment::from_harmony_attachment)
+                                .collect();
+                            for attachment in &msg.attachments {
+                                if attachment.is_thumbnail() {
+                                    post.push(PostProcessEvent::FetchThumbnail {
+                                        guild_id: Some(guild_id),
+                                        id: attachment.id.clone(),
+                                    });
+                                }
+                            }
+                        }
+                        if message_updated.update_overrides {
+                            msg.overrides = message_updated.overrides.map(|overrides| {
+                                let overrides: Override = overrides.into();
+                                if let Some(id) = overrides.avatar_url.clone() {
+                                    post.push(PostProcessEvent::FetchThumbnail {
+                                        guild_id: Some(guild_id),
+                                        id,
+                                    });
+                                }
+                                overrides
+                            });
+                        }
+                        if message_updated.update_embeds {
+                            msg.embeds =
+                                message_updated.embeds.into_iter().map(From::from).collect();
+                            for embed in &msg.embeds {
+                                post_heading(&mut post, guild_id, &embed);
+                            }
+                        }
+                    }
+                }
+                self.reindex_message(guild_id, channel_id, message_updated.message_id);
+            }
+            Event::ReactionAdded(reaction_added) => {
+                let own = self.user_id == Some(reaction_added.user_id);
+                if let Some(message) = self.get_message(
+                    reaction_added.guild_id,
+                    reaction_added.channel_id,
+                    reaction_added.message_id,
+                ) {
+                    message.add_reaction(&reaction_added.emote, own);
+                }
+            }
+            Event::ReactionRemoved(reaction_removed) => {
+                let own = self.user_id == Some(reaction_removed.user_id);
+                if let Some(message) = self.get_message(
+                    reaction_removed.guild_id,
+                    reaction_removed.channel_id,
+                    reaction_removed.message_id,
+                ) {
+                    message.remove_reaction(&reaction_removed.emote, own);
+                }
+            }
+            Event::DeletedChannel(ChannelDeleted {
+                guild_id,
+                channel_id,
+            }) => {
+                if let Some(guild) = self.get_guild(guild_id) {
+                    guild.channels.remove(&channel_id);
+                }
+                self.search_index.remove_channel(guild_id, channel_id);
+            }
+            Event::EditedChannel(ChannelUpdated {
+                guild_id,
+                channel_id,
+                name,
+                update_name,
+                previous_id,
+                next_id,
+                update_order,
+                metadata: _,
+                update_metadata: _,
+            }) => {
+                if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                    if update_name {
+                        channel.name = name;
+                    }
+                }
+
+                if update_order {
+                    if let Some(guild) = self.get_guild(guild_id) {
+                        guild.update_channel_order(previous_id, next_id, channel_id);
+                    }
+                }
+            }
+            Event::CreatedChannel(ChannelCreated {
+                guild_id,
+                channel_id,
+                name,
+                previous_id,
+                next_id,
+                is_category,
+                metadata: _,
+            }) => {
+                if let Some(guild) = self.get_guild(guild_id) {
+                    // The channel may already be there if it was restored from the cache
+                    let channel = guild.channels.entry(channel_id).or_default();
+                    channel.is_category = is_category;
+                    channel.name = name;
+                    guild.update_channel_order(previous_id, next_id, channel_id);
+                }
+            }
+            Event::Typing(Typing {
+                guild_id,
+                channel_id,
+                user_id,
+            }) => {
+                if let Some(member) = self.get_member(user_id) {
+                    member.typing_in_channel = Some((guild_id, channel_id, Instant::now()));
+                }
+            }
+            Event::JoinedMember(MemberJoined {
+                guild_id,
+                member_id,
+            }) => {
+                if member_id == 0 {
+                    return post;
+                }
+
+                if let Some(guild) = self.get_guild(guild_id) {
+                    guild.members.insert(member_id);
+                }
+
+                if !self.members.contains_key(&member_id) {
+                    post.push(PostProcessEvent::FetchProfile {
+                        guild_id,
+                        user_id: member_id,
+                    });
+                }
+            }
+            Event::LeftMember(MemberLeft {
+                guild_id,
+                member_id,
+                leave_reason: _,
+            }) => {
+                if let Some(guild) = self.get_guild(guild_id) {
+                    guild.members.remove(&member_id);
+                    guild.member_roles.remove(&member_id);
+                }
+            }
+            Event::ProfileUpdated(ProfileUpdated {
+                user_id,
+                new_username,
+                update_username,
+                new_avatar,
+                update_avatar,
+                new_status,
+                update_status,
+                is_bot: _,
+                update_is_bot: _,
+            }) => {
+                let member = self.members.entry(user_id).or_default();
+                if update_username {
+                    member.username = new_username;
+                }
+                if update_status {
+                    member.status = UserStatus::from_i32(new_status).unwrap_or(UserStatus::Offline);
+                }
+                if update_avatar {
+                    let parsed = FileId::from_str(&new_avatar).ok();
+                    member.avatar_url = parsed.clone();
+                    // Profile events don't tell which guild the user was seen in
+                    if let Some(id) = parsed {
+                        post.push(PostProcessEvent::FetchThumbnail { guild_id: None, id });
+                    }
+                };
+            }
+            Event::GuildAddedToList(GuildAddedToList {
+                guild_id,
+                homeserver,
+            }) => {
+                let homeserver = self.parse_guild_homeserver(&homeserver);
+                // The guild may already be there if it was restored from the cache
+                self.guilds.entry(guild_id).or_default().homeserver = homeserver.clone();
+                if let Some(homeserver) = homeserver {
+                    post.push(PostProcessEvent::Federate(homeserver));
+                }
+                post.push(PostProcessEvent::FetchGuildData(guild_id));
+            }
+            Event::GuildRemovedFromList(GuildRemovedFromList {
+                guild_id,
+                homeserver: _,
+            }) => {
+                self.guilds.remove(&guild_id);
+                self.search_index.remove_guild(guild_id);
+            }
+            Event::DeletedGuild(GuildDeleted { guild_id }) => {
+                self.guilds.remove(&guild_id);
+                self.search_index.remove_guild(guild_id);
+            }
+            Event::EditedGuild(GuildUpdated {
+                guild_id,
+                name,
+                update_name,
+                picture,
+                update_picture,
+                metadata: _,
+                update_metadata: _,
+            }) => {
+                let guild = self.guilds.entry(guild_id).or_default();
+
+                if update_name {
+                    guild.name = name;
+                }
+                if update_picture {
+                    let parsed = FileId::from_str(&picture).ok();
+                    guild.picture = parsed.clone();
+                    if let Some(id) = parsed {
+                        post.push(PostProcessEvent::FetchThumbnail {
+                            guild_id: Some(guild_id),
+                            id,
+                        });
+                    }
+                }
+            }
+            Event::RoleCreated(role_created) => {
+                if let Some(guild) = self.get_guild(role_created.guild_id) {
+                    guild.roles.insert(
+                        role_created.role_id,
+                        Role {
+                            name: role_created.name,
+                            color: role::color_from_i32(role_created.color),
+                            hoist: role_created.hoist,
+                            pingable: role_created.pingable,
+                        },
+                    );
+                }
+            }
+            Event::RoleUpdated(role_updated) => {
+                if let Some(role) = self
+                    .get_guild(role_updated.guild_id)
+                    .map(|guild| guild.roles.get_mut(&role_updated.role_id))
+                    .flatten()
+                {
+                    if role_updated.update_name {
+                        role.name = role_updated.name;
+                    }
+                    if role_updated.update_color {
+                        role.color = role::color_from_i32(role_updated.color);
+                    }
+                    if role_updated.update_hoist {
+                        role.hoist = role_updated.hoist;
+                    }
+                    if role_updated.update_pingable {
+                        role.pingable = role_updated.pingable;
+                    }
+                }
+            }
+            Event::RoleDeleted(RoleDeleted { guild_id, role_id }) => {
+                if let Some(guild) = self.get_guild(guild_id) {
+                    guild.roles.shift_remove(&role_id);
+                    for role_ids in guild.member_roles.values_mut() {
+                        role_ids.retain(|id| *id != role_id);
+                    }
+                }
+                post.extend(self.refresh_permissions(guild_id));
+            }
+            Event::RoleMoved(RoleMoved {
+                guild_id,
+                role_id,
+                previous_id,
+                next_id,
+            }) => {
+                if let Some(guild) = self.get_guild(guild_id) {
+                    guild.update_role_order(previous_id, next_id, role_id);
+                }
+                post.extend(self.refresh_permissions(guild_id));
+            }
+            Event::UserRolesUpdated(UserRolesUpdated {
+                guild_id,
+                user_id,
+                role_ids,
+            }) => {
+                if let Some(guild) = self.get_guild(guild_id) {
+                    guild.member_roles.insert(user_id, role_ids);
+                }
+                if self.user_id == Some(user_id) {
+                    post.extend(self.refresh_permissions(guild_id));
+                }
+            }
+            Event::PermissionUpdated(PermissionUpdated {
+                guild_id,
+                channel_id,
+                query,
+                ok,
+            }) => {
+                // A channel ID of 0 means the permission applies to the whole guild
+                if channel_id == 0 {
+                    if let Some(guild) = self.get_guild(guild_id) {
+                        guild.permissions.insert(query, ok);
+                    }
+                } else if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                    channel.permissions.insert(query, ok);
+                }
+            }
+            // We don't show permissions of roles, but ours may have changed
+            Event::RolePermsUpdated(role_perms_updated) => {
+                post.extend(self.refresh_permissions(role_perms_updated.guild_id));
+            }
+            // Actions are only meant for bots, so we don't need to do anything
+            Event::ActionPerformed(_) => {}
+            // Newer homeservers may send events we don't know about yet, don't crash on them
+            #[allow(unreachable_patterns)]
+            event => tracing::warn!("ignoring unknown event: {:?}", event),
+        }
+
+        post
+    }
+
+    /// Returns the event that queries our permissions again, for the guild and the channels we
+    /// already know the permissions of.
+    fn refresh_permissions(&mut self, guild_id: u64) -> Option<PostProcessEvent> {
+        let guild = self.get_guild(guild_id)?;
+        let channel_ids = guild
+            .channels
+            .iter()
+            .filter(|(_, channel)| !channel.permissions.is_empty())
+            .map(|(channel_id, _)| *channel_id)
+            .collect();
+
+        Some(PostProcessEvent::FetchPermissions {
+            guild_id,
+            channel_ids,
+        })
+    }
+
+    pub fn process_get_message_history_response(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<HarmonyMessage>,
+        reached_top: bool,
+    ) -> Vec<PostProcessEvent> {
+        let mut post = Vec::new();
+        let messages = harmony_messages_to_ui_messages(messages);
+
+        for message in &messages {
+            post_thumbnails(&mut post, guild_id, message);
+            self.search_index.insert(guild_id, channel_id, message);
+        }
+
+        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+            // Pages can overlap with what we have, if messages arrived while they were loading
+            channel.merge_messages(messages);
+            if reached_top && !channel.reached_top {
+                channel.reached_top = true;
+                self.state_dirty = true;
+            }
+            self.dirty_channels.insert((guild_id, channel_id));
+        }
+
+        post
+    }
+
+    pub fn subscribe_to(&self) -> Vec<EventSource> {
+        // Events of guilds on other homeservers come from their own event streams
+        let mut subs = self
+            .guilds
+            .iter()
+            .filter(|(_, guild)| guild.homeserver.is_none())
+            .map(|(guild_id, _)| EventSource::Guild(*guild_id))
+            .collect::<Vec<_>>();
+        subs.push(EventSource::Homeserver);
+        subs
+    }
+}
+
+fn post_thumbnails(post: &mut Vec<PostProcessEvent>, guild_id: u64, message: &Message) {
+    if let Some(id) = message
+        .overrides
+        .as_ref()
+        .map(|overrides| overrides.avatar_url.clone())
+        .flatten()
+    {
+        post.push(PostProcessEvent::FetchThumbnail {
+            guild_id: Some(guild_id),
+            id,
+        });
+    }
+
+    for attachment in &message.attachments {
+        if attachment.is_thumbnail() {
+            post.push(PostProcessEvent::FetchThumbnail {
+                guild_id: Some(guild_id),
+                id: attachment.id.clone(),
+            });
+        }
+    }
+
+    for embed in &message.embeds {
+        post_heading(post, guild_id, &embed);
+    }
+}
+
+fn post_heading(post: &mut Vec<PostProcessEvent>, guild_id: u64, embed: &Embed) {
+    let mut inner = |h: Option<&EmbedHeading>| {
+        if let Some(id) = h.map(|h| h.icon.clone()).flatten() {
+            post.push(PostProcessEvent::FetchThumbnail {
+                guild_id: Some(guild_id),
+                id,
+            });
+        }
+    };
+    inner(embed.header.as_ref());
+    inner(embed.footer.as_ref());
+}
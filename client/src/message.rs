@@ -0,0 +1,784 @@
+use ahash::AHashMap;
+use chrono::NaiveDateTime;
+use harmony_rust_sdk::{
+    api::harmonytypes::{
+        self, r#override::Reason, FieldPresentation, Message as HarmonyMessage,
+        Override as HarmonyOverride,
+    },
+    client::api::{
+        chat::message::{SendMessage, SendMessageSelfBuilder},
+        rest::FileId,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{btree_map, BTreeMap},
+    ops::Range,
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+use uuid::Uuid;
+
+use super::{
+    color::Color,
+    content::{ContentType, MAX_THUMB_SIZE},
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmbedField {
+    pub title: String,
+    pub subtitle: String,
+    pub body: String,
+    #[serde(with = "serde_helpers::presentation")]
+    pub presentation: FieldPresentation,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmbedHeading {
+    pub url: Option<String>,
+    #[serde(with = "serde_helpers::opt_file_id")]
+    pub icon: Option<FileId>,
+    pub text: String,
+    pub subtext: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Embed {
+    pub title: String,
+    pub body: String,
+    pub color: Color,
+    pub footer: Option<EmbedHeading>,
+    pub header: Option<EmbedHeading>,
+    pub fields: Vec<EmbedField>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Attachment {
+    pub kind: ContentType,
+    pub name: String,
+    #[serde(with = "serde_helpers::file_id")]
+    pub id: FileId,
+    pub size: u32,
+}
+
+impl Attachment {
+    pub fn is_thumbnail(&self) -> bool {
+        matches!(self.kind, ContentType::Image) && (self.size as u64) < MAX_THUMB_SIZE
+    }
+
+    pub fn from_harmony_attachment(attachment: harmonytypes::Attachment) -> Option<Self> {
+        Some(Attachment {
+            id: FileId::from_str(&attachment.id).ok()?,
+            kind: ContentType::new(&attachment.r#type),
+            name: attachment.name,
+            size: attachment.size as u32,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Override {
+    pub name: String,
+    #[serde(with = "serde_helpers::opt_file_id")]
+    pub avatar_url: Option<FileId>,
+    #[serde(with = "serde_helpers::reason")]
+    pub reason: Option<Reason>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum MessageId {
+    Ack(u64),
+    Unack(u64),
+}
+
+impl MessageId {
+    pub fn is_ack(&self) -> bool {
+        matches!(self, MessageId::Ack(_))
+    }
+
+    pub fn transaction_id(&self) -> Option<u64> {
+        match self {
+            MessageId::Unack(transaction) => Some(*transaction),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            MessageId::Ack(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MessageId {
+    fn default() -> Self {
+        let transaction = Uuid::new_v4().as_u128() as u64;
+        MessageId::Unack(transaction)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Reaction {
+    pub emote: String,
+    pub count: u32,
+    /// Whether the current user reacted with this emote.
+    pub reacted: bool,
+}
+
+impl From<harmonytypes::Reaction> for Reaction {
+    fn from(reaction: harmonytypes::Reaction) -> Self {
+        Self {
+            emote: reaction.emote,
+            count: reaction.count,
+            reacted: reaction.reacted,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Message {
+    pub id: MessageId,
+    pub content: String,
+    pub sender: u64,
+    pub timestamp: NaiveDateTime,
+    pub attachments: Vec<Attachment>,
+    pub overrides: Option<Override>,
+    pub embeds: Vec<Embed>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    /// ID of the message this message replies to.
+    #[serde(default)]
+    pub reply_to: Option<u64>,
+    #[serde(skip)]
+    pub being_edited: Option<String>,
+    /// Whether we gave up sending this message, or its pending edit.
+    #[serde(skip)]
+    pub failed_to_send: bool,
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Self {
+            id: Default::default(),
+            content: Default::default(),
+            sender: Default::default(),
+            timestamp: {
+                let timestamp = UNIX_EPOCH.elapsed().unwrap();
+                NaiveDateTime::from_timestamp(timestamp.as_secs() as i64, timestamp.subsec_nanos())
+            },
+            attachments: Default::default(),
+            overrides: None,
+            embeds: Default::default(),
+            reactions: Default::default(),
+            reply_to: None,
+            being_edited: None,
+            failed_to_send: false,
+        }
+    }
+}
+
+impl Message {
+    /// Creates the request that sends this message.
+    ///
+    /// The homeserver echoes `transaction_id` back in the event of the message.
+    pub fn into_send_request(
+        self,
+        guild_id: u64,
+        channel_id: u64,
+        transaction_id: u64,
+    ) -> SendMessage {
+        SendMessage::new(guild_id, channel_id, self.content)
+            .echo_id(transaction_id)
+            .in_reply_to(self.reply_to.unwrap_or_default())
+            .attachments(
+                self.attachments
+                    .into_iter()
+                    .map(|a| a.id)
+                    .collect::<Vec<_>>(),
+            )
+            .overrides(self.overrides.map(|o| {
+                HarmonyOverride {
+                    avatar: o
+                        .avatar_url
+                        .as_ref()
+                        .map_or_else(String::default, |id| id.to_string()),
+                    name: o.name,
+                    reason: o.reason,
+                }
+            }))
+    }
+
+    /// Adds a reaction to this message.
+    ///
+    /// Does nothing if it's our own reaction and we already reacted, so our optimistic updates
+    /// aren't counted twice when the homeserver echoes them back.
+    pub fn add_reaction(&mut self, emote: &str, own: bool) {
+        match self.reactions.iter_mut().find(|r| r.emote == emote) {
+            Some(reaction) if own && reaction.reacted => {}
+            Some(reaction) => {
+                reaction.count += 1;
+                reaction.reacted |= own;
+            }
+            None => self.reactions.push(Reaction {
+                emote: emote.to_string(),
+                count: 1,
+                reacted: own,
+            }),
+        }
+    }
+
+    /// Removes a reaction from this message.
+    ///
+    /// Does nothing if it's our own reaction and we didn't react, see [`Message::add_reaction`].
+    pub fn remove_reaction(&mut self, emote: &str, own: bool) {
+        if let Some(pos) = self.reactions.iter().position(|r| r.emote == emote) {
+            let reaction = &mut self.reactions[pos];
+            if own && !reaction.reacted {
+                return;
+            }
+
+            reaction.count = reaction.count.saturating_sub(1);
+            if own {
+                reaction.reacted = false;
+            }
+            if reaction.count == 0 {
+                self.reactions.remove(pos);
+            }
+        }
+    }
+}
+
+/// Converts messages fetched from the homeserver, which come newest first, to messages in the
+/// order they were sent.
+pub fn harmony_messages_to_ui_messages(messages: Vec<HarmonyMessage>) -> Vec<Message> {
+    messages.into_iter().map(From::from).rev().collect()
+}
+
+/// Where a message goes in a timeline: acknowledged messages are ordered by timestamp and then
+/// ID, and are followed by the messages the homeserver didn't acknowledge yet, ordered by
+/// timestamp and then transaction ID.
+type TimelineKey = (bool, NaiveDateTime, u64);
+
+fn timeline_key(message: &Message) -> TimelineKey {
+    match message.id {
+        MessageId::Ack(id) => (false, message.timestamp, id),
+        MessageId::Unack(transaction_id) => (true, message.timestamp, transaction_id),
+    }
+}
+
+/// Timeline of a channel, with the messages indexed by their ID.
+///
+/// Messages are kept sorted by where they go in the timeline, so adding or removing one doesn't
+/// move the others, and are found by ID without scanning the timeline. Since the order depends
+/// on them, the ID and timestamp of a message are only changed by merging it again, see
+/// [`Messages::merge`].
+#[derive(Debug, Clone, Default)]
+pub struct Messages {
+    timeline: BTreeMap<TimelineKey, Message>,
+    /// Where every message is in `timeline`.
+    keys: AHashMap<MessageId, TimelineKey>,
+}
+
+impl From<Vec<Message>> for Messages {
+    fn from(messages: Vec<Message>) -> Self {
+        let mut store = Messages::default();
+        store.merge(messages);
+        store
+    }
+}
+
+impl Messages {
+    pub fn len(&self) -> usize {
+        self.timeline.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timeline.is_empty()
+    }
+
+    /// Returns the messages in timeline order.
+    pub fn iter(&self) -> btree_map::Values<'_, TimelineKey, Message> {
+        self.timeline.values()
+    }
+
+    pub fn first(&self) -> Option<&Message> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&Message> {
+        self.iter().next_back()
+    }
+
+    /// Returns the messages in `range` of the timeline.
+    ///
+    /// The timeline is walked from the end closer to `range`, so getting the latest messages
+    /// doesn't depend on how much history was loaded. Getting messages in the middle of a long
+    /// timeline walks up to half of it, which keeps merging cheap compared to maintaining an
+    /// index of positions, see the `timeline_lookups` benchmarks.
+    pub fn slice(&self, range: Range<usize>) -> Vec<&Message> {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        if start < self.len() - end {
+            self.iter().skip(start).take(end - start).collect()
+        } else {
+            let mut messages = self
+                .iter()
+                .rev()
+                .skip(self.len() - end)
+                .take(end - start)
+                .collect::<Vec<_>>();
+            messages.reverse();
+            messages
+        }
+    }
+
+    /// Returns where a message is in the timeline.
+    ///
+    /// This counts the messages after it, so it's cheap for the latest messages, which are the
+    /// ones usually looked for, and takes as long as the timeline is for the oldest. Use
+    /// [`Messages::by_id`] to get a message.
+    pub fn position(&self, id: MessageId) -> Option<usize> {
+        let key = self.keys.get(&id)?;
+        Some(self.len() - self.timeline.range(key..).count())
+    }
+
+    /// Returns the message right before a message in the timeline.
+    pub fn before(&self, id: MessageId) -> Option<&Message> {
+        let key = self.keys.get(&id)?;
+        self.timeline
+            .range(..key)
+            .next_back()
+            .map(|(_, message)| message)
+    }
+
+    pub fn contains(&self, id: MessageId) -> bool {
+        self.keys.contains_key(&id)
+    }
+
+    pub fn by_id(&self, id: MessageId) -> Option<&Message> {
+        self.timeline.get(self.keys.get(&id)?)
+    }
+
+    /// Returns a message to be modified in place, which must keep its ID and timestamp.
+    pub fn by_id_mut(&mut self, id: MessageId) -> Option<&mut Message> {
+        self.timeline.get_mut(self.keys.get(&id)?)
+    }
+
+    pub fn remove(&mut self, id: MessageId) -> Option<Message> {
+        let key = self.keys.remove(&id)?;
+        self.timeline.remove(&key)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&Message) -> bool) {
+        let keys = &mut self.keys;
+        self.timeline.retain(|_, message| {
+            let keep = f(message);
+            if !keep {
+                keys.remove(&message.id);
+            }
+            keep
+        });
+    }
+
+    /// Merges messages into the timeline, in whatever order and however they overlap with it.
+    ///
+    /// Messages we already have are replaced, as are earlier copies of a message given more than
+    /// once, and the timeline stays ordered with no message in it twice. Returns how many of the
+    /// messages are new.
+    pub fn merge(&mut self, messages: Vec<Message>) -> usize {
+        let mut added = 0;
+        for message in messages {
+            let key = timeline_key(&message);
+            match self.keys.insert(message.id, key) {
+                // The message moved, its timestamp changed
+                Some(old_key) if old_key != key => {
+                    self.timeline.remove(&old_key);
+                }
+                Some(_) => {}
+                None => added += 1,
+            }
+            self.timeline.insert(key, message);
+        }
+        added
+    }
+}
+
+impl From<harmonytypes::Override> for Override {
+    fn from(overrides: harmonytypes::Override) -> Self {
+        Override {
+            name: overrides.name,
+            avatar_url: FileId::from_str(&overrides.avatar).ok(),
+            reason: overrides.reason,
+        }
+    }
+}
+
+impl From<harmonytypes::EmbedHeading> for EmbedHeading {
+    fn from(h: harmonytypes::EmbedHeading) -> Self {
+        EmbedHeading {
+            text: h.text,
+            subtext: h.subtext,
+            url: {
+                if h.url.is_empty() {
+                    None
+                } else {
+                    Some(h.url)
+                }
+            },
+            icon: FileId::from_str(&h.icon).ok(),
+        }
+    }
+}
+
+impl From<harmonytypes::Embed> for Embed {
+    fn from(e: harmonytypes::Embed) -> Self {
+        Embed {
+            title: e.title,
+            body: e.body,
+            footer: e.footer.map(From::from),
+            header: e.header.map(From::from),
+            fields: e
+                .fields
+                .into_iter()
+                .map(|f| EmbedField {
+                    presentation: f.presentation(),
+                    title: f.title,
+                    subtitle: f.subtitle,
+                    body: f.body,
+                })
+                .collect(),
+            color: Color::from_rgb_i32(e.color),
+        }
+    }
+}
+
+impl From<HarmonyMessage> for Message {
+    fn from(message: HarmonyMessage) -> Self {
+        Message {
+            embeds: message.embeds.into_iter().map(From::from).collect(),
+            content: message.content,
+            id: MessageId::Ack(message.message_id),
+            sender: message.author_id,
+            timestamp: {
+                let t = message
+                    .created_at
+                    .unwrap_or_else(|| std::time::SystemTime::now().into());
+                NaiveDateTime::from_timestamp(t.seconds, t.nanos as u32)
+            },
+            overrides: message.overrides.map(From::from),
+            attachments: message
+                .attachments
+                .into_iter()
+                .flat_map(Attachment::from_harmony_attachment)
+                .collect(),
+            reactions: message.reactions.into_iter().map(From::from).collect(),
+            reply_to: Some(message.in_reply_to).filter(|id| *id != 0),
+            being_edited: None,
+            failed_to_send: false,
+        }
+    }
+}
+
+/// `serde` adapters for the foreign types we store in messages, so they can be written to the
+/// local cache.
+mod serde_helpers {
+    pub mod file_id {
+        use crate::content::file_id_to_string;
+        use harmony_rust_sdk::client::api::rest::FileId;
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+        use std::str::FromStr;
+
+        pub fn serialize<S: Serializer>(id: &FileId, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&file_id_to_string(id))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FileId, D::Error> {
+            let raw = String::deserialize(deserializer)?;
+            FileId::from_str(&raw)
+                .map_err(|_| D::Error::custom(format!("invalid file id: {}", raw)))
+        }
+    }
+
+    pub mod opt_file_id {
+        use crate::content::file_id_to_string;
+        use harmony_rust_sdk::client::api::rest::FileId;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use std::str::FromStr;
+
+        pub fn serialize<S: Serializer>(
+            id: &Option<FileId>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            id.as_ref().map(file_id_to_string).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<FileId>, D::Error> {
+            let raw = Option::<String>::deserialize(deserializer)?;
+            Ok(raw.map(|raw| FileId::from_str(&raw).ok()).flatten())
+        }
+    }
+
+    pub mod presentation {
+        use harmony_rust_sdk::api::harmonytypes::FieldPresentation;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            presentation: &FieldPresentation,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            (*presentation as i32).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<FieldPresentation, D::Error> {
+            let raw = i32::deserialize(deserializer)?;
+            Ok(FieldPresentation::from_i32(raw).unwrap_or_default())
+        }
+    }
+
+    pub mod reason {
+        use harmony_rust_sdk::api::harmonytypes::r#override::Reason;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        #[derive(Deserialize, Serialize)]
+        enum StoredReason {
+            UserDefined(String),
+            Webhook,
+            SystemPlurality,
+            SystemMessage,
+            Bridge,
+        }
+
+        pub fn serialize<S: Serializer>(
+            reason: &Option<Reason>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            reason
+                .as_ref()
+                .map(|reason| match reason {
+                    Reason::UserDefined(reason) => StoredReason::UserDefined(reason.clone()),
+                    Reason::Webhook(_) => StoredReason::Webhook,
+                    Reason::SystemPlurality(_) => StoredReason::SystemPlurality,
+                    Reason::SystemMessage(_) => StoredReason::SystemMessage,
+                    Reason::Bridge(_) => StoredReason::Bridge,
+                })
+                .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Reason>, D::Error> {
+            let raw = Option::<StoredReason>::deserialize(deserializer)?;
+            Ok(raw.map(|reason| match reason {
+                StoredReason::UserDefined(reason) => Reason::UserDefined(reason),
+                StoredReason::Webhook => Reason::Webhook(Default::default()),
+                StoredReason::SystemPlurality => Reason::SystemPlurality(Default::default()),
+                StoredReason::SystemMessage => Reason::SystemMessage(Default::default()),
+                StoredReason::Bridge => Reason::Bridge(Default::default()),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, MessageId, Messages};
+    use crate::channel::Channel;
+    use chrono::NaiveDateTime;
+
+    fn message(id: u64) -> Message {
+        Message {
+            id: MessageId::Ack(id),
+            content: format!("message {}", id),
+            timestamp: NaiveDateTime::from_timestamp(1_600_000_000 + id as i64, 0),
+            ..Default::default()
+        }
+    }
+
+    fn ids(messages: &Messages) -> Vec<MessageId> {
+        messages.iter().map(|message| message.id).collect()
+    }
+
+    /// Checks that every message is found where it is in the timeline.
+    fn assert_positions(messages: &Messages) {
+        for (pos, message) in messages.iter().enumerate() {
+            assert_eq!(messages.position(message.id), Some(pos), "{:?}", message.id);
+        }
+    }
+
+    fn acked(range: impl Iterator<Item = u64>) -> Vec<MessageId> {
+        range.map(MessageId::Ack).collect()
+    }
+
+    #[test]
+    fn overlapping_pages() {
+        let mut messages = Messages::default();
+
+        assert_eq!(messages.merge((10..20).map(message).collect()), 10);
+        assert_positions(&messages);
+
+        // Newer page that overlaps, with an edit of a message we had
+        let mut page = (15..25).map(message).collect::<Vec<_>>();
+        page[0].content = "edited".to_string();
+        assert_eq!(messages.merge(page), 5);
+        assert_positions(&messages);
+        assert_eq!(
+            messages.by_id(MessageId::Ack(15)).unwrap().content,
+            "edited"
+        );
+
+        // Older page that overlaps
+        assert_eq!(messages.merge((0..12).map(message).collect()), 10);
+        assert_positions(&messages);
+        assert_eq!(ids(&messages), acked(0..25));
+    }
+
+    #[test]
+    fn duplicate_ids_in_one_batch() {
+        let mut messages = Messages::from((0..5).map(message).collect::<Vec<_>>());
+
+        // The copies aren't next to each other once ordered, the last one wins
+        let mut moved = message(1);
+        moved.timestamp = message(3).timestamp;
+        moved.content = "moved".to_string();
+        let batch = vec![message(7), message(1), message(6), moved, message(7)];
+        assert_eq!(messages.merge(batch), 2);
+        assert_positions(&messages);
+        assert_eq!(ids(&messages), acked([0, 2, 1, 3, 4, 6, 7].iter().copied()));
+        assert_eq!(messages.by_id(MessageId::Ack(1)).unwrap().content, "moved");
+
+        // Same for messages we didn't have
+        let mut later = message(9);
+        later.content = "later".to_string();
+        let mut earlier = message(9);
+        earlier.timestamp = message(8).timestamp;
+        assert_eq!(messages.merge(vec![later, earlier.clone()]), 1);
+        assert_positions(&messages);
+        assert_eq!(messages.len(), 8);
+        assert_eq!(messages.last().unwrap().timestamp, earlier.timestamp);
+    }
+
+    #[test]
+    fn out_of_order_input() {
+        let mut messages = Messages::default();
+
+        let batch = [5, 1, 9, 3, 7].iter().copied().map(message).collect();
+        assert_eq!(messages.merge(batch), 5);
+        assert_positions(&messages);
+
+        let batch = [8, 0, 6, 2, 4].iter().copied().map(message).collect();
+        assert_eq!(messages.merge(batch), 5);
+        assert_positions(&messages);
+        assert_eq!(ids(&messages), acked(0..10));
+    }
+
+    #[test]
+    fn ack_replaces_unack() {
+        let mut channel = Channel {
+            messages: Messages::from((0..5).map(message).collect::<Vec<_>>()),
+            ..Default::default()
+        };
+        let sent = Message {
+            id: MessageId::Unack(100),
+            ..message(5)
+        };
+        assert_eq!(channel.merge_messages(vec![sent]), 1);
+
+        // Messages we didn't send yet stay at the bottom
+        assert_eq!(channel.merge_messages(vec![message(6)]), 1);
+        assert_positions(&channel.messages);
+        assert_eq!(channel.messages.last().unwrap().id, MessageId::Unack(100));
+
+        channel.ack_message(100, 5);
+        assert_positions(&channel.messages);
+        assert_eq!(ids(&channel.messages), acked(0..7));
+        assert!(!channel.messages.contains(MessageId::Unack(100)));
+
+        // The homeserver may send us our message before acknowledging it
+        let sent = Message {
+            id: MessageId::Unack(101),
+            ..message(7)
+        };
+        channel.merge_messages(vec![sent]);
+        channel.merge_messages(vec![message(7)]);
+        channel.ack_message(101, 7);
+        assert_positions(&channel.messages);
+        assert_eq!(ids(&channel.messages), acked(0..8));
+    }
+
+    #[test]
+    fn mid_timeline_inserts() {
+        let even = (0..128).map(|id| id * 2);
+        let mut messages = Messages::from(even.clone().map(message).collect::<Vec<_>>());
+
+        let batch = (0..64).map(|id| id * 2 + 1).map(message).collect();
+        assert_eq!(messages.merge(batch), 64);
+        assert_positions(&messages);
+
+        let mut expected = even.clone().map(MessageId::Ack).collect::<Vec<_>>();
+        expected.extend((0..64).map(|id| MessageId::Ack(id * 2 + 1)));
+        expected.sort_by_key(|id| id.id());
+        assert_eq!(ids(&messages), expected);
+
+        // Removing keeps the positions of what's left right
+        for id in (0..128).step_by(3) {
+            messages.remove(MessageId::Ack(id * 2));
+        }
+        assert_positions(&messages);
+    }
+
+    #[test]
+    fn slices() {
+        let messages = Messages::from((0..10).map(message).collect::<Vec<_>>());
+        let slice_ids = |start, end| {
+            messages
+                .slice(start..end)
+                .into_iter()
+                .map(|message| message.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(slice_ids(1, 3), acked(1..3));
+        assert_eq!(slice_ids(6, 10), acked(6..10));
+        assert_eq!(slice_ids(8, 20), acked(8..10));
+        assert_eq!(slice_ids(12, 20), acked(0..0));
+    }
+
+    #[test]
+    fn filling_a_gap() {
+        let mut messages = Messages::from((0..10).map(message).collect::<Vec<_>>());
+
+        // Latest messages that don't reach back to what we had, then the messages in between
+        messages.merge((20..30).rev().map(message).collect());
+        assert_eq!(
+            messages
+                .before(MessageId::Ack(20))
+                .map(|message| message.id),
+            Some(MessageId::Ack(9))
+        );
+        messages.merge((10..20).rev().map(message).collect());
+        assert_eq!(
+            messages
+                .before(MessageId::Ack(20))
+                .map(|message| message.id),
+            Some(MessageId::Ack(19))
+        );
+
+        assert_eq!(
+            messages.before(MessageId::Ack(0)).map(|message| message.id),
+            None
+        );
+        assert_eq!(
+            messages
+                .before(MessageId::Ack(30))
+                .map(|message| message.id),
+            None
+        );
+        assert_positions(&messages);
+        assert_eq!(ids(&messages), acked(0..30));
+    }
+}
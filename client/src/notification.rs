@@ -0,0 +1,421 @@
+//! Notifications for messages that need the user's attention.
+//!
+//! Every notification is kept in the notification center, and is also handed to a
+//! [`NotificationBackend`] which decides how the user is told about it.
+
+use super::{message::Message, Client};
+
+use chrono::NaiveDateTime;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Formatter},
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+
+/// How many notifications the notification center keeps.
+pub const NOTIFICATIONS_LIMIT: usize = 100;
+
+/// Environment variable that selects the notification backend.
+///
+/// Can be `in-app`, `desktop` or `log`. Defaults to `desktop`, or to `in-app` if the
+/// `desktop-notifications` feature is disabled.
+pub const BACKEND_ENV_VAR: &str = "CRUST_NOTIFICATIONS";
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub id: u64,
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub title: String,
+    pub body: String,
+    pub timestamp: NaiveDateTime,
+    /// Whether the user saw this notification in the notification center.
+    pub read: bool,
+}
+
+impl Client {
+    /// Builds the notification for a message that mentions the current user, or that was sent
+    /// to them directly.
+    pub(super) fn message_notification(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        message: &Message,
+    ) -> Notification {
+        let sender_name = message.overrides.as_ref().map_or_else(
+            || {
+                self.members
+                    .get(&message.sender)
+                    .map_or_else(|| String::from("unknown"), |member| member.username.clone())
+            },
+            |overrides| overrides.name.clone(),
+        );
+        let title = match self.guilds.get(&guild_id) {
+            Some(guild) if guild.is_direct_message() => {
+                format!("{} (direct message)", sender_name)
+            }
+            Some(guild) => {
+                let channel_name = guild
+                    .channels
+                    .get(&channel_id)
+                    .map_or("unknown", |channel| channel.name.as_str());
+                format!("{} in #{} ({})", sender_name, channel_name, guild.name)
+            }
+            None => format!("{} in #unknown (unknown)", sender_name),
+        };
+
+        Notification {
+            id: 0,
+            guild_id,
+            channel_id,
+            message_id,
+            title,
+            body: message.content.clone(),
+            timestamp: message.timestamp,
+            read: false,
+        }
+    }
+}
+
+/// Delivers notifications to the user.
+pub trait NotificationBackend: Send {
+    fn deliver(&mut self, notification: &Notification);
+}
+
+/// Only shows notifications in the notification center and as toasts.
+#[derive(Debug, Default)]
+pub struct InAppBackend;
+
+impl NotificationBackend for InAppBackend {
+    fn deliver(&mut self, _: &Notification) {}
+}
+
+/// Shows notifications through the freedesktop notification service (or the platform
+/// equivalent).
+///
+/// Talking to the notification service blocks, so notifications are shown on tokio's blocking
+/// thread pool, which means this backend must be used from within a tokio runtime.
+#[cfg(feature = "desktop-notifications")]
+#[derive(Debug, Default)]
+pub struct DesktopBackend;
+
+#[cfg(feature = "desktop-notifications")]
+impl NotificationBackend for DesktopBackend {
+    fn deliver(&mut self, notification: &Notification) {
+        let title = notification.title.clone();
+        let body = notification.body.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let result = notify_rust::Notification::new()
+                .appname("Crust")
+                .summary(&title)
+                .body(&body)
+                .show();
+
+            if let Err(err) = result {
+                tracing::error!("couldn't show desktop notification: {}", err);
+            }
+        });
+    }
+}
+
+/// Logs notifications and keeps every delivered notification around.
+///
+/// `delivered` can be cloned before the backend is handed to the client, to look at what was
+/// delivered later.
+#[derive(Debug, Default, Clone)]
+pub struct LogBackend {
+    pub delivered: Arc<Mutex<Vec<Notification>>>,
+}
+
+impl NotificationBackend for LogBackend {
+    fn deliver(&mut self, notification: &Notification) {
+        tracing::info!(
+            "notification for message {} in {}/{}: {}: {}",
+            notification.message_id,
+            notification.guild_id,
+            notification.channel_id,
+            notification.title,
+            notification.body
+        );
+        self.delivered.lock().unwrap().push(notification.clone());
+    }
+}
+
+/// Returns the backend selected with [`BACKEND_ENV_VAR`].
+pub fn backend_from_env() -> Box<dyn NotificationBackend> {
+    match std::env::var(BACKEND_ENV_VAR).as_deref() {
+        Ok("in-app") => Box::new(InAppBackend),
+        Ok("log") => Box::new(LogBackend::default()),
+        Ok("desktop") | Err(_) => default_backend(),
+        Ok(other) => {
+            tracing::warn!("unknown notification backend {}, using the default", other);
+            default_backend()
+        }
+    }
+}
+
+#[cfg(feature = "desktop-notifications")]
+fn default_backend() -> Box<dyn NotificationBackend> {
+    Box::new(DesktopBackend)
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+fn default_backend() -> Box<dyn NotificationBackend> {
+    Box::new(InAppBackend)
+}
+
+/// The notification center.
+pub struct Notifications {
+    backend: Box<dyn NotificationBackend>,
+    entries: VecDeque<Notification>,
+    next_id: u64,
+    /// Messages sent before this are old news, even if we only get them now.
+    since: NaiveDateTime,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self::new(backend_from_env())
+    }
+}
+
+impl Debug for Notifications {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("Notifications")
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl Notifications {
+    pub fn new(backend: Box<dyn NotificationBackend>) -> Self {
+        let now = UNIX_EPOCH.elapsed().unwrap();
+        Self {
+            backend,
+            entries: VecDeque::new(),
+            next_id: 0,
+            since: NaiveDateTime::from_timestamp(now.as_secs() as i64, now.subsec_nanos()),
+        }
+    }
+
+    pub fn set_backend(&mut self, backend: Box<dyn NotificationBackend>) {
+        self.backend = backend;
+    }
+
+    /// Returns whether a message sent at `timestamp` should notify the user.
+    pub fn is_new(&self, timestamp: NaiveDateTime) -> bool {
+        timestamp >= self.since
+    }
+
+    /// Adds a notification to the notification center and delivers it.
+    ///
+    /// Returns the ID given to the notification.
+    pub fn push(&mut self, mut notification: Notification) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        notification.id = id;
+
+        self.backend.deliver(&notification);
+
+        self.entries.push_front(notification);
+        self.entries.truncate(NOTIFICATIONS_LIMIT);
+
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Notification> {
+        self.entries
+            .iter()
+            .find(|notification| notification.id == id)
+    }
+
+    /// Returns all notifications, newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Notification> + '_ {
+        self.entries.iter()
+    }
+
+    pub fn unread(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|notification| !notification.read)
+            .count()
+    }
+
+    pub fn mark_all_read(&mut self) {
+        for notification in &mut self.entries {
+            notification.read = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::Channel, content::ContentStore, member::Member, InnerSession, PostProcessEvent,
+    };
+    use harmony_rust_sdk::api::{
+        chat::event::{Event, MessageSent},
+        harmonytypes::Message as HarmonyMessage,
+    };
+    use std::time::SystemTime;
+    use tempfile::TempDir;
+
+    const USER_ID: u64 = 1;
+    const OTHER_ID: u64 = 2;
+    const THIRD_ID: u64 = 3;
+    const GUILD_ID: u64 = 10;
+    const CHANNEL_ID: u64 = 20;
+
+    /// Returns a client in a guild with the given members, the backend it delivers
+    /// notifications to and the directory it stores its data in.
+    async fn client(members: &[u64]) -> (Client, LogBackend, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let session = InnerSession {
+            user_id: USER_ID,
+            session_token: String::from("token"),
+        };
+        let mut client = Client::new(
+            "https://example.org".parse().unwrap(),
+            Some(session),
+            Arc::new(ContentStore::in_dir(dir.path())),
+        )
+        .await
+        .unwrap();
+
+        let backend = LogBackend::default();
+        client.notifications = Notifications::new(Box::new(backend.clone()));
+        for (user_id, username) in &[(USER_ID, "alice"), (OTHER_ID, "bob")] {
+            client.members.insert(
+                *user_id,
+                Member {
+                    username: username.to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let guild = client.guilds.entry(GUILD_ID).or_default();
+        guild.name = String::from("guild");
+        guild.members.extend(members);
+        guild.channels.insert(
+            CHANNEL_ID,
+            Channel {
+                name: String::from("general"),
+                ..Default::default()
+            },
+        );
+
+        (client, backend, dir)
+    }
+
+    fn message(sender: u64, message_id: u64, content: &str) -> HarmonyMessage {
+        HarmonyMessage {
+            guild_id: GUILD_ID,
+            channel_id: CHANNEL_ID,
+            message_id,
+            author_id: sender,
+            content: content.to_string(),
+            created_at: Some(SystemTime::now().into()),
+            ..Default::default()
+        }
+    }
+
+    /// Hands a message to the client like the event stream does, and delivers its notification
+    /// like the frontends do.
+    fn receive(client: &mut Client, message: HarmonyMessage) {
+        let event = Event::SentMessage(MessageSent {
+            message: Some(message),
+            ..Default::default()
+        });
+        for post in client.process_event(event) {
+            if let PostProcessEvent::Notify(notification) = post {
+                client.notifications.push(*notification);
+            }
+        }
+    }
+
+    fn delivered(backend: &LogBackend) -> Vec<(u64, String)> {
+        backend
+            .delivered
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|notification| (notification.message_id, notification.title.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn mentions_notify() {
+        let (mut client, backend, _dir) = client(&[USER_ID, OTHER_ID, THIRD_ID]).await;
+
+        receive(&mut client, message(OTHER_ID, 100, "hello everyone"));
+        receive(&mut client, message(OTHER_ID, 101, "hey <@1>"));
+        receive(
+            &mut client,
+            message(OTHER_ID, 102, "what do you think @alice?"),
+        );
+
+        assert_eq!(
+            delivered(&backend),
+            vec![
+                (101, String::from("bob in #general (guild)")),
+                (102, String::from("bob in #general (guild)")),
+            ]
+        );
+        assert_eq!(client.notifications.unread(), 2);
+    }
+
+    #[tokio::test]
+    async fn own_messages_dont_notify() {
+        let (mut client, backend, _dir) = client(&[USER_ID, OTHER_ID]).await;
+
+        receive(
+            &mut client,
+            message(USER_ID, 100, "talking to myself, @alice"),
+        );
+
+        assert!(delivered(&backend).is_empty());
+    }
+
+    #[tokio::test]
+    async fn old_messages_dont_notify() {
+        let (mut client, backend, _dir) = client(&[USER_ID, OTHER_ID, THIRD_ID]).await;
+
+        let mut old = message(OTHER_ID, 100, "hey <@1>");
+        old.created_at = Some(SystemTime::UNIX_EPOCH.into());
+        receive(&mut client, old);
+
+        assert!(delivered(&backend).is_empty());
+    }
+
+    #[tokio::test]
+    async fn direct_messages_notify() {
+        let (mut client, backend, _dir) = client(&[USER_ID, OTHER_ID]).await;
+
+        receive(&mut client, message(OTHER_ID, 100, "are you there?"));
+
+        assert_eq!(
+            delivered(&backend),
+            vec![(100, String::from("bob (direct message)"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn muted_guilds_dont_notify() {
+        let (mut client, backend, _dir) = client(&[USER_ID, OTHER_ID, THIRD_ID]).await;
+        client.update_notification_rules(|rules| rules.guild_mut(GUILD_ID).mute(None));
+
+        receive(&mut client, message(OTHER_ID, 100, "hey <@1>"));
+
+        assert!(delivered(&backend).is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+//! Rules that decide which messages notify the user.
+//!
+//! Guilds and channels can be muted (optionally until some time) and can have their own
+//! [`NotifyLevel`], channel rules taking precedence over guild rules. Keywords and regexes
+//! chosen by the user highlight messages, and count as mentions when deciding whether to
+//! notify.
+
+use super::{error::ClientResult, Client};
+
+use chrono::NaiveDateTime;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+/// Which messages notify the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum NotifyLevel {
+    All,
+    /// Only messages that mention the user or contain a highlight.
+    Mentions,
+    Nothing,
+}
+
+impl NotifyLevel {
+    pub const ALL: [NotifyLevel; 3] = [
+        NotifyLevel::All,
+        NotifyLevel::Mentions,
+        NotifyLevel::Nothing,
+    ];
+}
+
+impl Default for NotifyLevel {
+    fn default() -> Self {
+        NotifyLevel::Mentions
+    }
+}
+
+impl Display for NotifyLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NotifyLevel::All => "All messages",
+            NotifyLevel::Mentions => "Mentions only",
+            NotifyLevel::Nothing => "Nothing",
+        })
+    }
+}
+
+/// Rules of a guild or a channel.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ScopeRules {
+    /// `None` to use the level of the guild, or the default level.
+    #[serde(default)]
+    pub level: Option<NotifyLevel>,
+    #[serde(default)]
+    pub muted: bool,
+    /// When the mute ends, `None` if it lasts until the user unmutes.
+    #[serde(default)]
+    pub muted_until: Option<NaiveDateTime>,
+}
+
+impl ScopeRules {
+    pub fn is_muted(&self, now: NaiveDateTime) -> bool {
+        self.muted && self.muted_until.map_or(true, |until| now < until)
+    }
+
+    pub fn mute(&mut self, until: Option<NaiveDateTime>) {
+        self.muted = true;
+        self.muted_until = until;
+    }
+
+    pub fn unmute(&mut self) {
+        self.muted = false;
+        self.muted_until = None;
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct NotificationRules {
+    #[serde(default)]
+    pub default_level: NotifyLevel,
+    #[serde(default)]
+    guilds: HashMap<u64, ScopeRules>,
+    /// Channel rules, keyed by guild ID and then channel ID.
+    #[serde(default)]
+    channels: HashMap<u64, HashMap<u64, ScopeRules>>,
+    /// Keywords that highlight a message. Keywords between slashes (`/like this/`) are regexes.
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(skip)]
+    highlights: Vec<Regex>,
+}
+
+impl NotificationRules {
+    pub fn guild(&self, guild_id: u64) -> Option<&ScopeRules> {
+        self.guilds.get(&guild_id)
+    }
+
+    pub fn guild_mut(&mut self, guild_id: u64) -> &mut ScopeRules {
+        self.guilds.entry(guild_id).or_default()
+    }
+
+    pub fn channel(&self, guild_id: u64, channel_id: u64) -> Option<&ScopeRules> {
+        self.channels
+            .get(&guild_id)
+            .map(|channels| channels.get(&channel_id))
+            .flatten()
+    }
+
+    pub fn channel_mut(&mut self, guild_id: u64, channel_id: u64) -> &mut ScopeRules {
+        self.channels
+            .entry(guild_id)
+            .or_default()
+            .entry(channel_id)
+            .or_default()
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// Replaces the highlight keywords.
+    ///
+    /// Invalid regexes are kept, so the user can fix them, but never match.
+    pub fn set_keywords(&mut self, keywords: Vec<String>) {
+        self.keywords = keywords;
+        self.compile_highlights();
+    }
+
+    fn compile_highlights(&mut self) {
+        self.highlights = self
+            .keywords
+            .iter()
+            .flat_map(|keyword| {
+                let pattern = match keyword
+                    .strip_prefix('/')
+                    .map(|rest| rest.strip_suffix('/'))
+                    .flatten()
+                {
+                    Some(regex) => regex.to_string(),
+                    None => format!(r"(?i)\b{}\b", regex::escape(keyword)),
+                };
+                Regex::new(&pattern)
+                    .map_err(|err| tracing::warn!("invalid highlight {}: {}", keyword, err))
+                    .ok()
+            })
+            .collect();
+    }
+
+    /// Returns whether a guild or channel is muted, a channel is muted if its guild is.
+    pub fn is_muted(&self, guild_id: u64, channel_id: Option<u64>, now: NaiveDateTime) -> bool {
+        self.guild(guild_id)
+            .map_or(false, |rules| rules.is_muted(now))
+            || channel_id
+                .map(|channel_id| self.channel(guild_id, channel_id))
+                .flatten()
+                .map_or(false, |rules| rules.is_muted(now))
+    }
+
+    /// Returns the level that applies to a channel, taking mutes into account.
+    pub fn level(&self, guild_id: u64, channel_id: u64, now: NaiveDateTime) -> NotifyLevel {
+        if self.is_muted(guild_id, Some(channel_id), now) {
+            return NotifyLevel::Nothing;
+        }
+
+        self.channel(guild_id, channel_id)
+            .map(|rules| rules.level)
+            .flatten()
+            .or_else(|| self.guild(guild_id).map(|rules| rules.level).flatten())
+            .unwrap_or(self.default_level)
+    }
+
+    /// Returns whether a message contains one of the highlight keywords.
+    pub fn is_highlight(&self, content: &str) -> bool {
+        self.highlights.iter().any(|regex| regex.is_match(content))
+    }
+
+    /// Returns whether a message sent by someone else should notify the user.
+    pub fn should_notify(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        content: &str,
+        is_mention: bool,
+        now: NaiveDateTime,
+    ) -> bool {
+        match self.level(guild_id, channel_id, now) {
+            NotifyLevel::All => true,
+            NotifyLevel::Mentions => is_mention || self.is_highlight(content),
+            NotifyLevel::Nothing => false,
+        }
+    }
+}
+
+/// Returns whether a message mentions a user, either by ID or by username.
+///
+/// A username mention has to stand on its own, so `@bob` doesn't mention `bo`.
+pub fn mentions_user(content: &str, user_id: u64, username: Option<&str>) -> bool {
+    content.contains(&format!("<@{}>", user_id))
+        || username.map_or(false, |username| {
+            let mention = format!("@{}", username);
+            !username.is_empty()
+                && content.match_indices(&mention).any(|(pos, _)| {
+                    let before = content[..pos].chars().next_back();
+                    let after = content[pos + mention.len()..].chars().next();
+                    !before.map_or(false, is_username_char)
+                        && !after.map_or(false, is_username_char)
+                })
+        })
+}
+
+/// Returns whether a character can be a part of a username, so a mention can't end before it.
+fn is_username_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+impl Client {
+    /// Changes the notification rules, which are saved with the cache.
+    pub fn update_notification_rules(&mut self, f: impl FnOnce(&mut NotificationRules)) {
+        f(&mut self.notification_rules);
+        self.rules_dirty = true;
+    }
+
+    pub(super) fn take_dirty_rules(&mut self) -> ClientResult<Option<(PathBuf, Vec<u8>)>> {
+        if !self.rules_dirty {
+            return Ok(None);
+        }
+
+        let data = serde_json::to_vec(&self.notification_rules)?;
+        self.rules_dirty = false;
+
+        Ok(Some((self.content_store.notification_rules_file(), data)))
+    }
+
+    pub(super) fn restore_rules(&mut self, mut rules: NotificationRules) {
+        rules.compile_highlights();
+        self.notification_rules = rules;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mentions_user;
+
+    #[test]
+    fn mentions_by_id() {
+        assert!(mentions_user("hey <@42>", 42, None));
+        assert!(!mentions_user("hey <@421>", 42, None));
+    }
+
+    #[test]
+    fn mentions_by_username() {
+        let mentions = |content| mentions_user(content, 42, Some("bob"));
+
+        assert!(mentions("@bob"));
+        assert!(mentions("hey @bob, look"));
+        assert!(mentions("(@bob)"));
+        assert!(mentions("@bobby and @bob"));
+        assert!(!mentions("hey @bobby"));
+        assert!(!mentions("@bob_smith"));
+        assert!(!mentions("mail bob@bob.org"));
+        assert!(!mentions("hey bob"));
+        assert!(!mentions_user("hey @", 42, Some("")));
+    }
+}
@@ -0,0 +1,280 @@
+//! Durable queue of message sends and edits that the homeserver hasn't acknowledged yet.
+//!
+//! Entries are stored in the local cache, so they survive restarts. Failed attempts are retried
+//! with a capped exponential backoff, and after [`MAX_ATTEMPTS`] the entry is marked as failed
+//! until the user retries or discards it.
+
+use super::{
+    error::ClientResult,
+    message::{Message, MessageId},
+    Client, Output,
+};
+
+use harmony_rust_sdk::client::api::chat::message::{send_message, update_message, UpdateMessage};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{future::Future, path::PathBuf, time::Duration};
+
+/// How many times an entry is attempted before it's marked as failed.
+pub const MAX_ATTEMPTS: u32 = 8;
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(32);
+
+pub type Outbox = IndexMap<u64, OutboxEntry>;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum OutboxAction {
+    Send(Message),
+    Edit {
+        message_id: u64,
+        new_content: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutboxEntry {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub action: OutboxAction,
+    /// How many attempts failed so far.
+    pub attempts: u32,
+    /// Whether we gave up retrying this entry.
+    pub failed: bool,
+}
+
+impl OutboxEntry {
+    pub fn new(guild_id: u64, channel_id: u64, action: OutboxAction) -> Self {
+        Self {
+            guild_id,
+            channel_id,
+            action,
+            attempts: 0,
+            failed: false,
+        }
+    }
+
+    /// ID of the message this entry shows up as in the channel.
+    pub fn message_id(&self) -> MessageId {
+        match &self.action {
+            OutboxAction::Send(message) => message.id,
+            OutboxAction::Edit { message_id, .. } => MessageId::Ack(*message_id),
+        }
+    }
+
+    /// How long to wait before the next attempt.
+    pub fn retry_after(&self) -> Duration {
+        if self.attempts == 0 {
+            Duration::from_secs(0)
+        } else {
+            BASE_RETRY_DELAY
+                .checked_mul(1 << (self.attempts - 1).min(16))
+                .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+        }
+    }
+}
+
+impl Client {
+    pub(super) fn queue_outbox_entry(
+        &mut self,
+        transaction_id: u64,
+        entry: OutboxEntry,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        self.outbox.insert(transaction_id, entry);
+        self.outbox_dirty = true;
+
+        self.outbox_attempt(transaction_id)
+    }
+
+    /// Returns the future that attempts sending an outbox entry, after waiting for its backoff.
+    pub fn outbox_attempt(
+        &self,
+        transaction_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let entry = self.outbox.get(&transaction_id)?.clone();
+        let retry_after = entry.retry_after();
+        let inner = self.inner_for_guild(entry.guild_id).clone();
+
+        Some(async move {
+            tokio::time::sleep(retry_after).await;
+
+            let OutboxEntry {
+                guild_id,
+                channel_id,
+                action,
+                ..
+            } = entry;
+
+            let result = match action {
+                OutboxAction::Send(message) => {
+                    let msg = message.into_send_request(guild_id, channel_id, transaction_id);
+
+                    send_message(&inner, msg)
+                        .await
+                        .map(|resp| Output::MessageSent {
+                            message_id: resp.message_id,
+                            transaction_id,
+                            channel_id,
+                            guild_id,
+                        })
+                }
+                OutboxAction::Edit {
+                    message_id,
+                    new_content,
+                } => update_message(
+                    &inner,
+                    UpdateMessage::new(guild_id, channel_id, message_id).new_content(new_content),
+                )
+                .await
+                .map(|_| Output::MessageEdited {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    transaction_id,
+                }),
+            };
+
+            result.unwrap_or_else(|err| {
+                tracing::error!("error occured when sending message: {}", err);
+                Output::OutboxAttemptFailed {
+                    transaction_id,
+                    err: Box::new(err.into()),
+                }
+            })
+        })
+    }
+
+    /// Returns the futures that resume sending entries restored from the cache.
+    pub fn resume_outbox(&self) -> Vec<impl Future<Output = Output> + Send + 'static> {
+        self.outbox
+            .iter()
+            .filter(|(_, entry)| !entry.failed)
+            .flat_map(|(transaction_id, _)| self.outbox_attempt(*transaction_id))
+            .collect()
+    }
+
+    /// Removes an entry that was acknowledged by the homeserver.
+    pub fn outbox_done(&mut self, transaction_id: u64) -> Option<OutboxEntry> {
+        let entry = self.outbox.shift_remove(&transaction_id);
+        if entry.is_some() {
+            self.outbox_dirty = true;
+        }
+        entry
+    }
+
+    /// Records a failed attempt.
+    ///
+    /// Returns the future of the next attempt, or `None` if we gave up and marked the entry as
+    /// failed.
+    pub fn outbox_attempt_failed(
+        &mut self,
+        transaction_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let entry = self.outbox.get_mut(&transaction_id)?;
+        entry.attempts += 1;
+        self.outbox_dirty = true;
+
+        if entry.attempts < MAX_ATTEMPTS {
+            self.outbox_attempt(transaction_id)
+        } else {
+            entry.failed = true;
+            self.set_outbox_message_failed(transaction_id, true);
+            None
+        }
+    }
+
+    /// Resets a failed entry and attempts sending it again.
+    pub fn retry_outbox(
+        &mut self,
+        transaction_id: u64,
+    ) -> Option<impl Future<Output = Output> + Send + 'static> {
+        let entry = self.outbox.get_mut(&transaction_id)?;
+        entry.attempts = 0;
+        entry.failed = false;
+        self.outbox_dirty = true;
+        self.set_outbox_message_failed(transaction_id, false);
+
+        self.outbox_attempt(transaction_id)
+    }
+
+    /// Throws an entry away, along with its pending changes in the channel.
+    pub fn discard_outbox(&mut self, transaction_id: u64) {
+        if let Some(entry) = self.outbox_done(transaction_id) {
+            let message_id = entry.message_id();
+            if let Some(channel) = self.get_channel(entry.guild_id, entry.channel_id) {
+                match entry.action {
+                    OutboxAction::Send(_) => {
+                        channel.messages.remove(message_id);
+                    }
+                    OutboxAction::Edit { .. } => {
+                        if let Some(message) = channel.messages.by_id_mut(message_id) {
+                            message.being_edited = None;
+                            message.failed_to_send = false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finds the outbox entry that a message in a channel is waiting for.
+    pub fn outbox_entry_for(&self, message_id: MessageId) -> Option<u64> {
+        self.outbox
+            .iter()
+            .find(|(_, entry)| entry.message_id() == message_id)
+            .map(|(transaction_id, _)| *transaction_id)
+    }
+
+    fn set_outbox_message_failed(&mut self, transaction_id: u64, failed: bool) {
+        let (guild_id, channel_id, message_id) = match self.outbox.get(&transaction_id) {
+            Some(entry) => (entry.guild_id, entry.channel_id, entry.message_id()),
+            None => return,
+        };
+
+        if let Some(message) = self
+            .get_channel(guild_id, channel_id)
+            .map(|channel| channel.messages.by_id_mut(message_id))
+            .flatten()
+        {
+            message.failed_to_send = failed;
+        }
+    }
+
+    /// Serializes the outbox if it changed since the last call.
+    pub(super) fn take_dirty_outbox(&mut self) -> ClientResult<Option<(PathBuf, Vec<u8>)>> {
+        if !self.outbox_dirty {
+            return Ok(None);
+        }
+
+        let entries = self.outbox.iter().collect::<Vec<_>>();
+        let data = serde_json::to_vec(&entries)?;
+        self.outbox_dirty = false;
+
+        Ok(Some((self.content_store.outbox_file(), data)))
+    }
+
+    /// Puts entries restored from the cache back into the outbox and their channels.
+    pub(super) fn restore_outbox(&mut self, entries: Vec<(u64, OutboxEntry)>) {
+        for (transaction_id, entry) in entries {
+            let failed = entry.failed;
+            let message_id = entry.message_id();
+
+            if let Some(channel) = self.get_channel(entry.guild_id, entry.channel_id) {
+                match &entry.action {
+                    OutboxAction::Send(message) => {
+                        let mut message = message.clone();
+                        message.failed_to_send = failed;
+                        channel.merge_messages(vec![message]);
+                    }
+                    OutboxAction::Edit { new_content, .. } => {
+                        if let Some(message) = channel.messages.by_id_mut(message_id) {
+                            message.being_edited = Some(new_content.clone());
+                            message.failed_to_send = failed;
+                        }
+                    }
+                }
+            }
+
+            self.outbox.insert(transaction_id, entry);
+        }
+    }
+}
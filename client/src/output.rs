@@ -0,0 +1,144 @@
+//! Results of the asynchronous operations of [`Client`].
+//!
+//! Methods that talk to a homeserver change the state of the client right away and return a
+//! future, which resolves to an [`Output`]. Frontends run the future however they like and hand
+//! the output back to the matching method of [`Client`].
+//!
+//! [`Client`]: super::Client
+
+use super::{
+    error::ClientError,
+    event_stream::{EventStreamUpdates, StreamUpdate},
+    federation::ForeignHomeserver,
+    message::Message,
+    Url,
+};
+
+use harmony_rust_sdk::api::{chat::event::Event, harmonytypes::Message as HarmonyMessage};
+use std::{future::Future, pin::Pin};
+
+/// A future of the client, boxed so that different operations can be returned together.
+pub type OutputFuture = Pin<Box<dyn Future<Output = Output> + Send + 'static>>;
+
+#[derive(Debug)]
+pub enum Output {
+    /// The homeserver acknowledged a message we sent, see [`Client::outbox_done`].
+    ///
+    /// [`Client::outbox_done`]: super::Client::outbox_done
+    MessageSent {
+        message_id: u64,
+        transaction_id: u64,
+        guild_id: u64,
+        channel_id: u64,
+    },
+    /// The homeserver acknowledged an edit we made, see [`Client::outbox_done`].
+    ///
+    /// [`Client::outbox_done`]: super::Client::outbox_done
+    MessageEdited {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        transaction_id: u64,
+    },
+    /// Sending an outbox entry failed, see [`Client::outbox_attempt_failed`].
+    ///
+    /// [`Client::outbox_attempt_failed`]: super::Client::outbox_attempt_failed
+    OutboxAttemptFailed {
+        transaction_id: u64,
+        err: Box<ClientError>,
+    },
+    MessagePinned {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        pinned: bool,
+    },
+    /// Pinned messages of a channel, see [`Client::set_pinned_messages`].
+    ///
+    /// [`Client::set_pinned_messages`]: super::Client::set_pinned_messages
+    PinnedMessages {
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<Message>,
+    },
+    /// Deleting a message failed, see [`Client::restore_message`].
+    ///
+    /// [`Client::restore_message`]: super::Client::restore_message
+    MessageDeleteFailed {
+        guild_id: u64,
+        channel_id: u64,
+        message: Box<Message>,
+        err: Box<ClientError>,
+    },
+    /// The homeserver rejected adding or removing one of our reactions, which should be
+    /// reverted.
+    ReactionFailed {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        emote: String,
+        added: bool,
+        err: Box<ClientError>,
+    },
+    /// Fetching a guild, or the latest messages of a channel if `channel_id` is given, failed.
+    /// See [`Client::fetch_failed`].
+    ///
+    /// [`Client::fetch_failed`]: super::Client::fetch_failed
+    FetchFailed {
+        guild_id: u64,
+        channel_id: Option<u64>,
+        err: Box<ClientError>,
+    },
+    /// Older messages of a channel, see [`Client::process_get_message_history_response`].
+    ///
+    /// [`Client::process_get_message_history_response`]:
+    /// super::Client::process_get_message_history_response
+    History {
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<HarmonyMessage>,
+        reached_top: bool,
+    },
+    /// Messages that were missed in a channel, see [`Client::process_backfill`].
+    ///
+    /// [`Client::process_backfill`]: super::Client::process_backfill
+    Backfill {
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<HarmonyMessage>,
+        /// The gaps the channel has once `messages` are merged, see [`Channel::gaps`].
+        ///
+        /// [`Channel::gaps`]: super::channel::Channel::gaps
+        gaps: Vec<u64>,
+        /// Whether `messages` reach back to the first message of the channel.
+        reached_top: bool,
+    },
+    /// Events to apply with [`Client::process_event`].
+    ///
+    /// [`Client::process_event`]: super::Client::process_event
+    Events(Vec<Event>),
+    /// Something happened on the event stream of our homeserver or of a foreign homeserver, or
+    /// it was replaced if there is no update. Wait for the next update with
+    /// [`EventStreamUpdates::next`].
+    EventStream {
+        updates: Box<EventStreamUpdates>,
+        update: Option<StreamUpdate>,
+    },
+    /// We logged into a foreign homeserver, see [`Client::federation_complete`].
+    ///
+    /// [`Client::federation_complete`]: super::Client::federation_complete
+    Federated {
+        homeserver: Url,
+        foreign: Box<ForeignHomeserver>,
+    },
+    /// Logging into a foreign homeserver failed, see [`Client::federation_failed`].
+    ///
+    /// [`Client::federation_failed`]: super::Client::federation_failed
+    FederationFailed {
+        homeserver: Url,
+        err: Box<ClientError>,
+    },
+    Error(Box<ClientError>),
+    /// The operation succeeded and there is nothing else to do.
+    Done,
+}
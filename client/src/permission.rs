@@ -0,0 +1,96 @@
+//! Permissions of the current user that the UI depends on.
+
+use super::{Client, Output};
+
+use futures::{stream, StreamExt};
+use harmony_rust_sdk::{
+    api::chat::event::{Event, PermissionUpdated},
+    client::api::chat::permissions::{
+        query_has_permission, QueryPermissions, QueryPermissionsSelfBuilder,
+    },
+};
+use std::future::Future;
+
+pub const SEND_MESSAGES: &str = "messages.send";
+pub const DELETE_MESSAGES: &str = "messages.manage.delete";
+pub const PIN_MESSAGES: &str = "messages.pins.add";
+pub const CREATE_CHANNELS: &str = "channels.manage.create";
+
+/// Permissions that are queried for a whole guild.
+pub const GUILD_PERMISSIONS: [&str; 1] = [CREATE_CHANNELS];
+/// Permissions that are queried for every channel.
+pub const CHANNEL_PERMISSIONS: [&str; 3] = [SEND_MESSAGES, DELETE_MESSAGES, PIN_MESSAGES];
+
+/// How many permission queries are in flight at once.
+const QUERIES_CONCURRENCY: usize = 8;
+
+impl Client {
+    /// Returns the future that queries our permissions in a guild, and in the given channels
+    /// of that guild.
+    ///
+    /// The results are returned as [`Event::PermissionUpdated`] events. Queries that fail are
+    /// left out, so those permissions stay unknown, and an error is only returned if every
+    /// query failed.
+    pub fn fetch_permissions(
+        &self,
+        guild_id: u64,
+        channel_ids: Vec<u64>,
+    ) -> impl Future<Output = Output> + Send + 'static {
+        let inner = self.inner_for_guild(guild_id).clone();
+
+        async move {
+            let queries = GUILD_PERMISSIONS.iter().map(|query| (0, *query)).chain(
+                channel_ids.into_iter().flat_map(|channel_id| {
+                    CHANNEL_PERMISSIONS
+                        .iter()
+                        .map(move |query| (channel_id, *query))
+                }),
+            );
+
+            let inner = &inner;
+            let mut results = stream::iter(queries)
+                .map(|(channel_id, query)| async move {
+                    let mut request = QueryPermissions::new(guild_id, query.to_string());
+                    if channel_id != 0 {
+                        request = request.channel_id(channel_id);
+                    }
+                    (
+                        channel_id,
+                        query,
+                        query_has_permission(inner, request).await,
+                    )
+                })
+                .buffer_unordered(QUERIES_CONCURRENCY);
+
+            // Permissions we couldn't query stay unknown
+            let mut events = Vec::new();
+            let mut last_err = None;
+            while let Some((channel_id, query, result)) = results.next().await {
+                match result {
+                    Ok(response) => events.push(Event::PermissionUpdated(PermissionUpdated {
+                        guild_id,
+                        channel_id,
+                        query: query.to_string(),
+                        ok: response.ok,
+                    })),
+                    Err(err) => {
+                        tracing::warn!(
+                            "couldn't query {} in guild {} channel {}: {}",
+                            query,
+                            guild_id,
+                            channel_id,
+                            err
+                        );
+                        last_err = Some(err);
+                    }
+                }
+            }
+
+            if let (true, Some(err)) = (events.is_empty(), last_err) {
+                return Output::Error(Box::new(err.into()));
+            }
+
+            Output::Events(events)
+        }
+    }
+}
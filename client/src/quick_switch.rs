@@ -0,0 +1,82 @@
+//! Finding guilds and channels by name, to switch to them quickly.
+//!
+//! A term starting with `*` only matches guilds, and one starting with `#` only matches
+//! channels. Other terms match both, guilds first.
+
+use super::guild::Guilds;
+
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+
+#[derive(Debug, Clone)]
+pub enum SearchResult {
+    Guild {
+        id: u64,
+        name: String,
+    },
+    Channel {
+        guild_id: u64,
+        id: u64,
+        name: String,
+    },
+}
+
+fn search_guilds(guilds: &Guilds, pattern: &str) -> Vec<SearchResult> {
+    let matcher = SkimMatcherV2::default();
+    let mut results = guilds
+        .iter()
+        .map(|(id, g)| (*id, g.name.as_str()))
+        .flat_map(|(id, name)| Some((matcher.fuzzy(name, pattern, false)?.0, id, name)))
+        .collect::<Vec<_>>();
+    results.sort_unstable_by_key(|(score, _, _)| *score);
+    results
+        .into_iter()
+        .rev()
+        .map(|(_, id, name)| SearchResult::Guild {
+            id,
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+fn search_channels(guilds: &Guilds, pattern: &str) -> Vec<SearchResult> {
+    let matcher = SkimMatcherV2::default();
+    let mut results = guilds
+        .iter()
+        .flat_map(|(gid, g)| {
+            g.channels
+                .iter()
+                .map(move |(cid, c)| (*gid, *cid, c.name.as_str()))
+                .flat_map(|(gid, cid, name)| {
+                    Some((matcher.fuzzy(name, pattern, false)?.0, gid, cid, name))
+                })
+        })
+        .collect::<Vec<_>>();
+    results.sort_unstable_by_key(|(score, _, _, _)| *score);
+    results
+        .into_iter()
+        .rev()
+        .map(|(_, gid, cid, name)| SearchResult::Channel {
+            guild_id: gid,
+            id: cid,
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+/// Returns the guilds and channels that match a term, best matches first.
+///
+/// Returns nothing for an empty term, frontends show recently visited channels instead.
+pub fn search(guilds: &Guilds, term: &str) -> Vec<SearchResult> {
+    let term_trimmed = term.trim();
+    if term_trimmed.is_empty() {
+        Vec::new()
+    } else if let Some(pattern) = term.strip_prefix('*').map(str::trim) {
+        search_guilds(guilds, pattern)
+    } else if let Some(pattern) = term.strip_prefix('#').map(str::trim) {
+        search_channels(guilds, pattern)
+    } else {
+        let mut results = search_guilds(guilds, term_trimmed);
+        results.append(&mut search_channels(guilds, term_trimmed));
+        results
+    }
+}
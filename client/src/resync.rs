@@ -0,0 +1,159 @@
+//! Catching up with what happened while we weren't connected.
+//!
+//! Events sent while an event stream was broken are lost, so every time it connects again the
+//! guilds and the channels we loaded from its homeserver are fetched again, along with the guild
+//! list if it's the stream of our homeserver. Guilds only fetch their lists of channels, members
+//! and roles, and the roles of members that joined meanwhile, see [`Client::fetch_guild`].
+//! Channels only fetch what's newer than the newest message they have and their gaps, which are
+//! merged into the channels in order, see [`Client::process_backfill`].
+
+use super::{
+    channel::Channel,
+    message::{harmony_messages_to_ui_messages, MessageId},
+    output::OutputFuture,
+    post_thumbnails, Client, PostProcessEvent, Url,
+};
+
+use ahash::AHashSet;
+use harmony_rust_sdk::api::harmonytypes::Message as HarmonyMessage;
+
+impl Client {
+    /// Returns the futures that fetch everything hosted on a homeserver that may have changed
+    /// while we weren't connected to it, and what we couldn't fetch from it before.
+    ///
+    /// `homeserver` is a foreign homeserver, or `None` for our homeserver.
+    pub fn resync(&mut self, homeserver: Option<&Url>) -> Vec<OutputFuture> {
+        let mut futures: Vec<OutputFuture> = Vec::new();
+        if homeserver.is_none() {
+            if let Some(future) = self.fetch_guild_list() {
+                futures.push(Box::pin(future));
+            }
+        }
+
+        let guild_ids = self
+            .guilds
+            .iter()
+            .filter(|(_, guild)| guild.homeserver.as_ref() == homeserver)
+            .map(|(guild_id, _)| *guild_id)
+            .collect::<Vec<_>>();
+        let (failed, other) = std::mem::take(&mut self.failed_fetches)
+            .into_iter()
+            .partition::<AHashSet<_>, _>(|(guild_id, _)| guild_ids.contains(guild_id));
+        self.failed_fetches = other;
+
+        for guild_id in guild_ids {
+            let guild = match self.get_guild(guild_id) {
+                Some(guild) => guild,
+                None => continue,
+            };
+            // Guilds and channels that weren't loaded yet will be fetched when they are opened
+            let was_fetched =
+                std::mem::replace(&mut guild.fetched, false) || failed.contains(&(guild_id, 0));
+            let channel_ids = guild
+                .channels
+                .iter_mut()
+                .filter(|(channel_id, channel)| {
+                    channel.fetched
+                        || !channel.gaps.is_empty()
+                        || failed.contains(&(guild_id, **channel_id))
+                })
+                .map(|(channel_id, channel)| {
+                    channel.fetched = false;
+                    *channel_id
+                })
+                .collect::<Vec<_>>();
+
+            if was_fetched {
+                if let Some(future) = self.fetch_guild(guild_id) {
+                    futures.push(Box::pin(future));
+                }
+            }
+            for channel_id in channel_ids {
+                if let Some(future) = self.fetch_channel(guild_id, channel_id) {
+                    futures.push(Box::pin(future));
+                }
+            }
+        }
+
+        futures
+    }
+
+    /// Merges messages fetched by [`Client::fetch_channel`] into their channel, and replaces its
+    /// gaps with `gaps`.
+    ///
+    /// Nothing we had is dropped, messages that may be missing are fetched later from the gaps.
+    /// The channel stays not fetched while it has gaps, so they are fetched the next time it's
+    /// opened or we reconnect.
+    pub fn process_backfill(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<HarmonyMessage>,
+        mut gaps: Vec<u64>,
+        reached_top: bool,
+    ) -> Vec<PostProcessEvent> {
+        let mut post = Vec::new();
+        let messages = harmony_messages_to_ui_messages(messages);
+
+        for message in &messages {
+            post_thumbnails(&mut post, guild_id, message);
+            self.search_index.insert(guild_id, channel_id, message);
+        }
+
+        let channel = match self
+            .guilds
+            .get(&guild_id)
+            .map(|guild| guild.channels.get(&channel_id))
+            .flatten()
+        {
+            Some(channel) => channel,
+            None => return post,
+        };
+        // Messages we never saw from others weren't read yet
+        let (unread, mentions) = messages
+            .iter()
+            .filter(|message| {
+                !channel.messages.contains(message.id) && self.user_id != Some(message.sender)
+            })
+            .fold((0, 0), |(unread, mentions), message| {
+                (
+                    unread + 1,
+                    mentions + self.mentions_us(&message.content) as usize,
+                )
+            });
+
+        let channel = match self.get_channel(guild_id, channel_id) {
+            Some(channel) => channel,
+            None => return post,
+        };
+        let newest = |channel: &Channel| {
+            channel
+                .messages
+                .iter()
+                .rev()
+                .find_map(|message| message.id.id())
+        };
+        let newest_before = newest(channel);
+        channel.merge_messages(messages);
+        if newest(channel) != newest_before {
+            post.push(PostProcessEvent::GoToFirstMsgOnChannel(channel_id));
+        }
+
+        // Messages deleted while they were fetched can't be connected to anymore
+        gaps.retain(|gap| channel.messages.contains(MessageId::Ack(*gap)));
+        let state_changed =
+            unread > 0 || gaps != channel.gaps || (reached_top && !channel.reached_top);
+        channel.fetched = gaps.is_empty();
+        channel.gaps = gaps;
+        channel.reached_top |= reached_top;
+        channel.unread += unread;
+        channel.mentions += mentions;
+
+        self.dirty_channels.insert((guild_id, channel_id));
+        if state_changed {
+            self.state_dirty = true;
+        }
+
+        post
+    }
+}
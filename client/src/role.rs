@@ -0,0 +1,28 @@
+use super::color::Color;
+
+use indexmap::IndexMap;
+
+/// Roles of a guild, ordered from the highest to the lowest.
+pub type Roles = IndexMap<u64, Role>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Role {
+    pub name: String,
+    /// Color of the names of members with this role, `None` if the role isn't colored.
+    pub color: Option<Color>,
+    /// Whether members with this role are shown separately in the member list.
+    pub hoist: bool,
+    /// Whether this role can be mentioned.
+    pub pingable: bool,
+}
+
+/// Converts a color sent by the homeserver (`0xRRGGBB`).
+///
+/// Returns `None` for `0`, which is what the homeserver sends for roles without a color.
+pub fn color_from_i32(color: i32) -> Option<Color> {
+    if color == 0 {
+        return None;
+    }
+
+    Some(Color::from_rgb_i32(color))
+}
@@ -0,0 +1,588 @@
+//! Full-text search over the messages we have seen.
+//!
+//! Every acknowledged message that goes through [`Client`] is split into lowercase words, and
+//! each word maps to the messages that contain it. A query matches messages that have all of
+//! its words, the last word also matching as a prefix so results show up while typing.
+
+use super::{
+    message::{Message, MessageId},
+    Client,
+};
+
+use ahash::{AHashMap, AHashSet};
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// How many results a search returns at most.
+pub const SEARCH_RESULTS_LIMIT: usize = 50;
+/// How many characters of a message are shown in a search result.
+const SNIPPET_LENGTH: usize = 120;
+
+/// Identifies a message by its guild, channel and message IDs.
+type MessageKey = (u64, u64, u64);
+
+#[derive(Debug)]
+struct IndexedMessage {
+    sender: u64,
+    timestamp: NaiveDateTime,
+    has_attachment: bool,
+    content: String,
+    words: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Only messages from members whose name contains this.
+    pub sender: String,
+    pub guild_id: Option<u64>,
+    pub channel_id: Option<u64>,
+    /// Only messages sent on or after this day.
+    pub from: Option<NaiveDate>,
+    /// Only messages sent on or before this day.
+    pub to: Option<NaiveDate>,
+    pub has_attachment: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub sender: u64,
+    pub timestamp: NaiveDateTime,
+    pub snippet: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    messages: AHashMap<MessageKey, IndexedMessage>,
+    words: AHashMap<String, AHashSet<MessageKey>>,
+}
+
+/// Splits text into the lowercase words that are indexed.
+fn words(text: &str) -> Vec<String> {
+    let mut words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+impl SearchIndex {
+    /// Adds a message to the index, replacing what was indexed for it before.
+    ///
+    /// Messages that weren't acknowledged by the homeserver yet are ignored.
+    pub fn insert(&mut self, guild_id: u64, channel_id: u64, message: &Message) {
+        let message_id = match message.id.id() {
+            Some(id) => id,
+            None => return,
+        };
+        let key = (guild_id, channel_id, message_id);
+        self.remove(guild_id, channel_id, message_id);
+
+        let words = words(&message.content);
+        for word in &words {
+            self.words.entry(word.clone()).or_default().insert(key);
+        }
+        self.messages.insert(
+            key,
+            IndexedMessage {
+                sender: message.sender,
+                timestamp: message.timestamp,
+                has_attachment: !message.attachments.is_empty(),
+                content: message.content.clone(),
+                words,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, guild_id: u64, channel_id: u64, message_id: u64) {
+        let key = (guild_id, channel_id, message_id);
+        if let Some(indexed) = self.messages.remove(&key) {
+            for word in indexed.words {
+                if let Some(keys) = self.words.get_mut(&word) {
+                    keys.remove(&key);
+                    if keys.is_empty() {
+                        self.words.remove(&word);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes every message of a channel from the index.
+    pub fn remove_channel(&mut self, guild_id: u64, channel_id: u64) {
+        self.remove_where(|(guild, channel, _)| *guild == guild_id && *channel == channel_id);
+    }
+
+    /// Removes every message of a guild from the index.
+    pub fn remove_guild(&mut self, guild_id: u64) {
+        self.remove_where(|(guild, _, _)| *guild == guild_id);
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&MessageKey) -> bool) {
+        let keys = self
+            .messages
+            .keys()
+            .filter(|key| predicate(key))
+            .copied()
+            .collect::<Vec<_>>();
+        for (guild_id, channel_id, message_id) in keys {
+            self.remove(guild_id, channel_id, message_id);
+        }
+    }
+
+    /// Returns the messages that contain all words of `text`, the last word matching as a
+    /// prefix. Returns every message if `text` has no words.
+    fn matching(&self, text: &str) -> AHashSet<MessageKey> {
+        let query_words = words(text);
+        let (last, rest) = match text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .last()
+        {
+            Some(last) => {
+                let last = last.to_lowercase();
+                let rest = query_words
+                    .into_iter()
+                    .filter(|word| *word != last)
+                    .collect::<Vec<_>>();
+                (last, rest)
+            }
+            None => return self.messages.keys().copied().collect(),
+        };
+
+        let mut matching = self
+            .words
+            .iter()
+            .filter(|(word, _)| word.starts_with(&last))
+            .flat_map(|(_, keys)| keys.iter().copied())
+            .collect::<AHashSet<_>>();
+
+        for word in rest {
+            match self.words.get(&word) {
+                Some(keys) => matching.retain(|key| keys.contains(key)),
+                None => return AHashSet::new(),
+            }
+        }
+
+        matching
+    }
+}
+
+impl Client {
+    /// Indexes a message again after it was changed.
+    pub(super) fn reindex_message(&mut self, guild_id: u64, channel_id: u64, message_id: u64) {
+        if let Some(message) = self
+            .guilds
+            .get(&guild_id)
+            .map(|guild| guild.channels.get(&channel_id))
+            .flatten()
+            .map(|channel| channel.messages.by_id(MessageId::Ack(message_id)))
+            .flatten()
+        {
+            self.search_index.insert(guild_id, channel_id, message);
+        }
+    }
+
+    /// Searches the messages we have seen, newest first.
+    pub fn search(&self, query: &SearchQuery) -> Vec<SearchResult> {
+        let sender = query.sender.trim().to_lowercase();
+        let sender_matches = |user_id: u64| {
+            sender.is_empty()
+                || self.members.get(&user_id).map_or(false, |member| {
+                    member.username.to_lowercase().contains(&sender)
+                })
+        };
+
+        let mut results = self
+            .search_index
+            .matching(&query.text)
+            .into_iter()
+            .filter(|(guild_id, channel_id, _)| {
+                query.guild_id.map_or(true, |id| id == *guild_id)
+                    && query.channel_id.map_or(true, |id| id == *channel_id)
+            })
+            .flat_map(|key| Some((key, self.search_index.messages.get(&key)?)))
+            .filter(|(_, indexed)| {
+                let date = indexed.timestamp.date();
+                (!query.has_attachment || indexed.has_attachment)
+                    && query.from.map_or(true, |from| date >= from)
+                    && query.to.map_or(true, |to| date <= to)
+                    && sender_matches(indexed.sender)
+            })
+            .collect::<Vec<_>>();
+
+        results.sort_unstable_by(|(_, a), (_, b)| b.timestamp.cmp(&a.timestamp));
+
+        results
+            .into_iter()
+            .take(SEARCH_RESULTS_LIMIT)
+            .map(
+                |((guild_id, channel_id, message_id), indexed)| SearchResult {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    sender: indexed.sender,
+                    timestamp: indexed.timestamp,
+                    snippet: if indexed.content.chars().count() > SNIPPET_LENGTH {
+                        let mut snippet = indexed
+                            .content
+                            .chars()
+                            .take(SNIPPET_LENGTH)
+                            .collect::<String>();
+                        snippet.push('…');
+                        snippet
+                    } else {
+                        indexed.content.clone()
+                    },
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::Channel,
+        content::{ContentStore, ContentType},
+        member::Member,
+        message::Attachment,
+        InnerSession,
+    };
+    use harmony_rust_sdk::{
+        api::chat::event::{ChannelDeleted, Event, GuildDeleted},
+        client::api::rest::FileId,
+    };
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    const ALICE: u64 = 1;
+    const BOB: u64 = 2;
+    const GUILD_ID: u64 = 10;
+    const OTHER_GUILD_ID: u64 = 11;
+    const CHANNEL_ID: u64 = 20;
+    const OTHER_CHANNEL_ID: u64 = 21;
+
+    /// Returns a client with two members, and the directory it stores its data in.
+    async fn client() -> (Client, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let session = InnerSession {
+            user_id: ALICE,
+            session_token: String::from("token"),
+        };
+        let mut client = Client::new(
+            "https://example.org".parse().unwrap(),
+            Some(session),
+            Arc::new(ContentStore::in_dir(dir.path())),
+        )
+        .await
+        .unwrap();
+
+        for (user_id, username) in &[(ALICE, "alice"), (BOB, "bob")] {
+            client.members.insert(
+                *user_id,
+                Member {
+                    username: username.to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        (client, dir)
+    }
+
+    fn message(id: u64, sender: u64, day: u32, content: &str) -> Message {
+        Message {
+            id: MessageId::Ack(id),
+            content: content.to_string(),
+            sender,
+            timestamp: NaiveDate::from_ymd(2021, 3, day).and_hms(12, 0, 0),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a message to a channel and to the index, like receiving it does.
+    fn add(client: &mut Client, guild_id: u64, channel_id: u64, message: Message) {
+        client.search_index.insert(guild_id, channel_id, &message);
+        client
+            .guilds
+            .entry(guild_id)
+            .or_default()
+            .channels
+            .entry(channel_id)
+            .or_insert_with(Channel::default)
+            .merge_messages(vec![message]);
+    }
+
+    fn search(client: &Client, query: SearchQuery) -> Vec<u64> {
+        client
+            .search(&query)
+            .into_iter()
+            .map(|result| result.message_id)
+            .collect()
+    }
+
+    fn text(text: &str) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn words_and_prefix() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "Hello world"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(2, BOB, 2, "hello there, WORLDS"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(3, BOB, 3, "goodbye world"),
+        );
+
+        // Newest first, and words match regardless of case
+        assert_eq!(search(&client, text("HELLO")), vec![2, 1]);
+        // The last word matches as a prefix, the others only as whole words
+        assert_eq!(search(&client, text("hello wor")), vec![2, 1]);
+        assert_eq!(search(&client, text("hell world")), Vec::<u64>::new());
+        assert_eq!(search(&client, text("nothing")), Vec::<u64>::new());
+        // No words matches everything
+        assert_eq!(search(&client, text("")), vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn sender_filter() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "hello"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(2, BOB, 2, "hello"),
+        );
+
+        let query = SearchQuery {
+            text: "hello".to_string(),
+            sender: " BO ".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(search(&client, query), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn guild_and_channel_filters() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "hello"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            OTHER_CHANNEL_ID,
+            message(2, ALICE, 2, "hello"),
+        );
+        add(
+            &mut client,
+            OTHER_GUILD_ID,
+            CHANNEL_ID,
+            message(3, ALICE, 3, "hello"),
+        );
+
+        let query = SearchQuery {
+            guild_id: Some(GUILD_ID),
+            ..text("hello")
+        };
+        assert_eq!(search(&client, query), vec![2, 1]);
+
+        let query = SearchQuery {
+            guild_id: Some(GUILD_ID),
+            channel_id: Some(CHANNEL_ID),
+            ..text("hello")
+        };
+        assert_eq!(search(&client, query), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn date_filters() {
+        let (mut client, _dir) = client().await;
+        for day in 1..=5 {
+            add(
+                &mut client,
+                GUILD_ID,
+                CHANNEL_ID,
+                message(day as u64, ALICE, day, "hello"),
+            );
+        }
+
+        // Both ends are inclusive
+        let query = SearchQuery {
+            from: Some(NaiveDate::from_ymd(2021, 3, 2)),
+            to: Some(NaiveDate::from_ymd(2021, 3, 4)),
+            ..text("hello")
+        };
+        assert_eq!(search(&client, query), vec![4, 3, 2]);
+
+        let query = SearchQuery {
+            from: Some(NaiveDate::from_ymd(2021, 3, 4)),
+            ..text("hello")
+        };
+        assert_eq!(search(&client, query), vec![5, 4]);
+    }
+
+    #[tokio::test]
+    async fn attachment_filter() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "look"),
+        );
+        let mut with_attachment = message(2, ALICE, 2, "look");
+        with_attachment.attachments.push(Attachment {
+            kind: ContentType::Image,
+            name: "cat.png".to_string(),
+            id: FileId::Id("cat".to_string()),
+            size: 1024,
+        });
+        add(&mut client, GUILD_ID, CHANNEL_ID, with_attachment);
+
+        let query = SearchQuery {
+            has_attachment: true,
+            ..text("look")
+        };
+        assert_eq!(search(&client, query), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn results_limit() {
+        let (mut client, _dir) = client().await;
+        let count = SEARCH_RESULTS_LIMIT as u64 + 10;
+        for id in 1..=count {
+            add(
+                &mut client,
+                GUILD_ID,
+                CHANNEL_ID,
+                message(id, ALICE, 1, "hello"),
+            );
+        }
+
+        let results = search(&client, text("hello"));
+        assert_eq!(results.len(), SEARCH_RESULTS_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn edits_replace_indexed_words() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "hello"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "goodbye"),
+        );
+
+        assert_eq!(search(&client, text("hello")), Vec::<u64>::new());
+        assert_eq!(search(&client, text("goodbye")), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn deleted_messages_are_removed() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "hello"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(2, ALICE, 2, "hello"),
+        );
+
+        assert!(client.delete_msg(GUILD_ID, CHANNEL_ID, 1).is_some());
+        assert_eq!(search(&client, text("hello")), vec![2]);
+        assert_eq!(client.search_index.words["hello"].len(), 1);
+
+        // Deleting failed, so the message is back
+        client.restore_message(GUILD_ID, CHANNEL_ID, message(1, ALICE, 1, "hello"));
+        assert_eq!(search(&client, text("hello")), vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn deleted_channels_are_removed() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "hello"),
+        );
+        add(
+            &mut client,
+            GUILD_ID,
+            OTHER_CHANNEL_ID,
+            message(2, ALICE, 2, "hello"),
+        );
+
+        client.process_event(Event::DeletedChannel(ChannelDeleted {
+            guild_id: GUILD_ID,
+            channel_id: CHANNEL_ID,
+        }));
+        assert_eq!(search(&client, text("hello")), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn removed_guilds_are_removed() {
+        let (mut client, _dir) = client().await;
+        add(
+            &mut client,
+            GUILD_ID,
+            CHANNEL_ID,
+            message(1, ALICE, 1, "hello"),
+        );
+        add(
+            &mut client,
+            OTHER_GUILD_ID,
+            CHANNEL_ID,
+            message(2, ALICE, 2, "goodbye"),
+        );
+
+        client.process_event(Event::DeletedGuild(GuildDeleted { guild_id: GUILD_ID }));
+        assert_eq!(search(&client, text("")), vec![2]);
+        // Words of the removed messages aren't kept around
+        assert!(client.search_index.words.get("hello").is_none());
+    }
+}
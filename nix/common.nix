@@ -27,7 +27,7 @@ with pkgs; {
   # Deps that certain crates need
   crateDeps =
     {
-      buildInputs = [ protobuf gtk3 atk cairo pango gdk_pixbuf glib expat freetype fontconfig x11 xorg.libxcb ];
+      buildInputs = [ protobuf gtk3 atk cairo pango gdk_pixbuf glib expat freetype fontconfig x11 xorg.libxcb dbus ];
       nativeBuildInputs = [ pkg-config cmake python3 ];
     };
 
@@ -6,7 +6,7 @@ use ui::{screen::ScreenManager, style::DEF_SIZE};
 use iced::{Application, Settings};
 use tracing_subscriber::EnvFilter;
 
-pub mod client;
+pub use crust_client as client;
 pub mod ui;
 
 #[tokio::main]
@@ -30,6 +30,8 @@ async fn main() {
     settings.antialiasing = false;
     settings.default_font = Some(include_bytes!("NotoSans-Regular.ttf"));
     settings.default_text_size = DEF_SIZE;
+    // The local cache is saved before exiting, see `Message::CloseRequested`
+    settings.exit_on_close_request = false;
 
     ScreenManager::run(settings).unwrap();
 }
@@ -1,13 +1,36 @@
 use crate::{
-    client::{channel::Channels, content::ThumbnailCache, guild::Guilds},
+    client::{channel::Channels, guild::Guilds},
     label,
     ui::{
         component::*,
         style::{Theme, DEF_SIZE, PADDING, SPACING},
+        thumbnail::ThumbnailCache,
     },
 };
 
 use iced::{tooltip::Position, Tooltip};
+use iced_aw::{style::badge, Badge};
+
+/// Builds the badge showing unread messages, or mentions if there are any.
+///
+/// Returns `None` if everything was read.
+fn unread_badge<'a, Message: 'a>(unread: usize, mentions: usize) -> Option<Element<'a, Message>> {
+    if mentions > 0 {
+        Some(
+            Badge::new(label!(mentions.to_string()).size(DEF_SIZE - 4))
+                .style(badge::Danger)
+                .into(),
+        )
+    } else if unread > 0 {
+        Some(
+            Badge::new(label!(unread.to_string()).size(DEF_SIZE - 4))
+                .style(badge::Secondary)
+                .into(),
+        )
+    } else {
+        None
+    }
+}
 
 /// Builds a room list.
 #[allow(clippy::clippy::too_many_arguments)]
@@ -29,7 +52,14 @@ pub fn build_channel_list<'a, Message: Clone + 'a>(
     for ((channel_id, channel), button_state) in channels.iter().zip(buttons_state.iter_mut()) {
         let channel_name_prefix = if channel.is_category { "+" } else { "#" };
         let channel_name_formatted = format!("{}{}", channel_name_prefix, channel.name);
-        let content = label!(channel_name_formatted).size(DEF_SIZE - 2);
+        let mut content = Row::new().align_items(align!(|)).spacing(SPACING).push(
+            label!(channel_name_formatted)
+                .size(DEF_SIZE - 2)
+                .width(length!(+)),
+        );
+        if let Some(badge) = unread_badge(channel.unread, channel.mentions) {
+            content = content.push(badge);
+        }
 
         let mut but = Button::new(button_state, content)
             .width(length!(+))
@@ -84,6 +114,15 @@ pub fn build_guild_list<'a, Message: Clone + 'a>(
                     |handle| Element::from(Image::new(handle.clone())),
                 ),
         );
+        let (unread, mentions) = guild.unread_counts();
+        let content: Element<Message> = match unread_badge(unread, mentions) {
+            Some(badge) => Column::new()
+                .align_items(align!(|))
+                .push(content)
+                .push(badge)
+                .into(),
+            None => content.into(),
+        };
 
         let mut but = Button::new(button_state, content)
             .width(length!(+))
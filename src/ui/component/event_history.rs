@@ -1,39 +1,62 @@
 use crate::{
     client::{
         channel::Channel,
-        content::{ContentStore, ContentType, ThumbnailCache},
+        content::{ContentStore, ContentType},
+        guild::Guild,
         member::Members,
-        message::EmbedHeading,
+        message::{EmbedHeading, MessageId},
+        notification_rules::{mentions_user, NotificationRules},
+        permission,
     },
-    color, label, space,
+    color, label, label_button, space,
     ui::{
-        component::*,
+        component::{
+            markdown::{self, render_markdown, MarkdownState},
+            *,
+        },
         screen::main::{Message, Mode},
         style::{
-            Theme, ALT_COLOR, AVATAR_WIDTH, DATE_SEPERATOR_SIZE, DEF_SIZE, ERROR_COLOR,
+            iced_color, Theme, ALT_COLOR, AVATAR_WIDTH, DATE_SEPERATOR_SIZE, DEF_SIZE, ERROR_COLOR,
             MESSAGE_SENDER_SIZE, MESSAGE_SIZE, MESSAGE_TIMESTAMP_SIZE, PADDING, SPACING,
         },
+        thumbnail::ThumbnailCache,
     },
 };
+use ahash::AHashSet;
 use chrono::{Datelike, Timelike};
 use harmony_rust_sdk::{api::harmonytypes::r#override::Reason, client::api::rest::FileId};
 
+/// How many messages of a channel are shown at once.
 pub const SHOWN_MSGS_LIMIT: usize = 32;
+/// How many reactions are shown under a message.
+pub const SHOWN_REACTIONS_LIMIT: usize = 16;
 const MSG_LR_PADDING: u16 = SPACING * 2;
+/// How many characters of the parent message are shown in a reply quote.
+const REPLY_QUOTE_LENGTH: usize = 80;
 
 #[allow(clippy::mutable_key_type)]
 #[allow(clippy::clippy::too_many_arguments)]
 pub fn build_event_history<'a>(
     content_store: &ContentStore,
     thumbnail_cache: &ThumbnailCache,
+    guild: &Guild,
     channel: &Channel,
     members: &Members,
+    notification_rules: &NotificationRules,
     current_user_id: u64,
     looking_at_message: usize,
+    new_messages_divider: Option<u64>,
     scrollable_state: &'a mut scrollable::State,
     content_open_buttons: &'a mut [button::State; SHOWN_MSGS_LIMIT],
     embed_buttons: &'a mut [[(button::State, button::State); SHOWN_MSGS_LIMIT]; SHOWN_MSGS_LIMIT],
     edit_buts_sate: &'a mut [button::State; SHOWN_MSGS_LIMIT],
+    retry_buts_state: &'a mut [(button::State, button::State); SHOWN_MSGS_LIMIT],
+    reaction_buts_state: &'a mut [(button::State, [button::State; SHOWN_REACTIONS_LIMIT]);
+                SHOWN_MSGS_LIMIT],
+    reply_buts_state: &'a mut [(button::State, button::State); SHOWN_MSGS_LIMIT],
+    pin_buts_state: &'a mut [button::State; SHOWN_MSGS_LIMIT],
+    content_state: &'a mut [MarkdownState; SHOWN_MSGS_LIMIT],
+    expanded_code_blocks: &AHashSet<(MessageId, usize)>,
     mode: Mode,
     theme: Theme,
 ) -> Element<'a, Message> {
@@ -52,27 +75,48 @@ pub fn build_event_history<'a>(
         .spacing(SPACING * 2)
         .padding(PADDING);
 
-    let displayable_events = &channel.messages;
     let timeline_range_end = looking_at_message
         .saturating_add(SHOWN_MSGS_LIMIT)
-        .min(displayable_events.len());
+        .min(channel.messages.len());
     let timeline_range_start = timeline_range_end.saturating_sub(SHOWN_MSGS_LIMIT);
-    let displayable_events = &displayable_events[timeline_range_start..timeline_range_end];
+    let displayable_events = channel
+        .messages
+        .slice(timeline_range_start..timeline_range_end);
 
     let mut last_timestamp = if let Some(ev) = displayable_events.first() {
         ev.timestamp
     } else {
         return event_history.into();
     };
+    let can_delete_others = channel.has_perm(permission::DELETE_MESSAGES) == Some(true);
+    let can_pin = channel.has_perm(permission::PIN_MESSAGES) != Some(false);
+    let current_username = members
+        .get(&current_user_id)
+        .map(|member| member.username.as_str());
+
     let mut last_sender_id = None;
     let mut last_sender_name = None;
+    let mut last_message_id = None;
     let mut message_group = vec![];
 
-    for (((message, media_open_button_state), embed_buts), edit_but_state) in displayable_events
-        .iter()
+    for (
+        (
+            (
+                ((((message, media_open_button_state), embed_buts), edit_but_state), retry_buts),
+                (add_reaction_but_state, reaction_buts),
+            ),
+            (reply_quote_but_state, reply_but_state),
+        ),
+        (pin_but_state, content_state),
+    ) in displayable_events
+        .into_iter()
         .zip(content_open_buttons.iter_mut())
         .zip(embed_buttons.iter_mut())
         .zip(edit_buts_sate.iter_mut())
+        .zip(retry_buts_state.iter_mut())
+        .zip(reaction_buts_state.iter_mut())
+        .zip(reply_buts_state.iter_mut())
+        .zip(pin_buts_state.iter_mut().zip(content_state.iter_mut()))
     {
         let id_to_use = if !message.id.is_ack() {
             current_user_id
@@ -97,14 +141,17 @@ pub fn build_event_history<'a>(
                 Reason::Webhook(_) => {
                     format!("webhook by {}", name_to_use)
                 }
-                _ => todo!("plurality"),
+                Reason::SystemPlurality(_) => "plurality".to_string(),
             });
         let sender_display_name = if let Some(overrides) = &message.overrides {
             overrides.name.clone()
         } else {
             name_to_use
         };
-        let sender_color = theme.calculate_sender_color(sender_display_name.len());
+        let sender_color = guild
+            .member_color(id_to_use)
+            .map(iced_color)
+            .unwrap_or_else(|| theme.calculate_sender_color(id_to_use));
         let sender_avatar_url = if let Some(overrides) = &message.overrides {
             overrides.avatar_url.as_ref()
         } else {
@@ -148,7 +195,30 @@ pub fn build_event_history<'a>(
                 .padding(0)
         };
 
-        let is_sender_different = last_sender_id.as_ref() != Some(&id_to_use)
+        // Messages after the last read one are shown as a new group, below a divider
+        let is_first_unread =
+            new_messages_divider.is_some() && last_message_id == new_messages_divider;
+        if is_first_unread {
+            if !message_group.is_empty() {
+                event_history = event_history.push(
+                    Container::new(
+                        column(message_group.drain(..).collect()).align_items(align!(|<)),
+                    )
+                    .style(theme.round()),
+                );
+            }
+            event_history = event_history.push(
+                fill_container(
+                    label!("New messages")
+                        .size(DATE_SEPERATOR_SIZE)
+                        .color(color!(240, 71, 71)),
+                )
+                .height(length!(-)),
+            );
+        }
+
+        let is_sender_different = is_first_unread
+            || last_sender_id.as_ref() != Some(&id_to_use)
             || last_sender_name.as_ref() != Some(&sender_display_name);
         if is_sender_different {
             if !message_group.is_empty() {
@@ -187,19 +257,103 @@ pub fn build_event_history<'a>(
 
         let mut message_body_widgets = Vec::with_capacity(2);
 
-        let mut message_text = label!(message
-            .being_edited
-            .as_deref()
-            .unwrap_or_else(|| message.content.as_str()))
-        .size(MESSAGE_SIZE);
+        if let Some(parent_id) = message.reply_to {
+            let parent = channel.messages.by_id(MessageId::Ack(parent_id));
+            let quote = if let Some(parent) = parent {
+                let parent_name = parent.overrides.as_ref().map_or_else(
+                    || {
+                        members
+                            .get(&parent.sender)
+                            .map_or_else(String::default, |member| member.username.clone())
+                    },
+                    |overrides| overrides.name.clone(),
+                );
+                let mut snippet = parent
+                    .content
+                    .chars()
+                    .take_while(|c| *c != '\n')
+                    .take(REPLY_QUOTE_LENGTH)
+                    .collect::<String>();
+                if snippet.len() < parent.content.len() {
+                    snippet.push('…');
+                }
+
+                format!("↪ {}: {}", parent_name, snippet)
+            } else {
+                "↪ Load the replied message".to_string()
+            };
 
-        if !message.id.is_ack() || message.being_edited.is_some() {
-            message_text = message_text.color(color!(200, 200, 200));
-        } else if mode == message.id.id().map_or(Mode::Normal, Mode::EditingMessage) {
-            message_text = message_text.color(ERROR_COLOR);
+            message_body_widgets.push(
+                Button::new(
+                    reply_quote_but_state,
+                    label!(quote)
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(color!(160, 160, 160)),
+                )
+                .on_press(Message::JumpToMessage(parent_id))
+                .padding(PADDING / 8)
+                .style(theme.embed())
+                .into(),
+            );
         }
 
-        message_body_widgets.push(message_text.into());
+        // The content is kept as written, so editing and copying get the raw markup
+        let message_text_color = if !message.id.is_ack() || message.being_edited.is_some() {
+            Some(color!(200, 200, 200))
+        } else if mode == message.id.id().map_or(Mode::Normal, Mode::EditingMessage) {
+            Some(ERROR_COLOR)
+        } else {
+            None
+        };
+
+        let message_id = message.id;
+        message_body_widgets.push(
+            render_markdown(
+                message
+                    .being_edited
+                    .as_deref()
+                    .unwrap_or_else(|| message.content.as_str()),
+                MESSAGE_SIZE,
+                message_text_color,
+                theme,
+                members,
+                guild,
+                content_state,
+                |index| expanded_code_blocks.contains(&(message_id, index)),
+            )
+            .map(move |msg| match msg {
+                markdown::Message::ToggleCodeBlock(index) => {
+                    Message::ToggleCodeBlock { message_id, index }
+                }
+                markdown::Message::CopyToClipboard(text) => Message::CopyToClipboard(text),
+                markdown::Message::OpenUrl(url) => Message::ConfirmOpenUrl(url),
+                markdown::Message::SelectUser(user_id) => Message::SelectedMember(user_id),
+                markdown::Message::SelectChannel(channel_id) => Message::ChannelChanged(channel_id),
+            }),
+        );
+
+        if message.failed_to_send {
+            let (retry_but_state, discard_but_state) = retry_buts;
+            message_body_widgets.push(
+                row(vec![
+                    label!("Failed to send")
+                        .color(ERROR_COLOR)
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .into(),
+                    label_button!(retry_but_state, "Retry")
+                        .on_press(Message::RetryFailed(message.id))
+                        .style(theme)
+                        .into(),
+                    label_button!(discard_but_state, "Discard")
+                        .on_press(Message::DiscardFailed(message.id))
+                        .style(theme)
+                        .into(),
+                ])
+                .padding(0)
+                .spacing(SPACING)
+                .into(),
+            );
+        }
 
         for (e, (h_but_state, f_but_state)) in message.embeds.iter().zip(embed_buts.iter_mut()) {
             let put_heading = |embed: &mut Vec<Element<'a, Message>>,
@@ -288,7 +442,12 @@ pub fn build_event_history<'a>(
                         .spacing(SPACING / 2)
                         .align_items(Align::Start),
                 )
-                .style(theme.round().secondary().with_border_color(e.color))
+                .style(
+                    theme
+                        .round()
+                        .secondary()
+                        .with_border_color(iced_color(e.color)),
+                )
                 .into(),
             );
         }
@@ -357,6 +516,64 @@ pub fn build_event_history<'a>(
             }
         }
 
+        // Only messages the homeserver knows about can be reacted or replied to
+        if let Some(message_id) = message.id.id() {
+            let mut reaction_widgets = Vec::with_capacity(message.reactions.len() + 1);
+
+            for (reaction, but_state) in message.reactions.iter().zip(reaction_buts.iter_mut()) {
+                let style = if reaction.reacted {
+                    theme.highlight()
+                } else {
+                    theme.secondary()
+                };
+
+                reaction_widgets.push(
+                    label_button!(but_state, "{} {}", reaction.emote, reaction.count)
+                        .on_press(Message::ToggleReaction {
+                            message_id,
+                            emote: reaction.emote.clone(),
+                        })
+                        .padding(PADDING / 4)
+                        .style(style)
+                        .into(),
+                );
+            }
+
+            reaction_widgets.push(
+                label_button!(add_reaction_but_state, "+")
+                    .on_press(Message::OpenReactionPicker(message_id))
+                    .padding(PADDING / 4)
+                    .style(theme.secondary())
+                    .into(),
+            );
+            reaction_widgets.push(
+                label_button!(reply_but_state, "Reply")
+                    .on_press(Message::ReplyTo(message_id))
+                    .padding(PADDING / 4)
+                    .style(theme.secondary())
+                    .into(),
+            );
+
+            if can_pin {
+                let is_pinned = channel
+                    .pinned_messages
+                    .as_ref()
+                    .map_or(false, |pinned| pinned.contains(&message_id));
+                reaction_widgets.push(
+                    label_button!(pin_but_state, if is_pinned { "Unpin" } else { "Pin" })
+                        .on_press(Message::PinMessage {
+                            message_id,
+                            pin: !is_pinned,
+                        })
+                        .padding(PADDING / 4)
+                        .style(theme.secondary())
+                        .into(),
+                );
+            }
+
+            message_body_widgets.push(row(reaction_widgets).padding(0).spacing(SPACING).into());
+        }
+
         let msg_body = column(message_body_widgets)
             .align_items(align!(|<))
             .padding(0)
@@ -382,12 +599,27 @@ pub fn build_event_history<'a>(
             space!(w = PADDING * 2 - (PADDING / 4 + PADDING / 16)).into()
         };
         message_row.push(maybe_timestamp);
+        // Messages that mention us or contain one of our keywords stand out
+        let is_highlighted = message.sender != current_user_id
+            && (mentions_user(&message.content, current_user_id, current_username)
+                || notification_rules.is_highlight(&message.content));
         let mut but = Button::new(edit_but_state, msg_body)
             .padding(1)
-            .style(theme.embed());
-        if current_user_id == message.sender && Mode::EditMessage == mode {
-            if let Some(id) = message.id.id() {
-                but = but.on_press(Message::ChangeMode(Mode::EditingMessage(id)));
+            .style(if is_highlighted {
+                theme.highlight()
+            } else {
+                theme.embed()
+            });
+        if let Some(id) = message.id.id() {
+            let is_own = current_user_id == message.sender;
+            match mode {
+                Mode::EditMessage if is_own => {
+                    but = but.on_press(Message::ChangeMode(Mode::EditingMessage(id)));
+                }
+                Mode::DeleteMessage if is_own || can_delete_others => {
+                    but = but.on_press(Message::DeleteMessage(id));
+                }
+                _ => {}
             }
         }
         message_row.push(but.into());
@@ -396,6 +628,7 @@ pub fn build_event_history<'a>(
 
         last_sender_id = Some(id_to_use);
         last_sender_name = Some(sender_display_name);
+        last_message_id = message.id.id();
         last_timestamp = message.timestamp;
     }
     if !message_group.is_empty() {
@@ -0,0 +1,69 @@
+//! Syntax highlighting for code blocks in messages.
+
+use crate::{color, ui::style::Theme};
+use ahash::AHashMap;
+use iced::Color;
+use std::{cell::RefCell, rc::Rc};
+use syntect::{
+    easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet, util::LinesWithEndings,
+};
+
+/// How many highlighted code blocks are kept around, so they aren't highlighted on every redraw.
+const HIGHLIGHT_CACHE_LIMIT: usize = 256;
+
+/// Lines of a code block, every line being made of spans of text with their color.
+pub type HighlightedCode = Vec<Vec<(Color, String)>>;
+
+thread_local! {
+    static SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static THEMES: ThemeSet = ThemeSet::load_defaults();
+    static CACHE: RefCell<AHashMap<(String, String, &'static str), Rc<HighlightedCode>>> =
+        RefCell::new(AHashMap::new());
+}
+
+/// Highlights code written in `language`, which is a name or file extension like `rust` or `toml`.
+///
+/// Code in unknown languages is returned as plain text, in the color of the theme.
+pub fn highlight_code(code: &str, language: &str, theme: Theme) -> Rc<HighlightedCode> {
+    let theme_name = theme.code_highlight_theme();
+    let key = (language.to_string(), code.to_string(), theme_name);
+    if let Some(highlighted) = CACHE.with(|cache| cache.borrow().get(&key).cloned()) {
+        return highlighted;
+    }
+
+    let highlighted = Rc::new(SYNTAXES.with(|syntaxes| {
+        THEMES.with(|themes| {
+            let syntax = syntaxes
+                .find_syntax_by_token(language)
+                .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+            let mut highlighter = HighlightLines::new(syntax, &themes.themes[theme_name]);
+
+            LinesWithEndings::from(code)
+                .map(|line| {
+                    highlighter
+                        .highlight(line, syntaxes)
+                        .into_iter()
+                        .map(|(style, text)| {
+                            let fg = style.foreground;
+                            (
+                                color!(fg.r, fg.g, fg.b),
+                                text.trim_end_matches(&['\r', '\n'][..]).to_string(),
+                            )
+                        })
+                        .filter(|(_, text)| !text.is_empty())
+                        .collect()
+                })
+                .collect()
+        })
+    }));
+
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= HIGHLIGHT_CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.insert(key, highlighted.clone());
+    });
+
+    highlighted
+}
@@ -0,0 +1,597 @@
+//! Renders message content written in CommonMark.
+//!
+//! iced can't style parts of a `Text`, so every styled span becomes a `Text` of its own in a
+//! row. Block elements (paragraphs, code blocks, quotes and lists) are stacked in a column.
+//! Fenced code blocks are highlighted for the language they are tagged with, and long ones are
+//! collapsed until the user expands them.
+//!
+//! URLs, user mentions (`<@user_id>`) and channel references (`<#channel_id>`) in text become
+//! buttons, mentions and references being shown with the name of the user or channel.
+
+use crate::{
+    client::{guild::Guild, member::Members},
+    label, length, space,
+    ui::{
+        component::{highlight::highlight_code, *},
+        style::{Theme, ALT_COLOR, MESSAGE_TIMESTAMP_SIZE, MONOSPACE, PADDING, SPACING},
+    },
+};
+use iced::Rule;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use regex::Regex;
+
+/// Width of the bar next to quotes.
+const QUOTE_BAR_WIDTH: u16 = 3;
+/// How many lines of a code block are shown until the user expands it.
+pub const COLLAPSED_CODE_LINES: usize = 12;
+const LINK_COLOR: Color = color!(0, 176, 255);
+
+thread_local! {
+    static INLINE_TOKEN: Regex = Regex::new(r"https?://[^\s<>]+|<@(\d+)>|<#(\d+)>").unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Sent when the user wants to see more (or less) of a code block, with the index of the
+    /// code block in the content.
+    ToggleCodeBlock(usize),
+    CopyToClipboard(String),
+    /// Sent when the user clicks on a link, which should be confirmed before opening.
+    OpenUrl(String),
+    SelectUser(u64),
+    SelectChannel(u64),
+}
+
+/// Button states of a code block.
+#[derive(Debug, Default)]
+struct CodeBlockState {
+    copy_but_state: button::State,
+    toggle_but_state: button::State,
+}
+
+/// Button states of rendered content.
+#[derive(Debug, Default)]
+pub struct MarkdownState {
+    code_blocks: Vec<CodeBlockState>,
+    /// States of links, mentions and channel references.
+    link_buts_state: Vec<button::State>,
+}
+
+/// Parts of text that get a button.
+enum Token<'t> {
+    Text(&'t str),
+    Url(&'t str),
+    User(u64),
+    Channel(u64),
+}
+
+/// Splits text into plain text, URLs, user mentions and channel references.
+fn tokens(text: &str) -> Vec<Token> {
+    INLINE_TOKEN.with(|regex| {
+        let mut tokens = Vec::new();
+        let mut last_end = 0;
+        for captures in regex.captures_iter(text) {
+            let whole = captures.get(0).unwrap();
+            let (token, end) = if let Some(id) = captures.get(1) {
+                (id.as_str().parse().ok().map(Token::User), whole.end())
+            } else if let Some(id) = captures.get(2) {
+                (id.as_str().parse().ok().map(Token::Channel), whole.end())
+            } else {
+                // Punctuation after a URL most likely ends the sentence
+                let url = whole
+                    .as_str()
+                    .trim_end_matches(&['.', ',', ':', ';', '!', '?', '\'', '"', ')'][..]);
+                (Some(Token::Url(url)), whole.start() + url.len())
+            };
+
+            if let Some(token) = token {
+                if whole.start() > last_end {
+                    tokens.push(Token::Text(&text[last_end..whole.start()]));
+                }
+                tokens.push(token);
+                last_end = end;
+            }
+        }
+        if last_end < text.len() {
+            tokens.push(Token::Text(&text[last_end..]));
+        }
+        tokens
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SpanStyle {
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    heading: Option<u32>,
+}
+
+enum BlockKind {
+    Root,
+    Quote,
+    /// A list item, with the bullet or number in front of it.
+    Item(String),
+}
+
+struct Block<'a> {
+    kind: BlockKind,
+    children: Vec<Element<'a, Message>>,
+}
+
+struct Renderer<'a, 'r> {
+    members: &'r Members,
+    guild: &'r Guild,
+    theme: Theme,
+    size: u16,
+    color: Option<Color>,
+    style: SpanStyle,
+    blocks: Vec<Block<'a>>,
+    /// The spans of the line being built.
+    spans: Vec<Element<'a, Message>>,
+    /// Text of the span being built.
+    run: String,
+    /// Contents of the code block being built.
+    code_block: Option<String>,
+    /// Language of the code block being built, empty if it isn't tagged with one.
+    code_language: String,
+    /// Destination of the link being built.
+    link: Option<String>,
+    link_buts_state: std::slice::IterMut<'a, button::State>,
+    code_blocks_state: std::slice::IterMut<'a, CodeBlockState>,
+    /// Whether each code block of the content is expanded.
+    code_blocks_expanded: Vec<bool>,
+    /// Index of the next code block.
+    code_block_index: usize,
+    /// Number of the next item of every list we are in, `None` for bulleted lists.
+    lists: Vec<Option<u64>>,
+}
+
+impl<'a, 'r> Renderer<'a, 'r> {
+    fn children(&mut self) -> &mut Vec<Element<'a, Message>> {
+        &mut self.blocks.last_mut().unwrap().children
+    }
+
+    fn styled_text(&self, text: String) -> Text {
+        let style = self.style;
+        let size = match style.heading {
+            Some(level) => self.size + 2 * (7 - level.min(6) as u16),
+            None if style.strong => self.size + 1,
+            None => self.size,
+        };
+        let text = label!(text).size(size);
+        if style.strikethrough {
+            text.color(ALT_COLOR)
+        } else if style.emphasis {
+            text.color(color!(200, 200, 230))
+        } else if let Some(color) = self.color {
+            text.color(color)
+        } else {
+            text
+        }
+    }
+
+    /// Makes a button out of a link, mention or channel reference.
+    ///
+    /// Falls back to text if we somehow ran out of button states.
+    fn link_button(&mut self, text: String, message: Message, pill: bool) {
+        let text = self.styled_text(text).color(LINK_COLOR);
+        let element = match self.link_buts_state.next() {
+            Some(state) if pill => Button::new(state, text)
+                .on_press(message)
+                .padding(0)
+                .style(self.theme.embed())
+                .into(),
+            Some(state) => Button::new(state, text)
+                .on_press(message)
+                .padding(0)
+                .style(self.theme.secondary())
+                .into(),
+            None => text.into(),
+        };
+        self.spans.push(element);
+    }
+
+    /// Ends the current span.
+    fn flush_run(&mut self) {
+        // Text of a link is flushed when the link ends
+        if self.run.is_empty() || self.link.is_some() {
+            return;
+        }
+
+        let run = std::mem::take(&mut self.run);
+        for token in tokens(&run) {
+            match token {
+                Token::Text(text) => {
+                    let text = self.styled_text(text.to_string());
+                    self.spans.push(text.into());
+                }
+                Token::Url(url) => {
+                    self.link_button(url.to_string(), Message::OpenUrl(url.to_string()), false)
+                }
+                Token::User(user_id) => match self.members.get(&user_id) {
+                    Some(member) => self.link_button(
+                        format!("@{}", member.username),
+                        Message::SelectUser(user_id),
+                        true,
+                    ),
+                    None => {
+                        let text = self.styled_text(format!("@{}", user_id));
+                        self.spans.push(text.into());
+                    }
+                },
+                Token::Channel(channel_id) => match self.guild.channels.get(&channel_id) {
+                    Some(channel) => self.link_button(
+                        format!("#{}", channel.name),
+                        Message::SelectChannel(channel_id),
+                        true,
+                    ),
+                    None => {
+                        let text = self.styled_text("#unknown-channel".to_string());
+                        self.spans.push(text.into());
+                    }
+                },
+            }
+        }
+    }
+
+    /// Ends the current line.
+    fn flush_line(&mut self) {
+        self.flush_run();
+        if self.spans.is_empty() {
+            return;
+        }
+
+        let line = if self.spans.len() == 1 {
+            self.spans.pop().unwrap()
+        } else {
+            Row::with_children(std::mem::take(&mut self.spans))
+                .align_items(align!(|))
+                .into()
+        };
+        self.children().push(line);
+    }
+
+    fn code(&self, code: String) -> Container<'a, Message> {
+        Container::new(label!(code).font(MONOSPACE).size(self.size - 2))
+            .padding(PADDING / 8)
+            .style(self.theme.code())
+    }
+
+    fn code_block(&mut self, code: String) -> Element<'a, Message> {
+        let index = self.code_block_index;
+        self.code_block_index += 1;
+        let expanded = self
+            .code_blocks_expanded
+            .get(index)
+            .copied()
+            .unwrap_or(false);
+        let state = match self.code_blocks_state.next() {
+            Some(state) => state,
+            None => return self.code(code).width(length!(+)).into(),
+        };
+
+        let lines = highlight_code(&code, &self.code_language, self.theme);
+        let collapsible = lines.len() > COLLAPSED_CODE_LINES;
+        let shown_lines = if collapsible && !expanded {
+            COLLAPSED_CODE_LINES
+        } else {
+            lines.len()
+        };
+
+        let small_button = |state: &'a mut button::State, text: &str, message: Message| {
+            Button::new(state, label!(text).size(MESSAGE_TIMESTAMP_SIZE))
+                .on_press(message)
+                .padding(PADDING / 8)
+                .style(self.theme.secondary())
+        };
+
+        let mut widgets: Vec<Element<'a, Message>> = vec![Row::with_children(vec![
+            label!(&self.code_language)
+                .size(MESSAGE_TIMESTAMP_SIZE)
+                .color(ALT_COLOR)
+                .into(),
+            space!(w+).into(),
+            small_button(
+                &mut state.copy_but_state,
+                "Copy",
+                Message::CopyToClipboard(code),
+            )
+            .into(),
+        ])
+        .align_items(align!(|))
+        .into()];
+
+        for line in lines.iter().take(shown_lines) {
+            let spans: Vec<Element<'a, Message>> = if line.is_empty() {
+                // Keep the height of empty lines
+                vec![label!(" ").font(MONOSPACE).size(self.size - 2).into()]
+            } else {
+                line.iter()
+                    .map(|(color, text)| {
+                        label!(text)
+                            .font(MONOSPACE)
+                            .size(self.size - 2)
+                            .color(*color)
+                            .into()
+                    })
+                    .collect()
+            };
+            widgets.push(Row::with_children(spans).into());
+        }
+
+        if collapsible {
+            let text = if expanded {
+                "Show less".to_string()
+            } else {
+                format!("Show {} more lines", lines.len() - shown_lines)
+            };
+            widgets.push(
+                small_button(
+                    &mut state.toggle_but_state,
+                    &text,
+                    Message::ToggleCodeBlock(index),
+                )
+                .into(),
+            );
+        }
+
+        Container::new(Column::with_children(widgets).spacing(SPACING / 2))
+            .width(length!(+))
+            .padding(PADDING / 4)
+            .style(self.theme.code())
+            .into()
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading(level) => self.style.heading = Some(level),
+            Tag::BlockQuote => {
+                self.flush_line();
+                self.blocks.push(Block {
+                    kind: BlockKind::Quote,
+                    children: Vec::new(),
+                });
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush_line();
+                self.code_block = Some(String::new());
+                self.code_language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+            }
+            Tag::List(start) => {
+                self.flush_line();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                self.blocks.push(Block {
+                    kind: BlockKind::Item(marker),
+                    children: Vec::new(),
+                });
+            }
+            Tag::Emphasis => {
+                self.flush_run();
+                self.style.emphasis = true;
+            }
+            Tag::Strong => {
+                self.flush_run();
+                self.style.strong = true;
+            }
+            Tag::Strikethrough => {
+                self.flush_run();
+                self.style.strikethrough = true;
+            }
+            Tag::Link(_, destination, _) => {
+                self.flush_run();
+                self.link = Some(destination.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.flush_line(),
+            Tag::Heading(_) => {
+                self.flush_line();
+                self.style.heading = None;
+            }
+            Tag::CodeBlock(_) => {
+                if let Some(mut code) = self.code_block.take() {
+                    if code.ends_with('\n') {
+                        code.pop();
+                    }
+                    let block = self.code_block(code);
+                    self.children().push(block);
+                }
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+            }
+            Tag::BlockQuote | Tag::Item => {
+                self.flush_line();
+                let block = self.blocks.pop().unwrap();
+                let content = Column::with_children(block.children).spacing(SPACING);
+                let element = match block.kind {
+                    // The bar is the background of the outer container, showing where the
+                    // spacer leaves the quote uncovered
+                    BlockKind::Quote => Container::new(Row::with_children(vec![
+                        space!(w = QUOTE_BAR_WIDTH).into(),
+                        Container::new(content)
+                            .padding(PADDING / 4)
+                            .width(length!(+))
+                            .style(self.theme)
+                            .into(),
+                    ]))
+                    .style(self.theme.quote())
+                    .into(),
+                    BlockKind::Item(marker) => Row::with_children(vec![
+                        label!(marker).size(self.size).into(),
+                        content.into(),
+                    ])
+                    .spacing(SPACING * 2)
+                    .into(),
+                    BlockKind::Root => content.into(),
+                };
+                self.children().push(element);
+            }
+            Tag::Emphasis => {
+                self.flush_run();
+                self.style.emphasis = false;
+            }
+            Tag::Strong => {
+                self.flush_run();
+                self.style.strong = false;
+            }
+            Tag::Strikethrough => {
+                self.flush_run();
+                self.style.strikethrough = false;
+            }
+            Tag::Link(..) => {
+                if let Some(destination) = self.link.take() {
+                    let text = std::mem::take(&mut self.run);
+                    let text = if text.is_empty() {
+                        destination.clone()
+                    } else {
+                        text
+                    };
+                    self.link_button(text, Message::OpenUrl(destination), false);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code_block {
+                Some(code) => code.push_str(&text),
+                None => self.run.push_str(&text),
+            },
+            Event::Code(code) if self.link.is_some() => self.run.push_str(&code),
+            Event::Code(code) => {
+                self.flush_run();
+                let code = self.code(code.to_string());
+                self.spans.push(code.into());
+            }
+            Event::Html(html) => self.run.push_str(&html),
+            Event::SoftBreak => self.run.push(' '),
+            Event::HardBreak => self.flush_line(),
+            Event::Rule => {
+                self.flush_line();
+                self.children()
+                    .push(Rule::horizontal(SPACING * 2).style(self.theme).into());
+            }
+            Event::TaskListMarker(checked) => {
+                self.run.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            Event::FootnoteReference(name) => {
+                self.run.push_str(&format!("[{}]", name));
+            }
+        }
+    }
+}
+
+/// Merges consecutive text events, which the parser splits at characters that could start
+/// markup, so URLs and mentions are found in one piece.
+fn merge_text(events: Parser<'_>) -> Vec<Event<'_>> {
+    let mut merged: Vec<Event> = Vec::new();
+    for event in events {
+        match (merged.last_mut(), event) {
+            (Some(Event::Text(last)), Event::Text(text)) => {
+                *last = CowStr::from(format!("{}{}", last, text));
+            }
+            (_, event) => merged.push(event),
+        }
+    }
+    merged
+}
+
+/// Renders message content as CommonMark.
+///
+/// `color` is the color of text that isn't styled otherwise, `None` for the default color.
+/// `members` and `guild` are used to show the names of mentioned users and channels.
+/// `is_expanded` tells whether the code block with the given index was expanded by the user.
+#[allow(clippy::clippy::too_many_arguments)]
+pub fn render_markdown<'a>(
+    content: &str,
+    size: u16,
+    color: Option<Color>,
+    theme: Theme,
+    members: &Members,
+    guild: &Guild,
+    state: &'a mut MarkdownState,
+    is_expanded: impl Fn(usize) -> bool,
+) -> Element<'a, Message> {
+    let events = merge_text(Parser::new_ext(content, Options::ENABLE_STRIKETHROUGH));
+    let mut code_blocks = 0;
+    let mut links = 0;
+    for event in &events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => code_blocks += 1,
+            Event::Start(Tag::Link(..)) => links += 1,
+            Event::Text(text) => {
+                links += tokens(text)
+                    .iter()
+                    .filter(|token| !matches!(token, Token::Text(_)))
+                    .count()
+            }
+            _ => {}
+        }
+    }
+    state.code_blocks.resize_with(code_blocks, Default::default);
+    state.link_buts_state.resize_with(links, Default::default);
+
+    let mut renderer = Renderer {
+        members,
+        guild,
+        theme,
+        size,
+        color,
+        style: SpanStyle::default(),
+        blocks: vec![Block {
+            kind: BlockKind::Root,
+            children: Vec::new(),
+        }],
+        spans: Vec::new(),
+        run: String::new(),
+        link: None,
+        link_buts_state: state.link_buts_state.iter_mut(),
+        code_block: None,
+        code_language: String::new(),
+        code_blocks_state: state.code_blocks.iter_mut(),
+        code_blocks_expanded: (0..code_blocks).map(is_expanded).collect(),
+        code_block_index: 0,
+        lists: Vec::new(),
+    };
+
+    for event in events {
+        renderer.event(event);
+    }
+    renderer.flush_line();
+
+    // Unclosed blocks can't happen with a well behaved parser, but don't lose their content
+    let mut children = Vec::new();
+    for block in renderer.blocks.drain(..) {
+        children.extend(block.children);
+    }
+
+    if children.len() == 1 {
+        children.pop().unwrap()
+    } else {
+        Column::with_children(children).spacing(SPACING).into()
+    }
+}
@@ -1,5 +1,7 @@
 pub mod chan_guild_list;
 pub mod event_history;
+pub mod highlight;
+pub mod markdown;
 
 use crate::length;
 pub use crate::{align, color, label};
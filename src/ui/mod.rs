@@ -1,3 +1,4 @@
 pub mod component;
 pub mod screen;
 pub mod style;
+pub mod thumbnail;
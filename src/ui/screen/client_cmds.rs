@@ -0,0 +1,348 @@
+//! Runs the operations of [`Client`] as iced commands.
+//!
+//! The client returns futures that resolve to an [`Output`], which is turned into the
+//! [`Message`] that handles it.
+
+use super::{
+    main::{self, pinned_messages},
+    Message,
+};
+use crate::client::{
+    event_stream::ConnectionState, message::Message as IcyMessage, Client, Output, Url,
+};
+
+use iced::Command;
+use std::future::Future;
+
+fn perform(future: impl Future<Output = Output> + Send + 'static) -> Command<Message> {
+    Command::perform(future, Message::from)
+}
+
+/// Runs a send or an edit, saving the outbox right away so the entry isn't lost if we crash
+/// before it's sent.
+fn perform_outbox(future: impl Future<Output = Output> + Send + 'static) -> Command<Message> {
+    let save_cmd = Command::perform(async {}, |_| Message::SaveCache);
+    Command::batch(vec![save_cmd, perform(future)])
+}
+
+impl From<Output> for Message {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::MessageSent {
+                message_id,
+                transaction_id,
+                guild_id,
+                channel_id,
+            } => Message::MessageSent {
+                message_id,
+                transaction_id,
+                guild_id,
+                channel_id,
+            },
+            Output::MessageEdited {
+                guild_id,
+                channel_id,
+                message_id,
+                transaction_id,
+            } => Message::MessageEdited {
+                guild_id,
+                channel_id,
+                message_id,
+                transaction_id,
+            },
+            Output::OutboxAttemptFailed {
+                transaction_id,
+                err,
+            } => Message::OutboxAttemptFailed {
+                transaction_id,
+                err,
+            },
+            Output::MessagePinned {
+                guild_id,
+                channel_id,
+                message_id,
+                pinned,
+            } => Message::MessagePinned {
+                guild_id,
+                channel_id,
+                message_id,
+                pinned,
+            },
+            Output::PinnedMessages {
+                guild_id,
+                channel_id,
+                messages,
+            } => Message::MainScreen(main::Message::PinnedMessagesMsg(
+                pinned_messages::Message::Loaded {
+                    guild_id,
+                    channel_id,
+                    messages,
+                },
+            )),
+            Output::MessageDeleteFailed {
+                guild_id,
+                channel_id,
+                message,
+                err,
+            } => Message::MessageDeleteFailed {
+                guild_id,
+                channel_id,
+                message,
+                err,
+            },
+            Output::ReactionFailed {
+                guild_id,
+                channel_id,
+                message_id,
+                emote,
+                added,
+                err,
+            } => Message::ReactionFailed {
+                guild_id,
+                channel_id,
+                message_id,
+                emote,
+                added,
+                err,
+            },
+            Output::FetchFailed {
+                guild_id,
+                channel_id,
+                err,
+            } => Message::FetchFailed {
+                guild_id,
+                channel_id,
+                err,
+            },
+            Output::History {
+                guild_id,
+                channel_id,
+                messages,
+                reached_top,
+            } => Message::GetEventsBackwardsResponse {
+                messages,
+                reached_top,
+                guild_id,
+                channel_id,
+            },
+            Output::Backfill {
+                guild_id,
+                channel_id,
+                messages,
+                gaps,
+                reached_top,
+            } => Message::Backfill {
+                guild_id,
+                channel_id,
+                messages,
+                gaps,
+                reached_top,
+            },
+            Output::Events(events) => Message::EventsReceived(events),
+            Output::EventStream { updates, update } => Message::EventStream { updates, update },
+            Output::Federated {
+                homeserver,
+                foreign,
+            } => Message::Federated {
+                homeserver,
+                foreign,
+            },
+            Output::FederationFailed { homeserver, err } => {
+                Message::FederationFailed { homeserver, err }
+            }
+            Output::Error(err) => Message::Error(err),
+            Output::Done => Message::Nothing,
+        }
+    }
+}
+
+/// Command versions of the operations of [`Client`].
+pub trait ClientCmds {
+    fn send_msg_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message: IcyMessage,
+    ) -> Option<Command<Message>>;
+
+    fn edit_msg_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        new_content: String,
+    ) -> Option<Command<Message>>;
+
+    fn delete_msg_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    ) -> Option<Command<Message>>;
+
+    fn pin_msg_cmd(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        pin: bool,
+    ) -> Command<Message>;
+
+    fn toggle_reaction_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        emote: String,
+    ) -> Option<Command<Message>>;
+
+    fn fetch_permissions_cmd(&self, guild_id: u64, channel_ids: Vec<u64>) -> Command<Message>;
+
+    fn fetch_guild_data_cmd(&self, guild_id: u64) -> Option<Command<Message>>;
+
+    fn fetch_guild_cmd(&mut self, guild_id: u64) -> Option<Command<Message>>;
+
+    fn fetch_channel_cmd(&mut self, guild_id: u64, channel_id: u64) -> Option<Command<Message>>;
+
+    fn fetch_history_cmd(&mut self, guild_id: u64, channel_id: u64) -> Option<Command<Message>>;
+
+    fn fetch_pinned_messages_cmd(&self, guild_id: u64, channel_id: u64) -> Command<Message>;
+
+    fn fetch_profiles_cmd(&self, guild_id: u64, user_ids: Vec<u64>) -> Command<Message>;
+
+    fn outbox_attempt_failed_cmd(&mut self, transaction_id: u64) -> Option<Command<Message>>;
+
+    fn retry_outbox_cmd(&mut self, transaction_id: u64) -> Option<Command<Message>>;
+
+    fn resume_outbox_cmds(&self) -> Vec<Command<Message>>;
+
+    fn connect_events_cmd(&mut self) -> Command<Message>;
+
+    fn stream_state_changed_cmd(
+        &mut self,
+        homeserver: Option<&Url>,
+        state: ConnectionState,
+    ) -> Command<Message>;
+
+    fn federate_cmd(&mut self, homeserver: Url) -> Option<Command<Message>>;
+
+    fn connect_foreign_events_cmd(&mut self, homeserver: Url) -> Option<Command<Message>>;
+}
+
+impl ClientCmds for Client {
+    fn send_msg_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message: IcyMessage,
+    ) -> Option<Command<Message>> {
+        self.send_msg(guild_id, channel_id, message)
+            .map(perform_outbox)
+    }
+
+    fn edit_msg_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        new_content: String,
+    ) -> Option<Command<Message>> {
+        self.edit_msg(guild_id, channel_id, message_id, new_content)
+            .map(perform_outbox)
+    }
+
+    fn delete_msg_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    ) -> Option<Command<Message>> {
+        self.delete_msg(guild_id, channel_id, message_id)
+            .map(perform)
+    }
+
+    fn pin_msg_cmd(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        pin: bool,
+    ) -> Command<Message> {
+        perform(self.pin_msg(guild_id, channel_id, message_id, pin))
+    }
+
+    fn toggle_reaction_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        emote: String,
+    ) -> Option<Command<Message>> {
+        self.toggle_reaction(guild_id, channel_id, message_id, emote)
+            .map(perform)
+    }
+
+    fn fetch_permissions_cmd(&self, guild_id: u64, channel_ids: Vec<u64>) -> Command<Message> {
+        perform(self.fetch_permissions(guild_id, channel_ids))
+    }
+
+    fn fetch_guild_data_cmd(&self, guild_id: u64) -> Option<Command<Message>> {
+        self.fetch_guild_data(guild_id).map(perform)
+    }
+
+    fn fetch_guild_cmd(&mut self, guild_id: u64) -> Option<Command<Message>> {
+        self.fetch_guild(guild_id).map(perform)
+    }
+
+    fn fetch_channel_cmd(&mut self, guild_id: u64, channel_id: u64) -> Option<Command<Message>> {
+        self.fetch_channel(guild_id, channel_id).map(perform)
+    }
+
+    fn fetch_history_cmd(&mut self, guild_id: u64, channel_id: u64) -> Option<Command<Message>> {
+        self.fetch_history(guild_id, channel_id).map(perform)
+    }
+
+    fn fetch_pinned_messages_cmd(&self, guild_id: u64, channel_id: u64) -> Command<Message> {
+        perform(self.fetch_pinned_messages(guild_id, channel_id))
+    }
+
+    fn fetch_profiles_cmd(&self, guild_id: u64, user_ids: Vec<u64>) -> Command<Message> {
+        perform(self.fetch_profiles(guild_id, user_ids))
+    }
+
+    fn outbox_attempt_failed_cmd(&mut self, transaction_id: u64) -> Option<Command<Message>> {
+        self.outbox_attempt_failed(transaction_id).map(perform)
+    }
+
+    fn retry_outbox_cmd(&mut self, transaction_id: u64) -> Option<Command<Message>> {
+        self.retry_outbox(transaction_id).map(perform)
+    }
+
+    fn resume_outbox_cmds(&self) -> Vec<Command<Message>> {
+        self.resume_outbox().into_iter().map(perform).collect()
+    }
+
+    fn connect_events_cmd(&mut self) -> Command<Message> {
+        perform(self.connect_events())
+    }
+
+    fn stream_state_changed_cmd(
+        &mut self,
+        homeserver: Option<&Url>,
+        state: ConnectionState,
+    ) -> Command<Message> {
+        Command::batch(
+            self.stream_state_changed(homeserver, state)
+                .into_iter()
+                .map(perform),
+        )
+    }
+
+    fn federate_cmd(&mut self, homeserver: Url) -> Option<Command<Message>> {
+        self.federate(homeserver).map(perform)
+    }
+
+    fn connect_foreign_events_cmd(&mut self, homeserver: Url) -> Option<Command<Message>> {
+        self.connect_foreign_events(homeserver).map(perform)
+    }
+}
@@ -37,6 +37,8 @@ pub enum Message {
     ProceedWithChoice(String),
     Proceed,
     GoBack,
+    /// Sent when the user doesn't want to add another account anymore.
+    Cancel,
     AuthStep(Option<AuthStep>),
 }
 
@@ -46,6 +48,7 @@ pub struct LoginScreen {
     choices: HashMap<String, button::State>,
     proceed: button::State,
     back: button::State,
+    cancel: button::State,
 
     current_step: AuthPart,
     can_go_back: bool,
@@ -53,6 +56,8 @@ pub struct LoginScreen {
     current_error: String,
     content_store: Arc<ContentStore>,
     pub waiting: bool,
+    /// Whether we are already logged into another account, so logging in can be cancelled.
+    pub adding_account: bool,
 }
 
 impl LoginScreen {
@@ -63,10 +68,12 @@ impl LoginScreen {
             choices: Default::default(),
             proceed: Default::default(),
             back: Default::default(),
+            cancel: Default::default(),
             current_step: AuthPart::Homeserver,
             can_go_back: false,
             current_error: Default::default(),
             waiting: false,
+            adding_account: false,
         };
         screen.reset_to_first_step();
         screen
@@ -155,6 +162,15 @@ impl LoginScreen {
             );
         }
 
+        if self.adding_account {
+            widgets.push(
+                label_button!(&mut self.cancel, "Cancel")
+                    .on_press(Message::Cancel)
+                    .style(theme)
+                    .into(),
+            );
+        }
+
         let field_panel = column(widgets);
 
         let padded_panel = row(vec![
@@ -214,6 +230,9 @@ impl LoginScreen {
                     );
                 }
             }
+            Message::Cancel => {
+                return Command::perform(async {}, |_| super::Message::PopScreen);
+            }
             Message::ProceedWithChoice(choice) => {
                 if let Some(client) = client {
                     let response = AuthStepResponse::Choice(choice);
@@ -303,13 +322,18 @@ impl LoginScreen {
                     // (How can there be no client, but we get authenticated?)
                     // We *can* recover from here but it's not worth the effort
                     let auth_status = client.unwrap().auth_status();
-                    let homeserver = client.unwrap().inner().homeserver_url().to_string();
-                    let session_file = content_store.session_file().to_path_buf();
+                    let homeserver_url = client.unwrap().inner().homeserver_url().clone();
+                    let content_store = content_store.clone();
                     return Command::perform(
                         async move {
                             if let AuthStatus::Complete(session) = auth_status {
+                                // Every account has its own session file
+                                let session_file = content_store
+                                    .for_account(&homeserver_url, session.user_id)
+                                    .session_file()
+                                    .to_path_buf();
                                 let session = Session {
-                                    homeserver,
+                                    homeserver: homeserver_url.to_string(),
                                     session_token: session.session_token,
                                     user_id: session.user_id.to_string(),
                                 };
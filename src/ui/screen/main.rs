@@ -1,58 +1,86 @@
 pub mod create_channel;
+pub mod delete_message;
 pub mod image_viewer;
 pub mod logout;
+pub mod message_search;
+pub mod notification_center;
+pub mod notification_settings;
+pub mod open_url;
+pub mod pinned_messages;
 pub mod quick_switcher;
+pub mod reaction_picker;
 
 use std::{
-    cmp::Ordering,
     path::PathBuf,
     time::{Duration, Instant},
 };
 
+use super::client_cmds::ClientCmds;
 use crate::{
     client::{
-        content::{self, ImageHandle, ThumbnailCache},
+        content,
         error::ClientError,
-        message::{Attachment, Message as IcyMessage},
+        event_stream::ConnectionState,
+        member::Member,
+        message::{Attachment, Message as IcyMessage, MessageId},
+        notification_rules, permission, quick_switch,
+        search::SearchQuery,
         Client,
     },
     label, label_button, length, space,
     ui::{
-        component::{event_history::SHOWN_MSGS_LIMIT, *},
-        style::{Theme, ALT_COLOR, AVATAR_WIDTH, ERROR_COLOR, MESSAGE_SIZE, PADDING, SPACING},
+        component::{
+            event_history::{SHOWN_MSGS_LIMIT, SHOWN_REACTIONS_LIMIT},
+            markdown::MarkdownState,
+            *,
+        },
+        style::{
+            iced_color, Theme, ALT_COLOR, AVATAR_WIDTH, ERROR_COLOR, MESSAGE_SIZE,
+            MESSAGE_TIMESTAMP_SIZE, PADDING, SPACING, SUCCESS_COLOR,
+        },
+        thumbnail::{ImageHandle, ThumbnailCache},
     },
 };
+use ahash::{AHashMap, AHashSet};
 use chan_guild_list::build_guild_list;
-use channel::{get_channel_messages, GetChannelMessages};
 use chat::Typing;
 use content::ContentType;
 use create_channel::ChannelCreationModal;
+use delete_message::DeleteMessageModal;
 use harmony_rust_sdk::{
-    api::{
-        chat::event::{ChannelCreated, Event, MemberJoined, MessageSent},
-        harmonytypes::UserStatus,
-    },
+    api::harmonytypes::UserStatus,
     client::api::{
-        chat::{
-            self,
-            channel::{self, get_guild_channels, GetChannelMessagesSelfBuilder},
-            guild::get_guild_members,
-            GuildId,
-        },
+        chat,
         rest::{download, upload_extract_id, FileId},
     },
 };
-use iced_aw::{modal, Modal};
+use iced_aw::{modal, Card, Modal};
 use image_viewer::ImageViewerModal;
 use indexmap::IndexMap;
 use logout::LogoutModal;
+use open_url::OpenUrlModal;
+
+use self::{
+    message_search::{MessageSearchModal, ScopeChoice, ShownResult},
+    notification_center::NotificationCenterModal,
+    notification_settings::{LevelChoice, MuteChoice, NotificationSettingsModal},
+    pinned_messages::{PinnedMessage, PinnedMessagesModal},
+    quick_switcher::QuickSwitcherModal,
+    reaction_picker::ReactionPickerModal,
+};
 
-use self::quick_switcher::QuickSwitcherModal;
+/// How many messages are shown after a message we jumped to.
+const JUMP_CONTEXT_MSGS: usize = 4;
+/// How long a notification toast is shown.
+const TOAST_DURATION: Duration = Duration::from_secs(8);
+/// How many notification toasts are shown at once.
+const TOASTS_LIMIT: usize = 3;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Mode {
     EditingMessage(u64),
     EditMessage,
+    DeleteMessage,
     Normal,
 }
 
@@ -65,6 +93,9 @@ impl Default for Mode {
 #[derive(Debug, Clone)]
 pub enum Message {
     QuickSwitch,
+    /// Sent when the user wants to search messages.
+    OpenSearch,
+    MessageSearchMsg(message_search::Message),
     ChangeMode(Mode),
     ClearError,
     /// Sent when the user wants to send a message.
@@ -89,6 +120,9 @@ pub enum Message {
         path: PathBuf,
     },
     OpenUrl(String),
+    /// Sent when the user clicks on a link in a message, which is opened after they confirm.
+    ConfirmOpenUrl(String),
+    OpenUrlChoice(bool),
     /// Sent when the user selects a different guild.
     GuildChanged(u64),
     /// Sent twhen the user selects a different channel.
@@ -103,6 +137,47 @@ pub enum Message {
     SelectedChannelMenuOption(String),
     SelectedMember(u64),
     LogoutChoice(bool),
+    /// Sent when the user selects a message to delete.
+    DeleteMessage(u64),
+    DeleteMessageChoice(bool),
+    /// Sent when the user wants to try connecting to the homeserver again.
+    Reconnect,
+    /// Sent when the user wants to try sending a failed message (or edit) again.
+    RetryFailed(MessageId),
+    /// Sent when the user wants to throw away a failed message (or edit).
+    DiscardFailed(MessageId),
+    /// Sent when the user clicks on a reaction under a message.
+    ToggleReaction {
+        message_id: u64,
+        emote: String,
+    },
+    /// Sent when the user wants to add a new reaction to a message.
+    OpenReactionPicker(u64),
+    /// Sent when the user wants to reply to a message.
+    ReplyTo(u64),
+    CancelReply,
+    /// Sent when the user wants to see a message, which is fetched if it isn't loaded.
+    JumpToMessage(u64),
+    PinMessage {
+        message_id: u64,
+        pin: bool,
+    },
+    PinnedMessagesMsg(pinned_messages::Message),
+    /// Sent when the user wants to see more (or less) of a code block in a message.
+    ToggleCodeBlock {
+        message_id: MessageId,
+        index: usize,
+    },
+    /// Handled by the screen manager, which owns the clipboard.
+    CopyToClipboard(String),
+    /// Sent when a notification should be shown as a toast.
+    ShowToast(u64),
+    DismissToast(u64),
+    /// Sent when the user wants to see the message of a notification.
+    OpenNotification(u64),
+    NotificationCenterMsg(notification_center::Message),
+    NotificationSettingsMsg(notification_settings::Message),
+    ReactionPickerMsg(reaction_picker::Message),
     ChannelCreationMessage(create_channel::Message),
     ImageViewMessage(image_viewer::Message),
     QuickSwitchMsg(quick_switcher::Message),
@@ -114,6 +189,9 @@ pub struct MainScreen {
     event_history_state: scrollable::State,
     content_open_buts_state: [button::State; SHOWN_MSGS_LIMIT],
     edit_buts_sate: [button::State; SHOWN_MSGS_LIMIT],
+    retry_buts_state: [(button::State, button::State); SHOWN_MSGS_LIMIT],
+    reaction_buts_state:
+        [(button::State, [button::State; SHOWN_REACTIONS_LIMIT]); SHOWN_MSGS_LIMIT],
     send_file_but_state: button::State,
     composer_state: text_input::State,
     scroll_to_bottom_but_state: button::State,
@@ -130,20 +208,49 @@ pub struct MainScreen {
     members_list_state: scrollable::State,
 
     logout_modal: modal::State<LogoutModal>,
+    delete_message_modal: modal::State<DeleteMessageModal>,
+    open_url_modal: modal::State<OpenUrlModal>,
+    pinned_messages_modal: modal::State<PinnedMessagesModal>,
+    pin_buts_state: [button::State; SHOWN_MSGS_LIMIT],
+    content_state: [MarkdownState; SHOWN_MSGS_LIMIT],
+    /// Code blocks the user expanded, by message and index of the code block in the message.
+    expanded_code_blocks: AHashSet<(MessageId, usize)>,
     create_channel_modal: modal::State<ChannelCreationModal>,
     pub image_viewer_modal: modal::State<ImageViewerModal>,
     quick_switcher_modal: modal::State<QuickSwitcherModal>,
+    reaction_picker_modal: modal::State<ReactionPickerModal>,
+    notification_center_modal: modal::State<NotificationCenterModal>,
+    message_search_modal: modal::State<MessageSearchModal>,
+    notification_settings_modal: modal::State<NotificationSettingsModal>,
+    /// Notifications shown as toasts, with when they were shown.
+    toasts: Vec<(u64, Instant)>,
+    toast_buts_state: Vec<button::State>,
+    reply_buts_state: [(button::State, button::State); SHOWN_MSGS_LIMIT],
+    reply_cancel_but_state: button::State,
 
     // Join room screen state
     /// `None` if the user didn't select a room, `Some(room_id)` otherwise.
     guild_last_channels: IndexMap<u64, u64>,
     current_guild_id: Option<u64>,
     current_channel_id: Option<u64>,
+    /// Index of the first shown message of channels the user scrolled up in, by guild and channel
+    /// ID. The latest messages of other channels are shown.
+    looking_at_message: AHashMap<(u64, u64), usize>,
     /// The message the user is currently typing.
     message: String,
+    /// The message the user is replying to.
+    reply_to: Option<u64>,
+    /// The message we are loading the history for, so we can jump to it.
+    jump_target: Option<u64>,
+    /// The last read message of the current channel when it was opened, new messages are shown
+    /// below it.
+    new_messages_divider: Option<u64>,
     error_text: String,
     error_close_but_state: button::State,
+    reconnect_but_state: button::State,
     mode: Mode,
+    /// Menu options that switch to another account, with the user ID of the account.
+    account_options: Vec<(String, u64)>,
 }
 
 impl MainScreen {
@@ -152,6 +259,7 @@ impl MainScreen {
         theme: Theme,
         client: &Client,
         thumbnail_cache: &ThumbnailCache,
+        accounts: &[super::AccountSummary],
     ) -> Element<Message> {
         let guilds = &client.guilds;
 
@@ -187,15 +295,49 @@ impl MainScreen {
             .get(&current_user_id)
             .map_or_else(|| String::from("unknown"), |member| member.username.clone());
 
+        self.account_options = accounts
+            .iter()
+            .map(|account| {
+                let option = if account.unread > 0 {
+                    format!("Switch to {} ({} unread)", account.name, account.unread)
+                } else {
+                    format!("Switch to {}", account.name)
+                };
+                (option, account.user_id)
+            })
+            .collect();
+        let mut menu_options = vec![current_username.clone()];
+        menu_options.extend(
+            self.account_options
+                .iter()
+                .map(|(option, _)| option.clone()),
+        );
+        let unread_notifications = client.notifications.unread();
+        menu_options.push(if unread_notifications > 0 {
+            format!("Notifications ({})", unread_notifications)
+        } else {
+            "Notifications".to_string()
+        });
+        menu_options.extend(vec![
+            "Search Messages".to_string(),
+            "Add Account".to_string(),
+            "Join / Create a Guild".to_string(),
+            "Logout".to_string(),
+        ]);
+
+        // Let the user know that other accounts have something new
+        let unread_elsewhere = accounts.iter().map(|account| account.unread).sum::<usize>();
+        let selected_menu_option = if unread_elsewhere > 0 {
+            format!("{} ({})", current_username, unread_elsewhere)
+        } else {
+            current_username
+        };
+
         // TODO: show user avatar next to name
         let menu = PickList::new(
             &mut self.menu_state,
-            vec![
-                current_username.clone(),
-                "Join / Create a Guild".to_string(),
-                "Logout".to_string(),
-            ],
-            Some(current_username),
+            menu_options,
+            Some(selected_menu_option),
             Message::SelectedMenuOption,
         )
         .width(length!(+))
@@ -214,34 +356,69 @@ impl MainScreen {
                 .spacing(SPACING)
                 .padding(PADDING);
 
+            // Online members are grouped by their highest hoisted role, the rest of the online
+            // members and all offline members get a group of their own after the roles
+            let online_group = guild.roles.len();
+            let offline_group = online_group + 1;
+            let member_group = |user_id: u64, member: &Member| {
+                if matches!(member.status, UserStatus::Offline) {
+                    offline_group
+                } else {
+                    guild
+                        .member_hoisted_role(user_id)
+                        .map(|role_id| guild.roles.get_index_of(&role_id))
+                        .flatten()
+                        .unwrap_or(online_group)
+                }
+            };
+
             let mut sorted_members = guild
                 .members
                 .iter()
                 .flat_map(|id| Some((id, client.members.get(id)?)))
                 .collect::<Vec<_>>();
             sorted_members.sort_by_key(|(_, member)| member.username.as_str());
-            sorted_members.sort_by(|(_, member), (_, other_member)| {
-                let offline = matches!(member.status, UserStatus::Offline);
-                let other_offline = matches!(other_member.status, UserStatus::Offline);
-
-                if !offline && other_offline {
-                    Ordering::Less
-                } else if offline && !other_offline {
-                    Ordering::Greater
-                } else {
-                    Ordering::Equal
-                }
-            });
+            sorted_members.sort_by_key(|(id, member)| member_group(**id, member));
+
+            let mut group_sizes = vec![0_usize; offline_group + 1];
+            for (id, member) in &sorted_members {
+                group_sizes[member_group(**id, member)] += 1;
+            }
 
+            let mut last_group = None;
             for (state, (user_id, member)) in self
                 .members_buts_state
                 .iter_mut()
                 .zip(sorted_members.iter())
             {
-                let mut username = label!(&member.username);
-                if matches!(member.status, UserStatus::Offline) {
-                    username = username.color(ALT_COLOR);
+                let group = member_group(**user_id, member);
+                if last_group != Some(group) {
+                    last_group = Some(group);
+                    let group_name = guild.roles.get_index(group).map_or_else(
+                        || {
+                            if group == offline_group {
+                                "Offline"
+                            } else {
+                                "Online"
+                            }
+                        },
+                        |(_, role)| role.name.as_str(),
+                    );
+                    members_list = members_list.push(
+                        label!("{} — {}", group_name, group_sizes[group])
+                            .size(MESSAGE_SIZE)
+                            .color(ALT_COLOR),
+                    );
                 }
+
+                let username = label!(&member.username).color(if group == offline_group {
+                    ALT_COLOR
+                } else {
+                    guild
+                        .member_color(**user_id)
+                        .map(iced_color)
+                        .unwrap_or_else(|| theme.calculate_sender_color(**user_id))
+                });
                 let mut content: Vec<Element<Message>> = vec![username.into(), space!(w+).into()];
                 if let Some(handle) = member
                     .avatar_url
@@ -280,9 +457,17 @@ impl MainScreen {
             }
 
             // TODO: show user avatar next to name
+            let mut channel_menu_options = vec![guild.name.clone()];
+            // Admin entries are hidden until we know we are allowed to use them
+            if guild.has_perm(permission::CREATE_CHANNELS) == Some(true) {
+                channel_menu_options.push("New Channel".to_string());
+            }
+            channel_menu_options.push("Pinned Messages".to_string());
+            channel_menu_options.push("Notification Settings".to_string());
+
             let channel_menu = PickList::new(
                 &mut self.channel_menu_state,
-                vec![guild.name.clone(), "New Channel".to_string()],
+                channel_menu_options,
                 Some(guild.name.clone()),
                 Message::SelectedChannelMenuOption,
             )
@@ -325,17 +510,28 @@ impl MainScreen {
                 .flatten()
             {
                 let message_count = channel.messages.len();
+                let looking_at_message =
+                    self.looking_at_message(guild_id, channel_id, message_count);
                 let message_history_list = build_event_history(
                     client.content_store(),
                     thumbnail_cache,
+                    guild,
                     channel,
                     &client.members,
+                    &client.notification_rules,
                     current_user_id,
-                    channel.looking_at_message,
+                    looking_at_message,
+                    self.new_messages_divider,
                     &mut self.event_history_state,
                     &mut self.content_open_buts_state,
                     &mut self.embed_buttons_state,
                     &mut self.edit_buts_sate,
+                    &mut self.retry_buts_state,
+                    &mut self.reaction_buts_state,
+                    &mut self.reply_buts_state,
+                    &mut self.pin_buts_state,
+                    &mut self.content_state,
+                    &self.expanded_code_blocks,
                     self.mode,
                     theme,
                 );
@@ -386,19 +582,39 @@ impl MainScreen {
                 ])
                 .height(length!(= 14));
 
-                let send_file_button = Button::new(
+                let mut send_file_button = Button::new(
                     &mut self.send_file_but_state,
                     label!(iced_aw::Icon::Upload)
                         .font(iced_aw::ICON_FONT)
                         .size((PADDING / 4) * 3 + MESSAGE_SIZE),
                 )
-                .style(theme.secondary())
-                .on_press(Message::SendFiles {
-                    guild_id,
-                    channel_id,
-                });
+                .style(theme.secondary());
+
+                // Only hide the composer if we know we can't send, not while we are still asking
+                let can_send = channel.has_perm(permission::SEND_MESSAGES) != Some(false);
+
+                if !client.offline && can_send {
+                    send_file_button = send_file_button.on_press(Message::SendFiles {
+                        guild_id,
+                        channel_id,
+                    });
+                }
 
                 let message_composer = match self.mode {
+                    _ if client.offline => {
+                        fill_container(label!("Can't send messages while offline").color(ALT_COLOR))
+                            .padding((PADDING / 4) * 3)
+                            .height(length!(-))
+                            .style(theme.secondary())
+                            .into()
+                    }
+                    Mode::Normal | Mode::EditingMessage(_) if !can_send => fill_container(
+                        label!("You don't have permission to send messages here").color(ALT_COLOR),
+                    )
+                    .padding((PADDING / 4) * 3)
+                    .height(length!(-))
+                    .style(theme.secondary())
+                    .into(),
                     Mode::Normal | Mode::EditingMessage(_) => TextInput::new(
                         &mut self.composer_state,
                         "Enter your message here...",
@@ -419,11 +635,57 @@ impl MainScreen {
                         .height(length!(-))
                         .style(theme.secondary())
                         .into(),
+                    Mode::DeleteMessage => fill_container(label!("Select a message to delete..."))
+                        .padding((PADDING / 4) * 3)
+                        .height(length!(-))
+                        .style(theme.secondary())
+                        .into(),
+                };
+
+                let reply_banner = if let Some(parent_id) = self.reply_to {
+                    let parent_name = channel
+                        .messages
+                        .by_id(MessageId::Ack(parent_id))
+                        .map(|message| {
+                            message.overrides.as_ref().map_or_else(
+                                || {
+                                    client.members.get(&message.sender).map_or_else(
+                                        || String::from("unknown"),
+                                        |member| member.username.clone(),
+                                    )
+                                },
+                                |overrides| overrides.name.clone(),
+                            )
+                        })
+                        .unwrap_or_else(|| String::from("unknown"));
+
+                    let banner = Container::new(
+                        Row::with_children(vec![
+                            label!("Replying to {}", parent_name)
+                                .size(MESSAGE_TIMESTAMP_SIZE)
+                                .width(length!(+))
+                                .into(),
+                            label_button!(&mut self.reply_cancel_but_state, "Cancel")
+                                .on_press(Message::CancelReply)
+                                .padding(PADDING / 4)
+                                .style(theme)
+                                .into(),
+                        ])
+                        .align_items(Align::Center)
+                        .spacing(SPACING * 2),
+                    )
+                    .width(length!(+))
+                    .padding(PADDING / 4)
+                    .style(theme.round());
+
+                    Some(banner)
+                } else {
+                    None
                 };
 
                 let mut bottom_area_widgets = vec![send_file_button.into(), message_composer];
 
-                if channel.looking_at_message < message_count.saturating_sub(SHOWN_MSGS_LIMIT) {
+                if looking_at_message < message_count.saturating_sub(SHOWN_MSGS_LIMIT) {
                     bottom_area_widgets.push(
                         Button::new(
                             &mut self.scroll_to_bottom_but_state,
@@ -437,9 +699,16 @@ impl MainScreen {
                     );
                 }
 
-                let message_area = Column::with_children(vec![
-                    message_history_list,
-                    typing_users.into(),
+                let mut message_area_widgets = vec![message_history_list, typing_users.into()];
+                if let Some(reply_banner) = reply_banner {
+                    message_area_widgets.push(
+                        Container::new(reply_banner)
+                            .width(length!(+))
+                            .padding(PADDING / 2)
+                            .into(),
+                    );
+                }
+                message_area_widgets.push(
                     Container::new(
                         Row::with_children(bottom_area_widgets)
                             .spacing(SPACING * 2)
@@ -448,7 +717,8 @@ impl MainScreen {
                     .width(length!(+))
                     .padding(PADDING / 2)
                     .into(),
-                ]);
+                );
+                let message_area = Column::with_children(message_area_widgets);
 
                 screen_widgets.push(fill_container(message_area).style(theme.secondary()).into());
             } else {
@@ -489,12 +759,98 @@ impl MainScreen {
             );
         }
 
+        self.toasts
+            .retain(|(_, shown_at)| shown_at.elapsed() < TOAST_DURATION);
+        self.toast_buts_state
+            .resize_with(self.toasts.len(), Default::default);
+        if !self.toasts.is_empty() {
+            let mut toasts = Column::new()
+                .width(length!(= 280))
+                .height(length!(+))
+                .spacing(SPACING * 2)
+                .padding(PADDING / 2);
+            for ((id, _), but_state) in self.toasts.iter().zip(self.toast_buts_state.iter_mut()) {
+                if let Some(notification) = client.notifications.get(*id) {
+                    let body =
+                        Button::new(but_state, label!(&notification.body).size(MESSAGE_SIZE))
+                            .on_press(Message::OpenNotification(*id))
+                            .width(length!(+))
+                            .style(theme.secondary());
+                    toasts = toasts.push(
+                        Card::new(label!(&notification.title).size(MESSAGE_SIZE), body)
+                            .on_close(Message::DismissToast(*id))
+                            .width(length!(+))
+                            .style(theme),
+                    );
+                }
+            }
+            screen_widgets.push(Container::new(toasts).style(theme).into());
+        }
+
         let content = Row::with_children(screen_widgets)
             .height(length!(+))
             .width(length!(+));
 
+        // Status bar with the state of the event stream
+        let connection = client.connection;
+        let mut status_bar = vec![label!(connection.to_string())
+            .size(MESSAGE_TIMESTAMP_SIZE)
+            .color(match connection {
+                ConnectionState::Connected => SUCCESS_COLOR,
+                ConnectionState::Offline { .. } => ERROR_COLOR,
+                _ => ALT_COLOR,
+            })
+            .width(length!(+))
+            .into()];
+        // Streams of other homeservers are only shown while something is wrong with them
+        for (homeserver, state) in client
+            .foreign_connections()
+            .filter(|(_, state)| *state != ConnectionState::Connected)
+        {
+            status_bar.push(
+                label!(format!(
+                    "{}: {}",
+                    homeserver.host_str().unwrap_or_default(),
+                    state
+                ))
+                .size(MESSAGE_TIMESTAMP_SIZE)
+                .color(match state {
+                    ConnectionState::Offline { .. } => ERROR_COLOR,
+                    _ => ALT_COLOR,
+                })
+                .into(),
+            );
+        }
+        if matches!(
+            connection,
+            ConnectionState::Reconnecting { .. } | ConnectionState::Offline { .. }
+        ) {
+            status_bar.push(space!(w+).into());
+            status_bar.push(
+                label_button!(&mut self.reconnect_but_state, "Reconnect now")
+                    .on_press(Message::Reconnect)
+                    .style(theme.secondary())
+                    .into(),
+            );
+        }
+
+        let content: Element<Message> = Column::with_children(vec![
+            content.into(),
+            fill_container(
+                Row::with_children(status_bar)
+                    .align_items(Align::Center)
+                    .padding(PADDING / 4),
+            )
+            .style(theme)
+            .height(length!(-))
+            .into(),
+        ])
+        .width(length!(+))
+        .height(length!(+))
+        .into();
+
         let content: Element<Message> = if self.error_text.is_empty() {
-            content.into()
+            content
         } else {
             Column::with_children(vec![
                 fill_container(
@@ -529,6 +885,24 @@ impl MainScreen {
         .backdrop(Message::QuickSwitch)
         .on_esc(Message::QuickSwitch);
 
+        let content = Modal::new(&mut self.message_search_modal, content, move |state| {
+            state.view(theme).map(Message::MessageSearchMsg)
+        })
+        .style(theme)
+        .backdrop(Message::MessageSearchMsg(message_search::Message::Close))
+        .on_esc(Message::MessageSearchMsg(message_search::Message::Close));
+
+        let content = Modal::new(&mut self.notification_center_modal, content, move |state| {
+            state.view(theme).map(Message::NotificationCenterMsg)
+        })
+        .style(theme)
+        .backdrop(Message::NotificationCenterMsg(
+            notification_center::Message::Close,
+        ))
+        .on_esc(Message::NotificationCenterMsg(
+            notification_center::Message::Close,
+        ));
+
         let content = Modal::new(&mut self.logout_modal, content, move |state| {
             state.view(theme).map(Message::LogoutChoice)
         })
@@ -537,6 +911,18 @@ impl MainScreen {
         .on_esc(Message::LogoutChoice(false));
 
         let content = if self.current_guild_id.is_some() {
+            let content = Modal::new(
+                &mut self.notification_settings_modal,
+                content,
+                move |state| state.view(theme).map(Message::NotificationSettingsMsg),
+            )
+            .style(theme)
+            .backdrop(Message::NotificationSettingsMsg(
+                notification_settings::Message::Close,
+            ))
+            .on_esc(Message::NotificationSettingsMsg(
+                notification_settings::Message::Close,
+            ));
             let content = Modal::new(&mut self.create_channel_modal, content, move |state| {
                 state.view(theme).map(Message::ChannelCreationMessage)
             })
@@ -548,6 +934,30 @@ impl MainScreen {
                 create_channel::Message::GoBack,
             ));
             if self.current_channel_id.is_some() {
+                let content = Modal::new(&mut self.pinned_messages_modal, content, move |state| {
+                    state.view(theme).map(Message::PinnedMessagesMsg)
+                })
+                .style(theme)
+                .backdrop(Message::PinnedMessagesMsg(pinned_messages::Message::Close))
+                .on_esc(Message::PinnedMessagesMsg(pinned_messages::Message::Close));
+                let content = Modal::new(&mut self.delete_message_modal, content, move |state| {
+                    state.view(theme).map(Message::DeleteMessageChoice)
+                })
+                .style(theme)
+                .backdrop(Message::DeleteMessageChoice(false))
+                .on_esc(Message::DeleteMessageChoice(false));
+                let content = Modal::new(&mut self.open_url_modal, content, move |state| {
+                    state.view(theme).map(Message::OpenUrlChoice)
+                })
+                .style(theme)
+                .backdrop(Message::OpenUrlChoice(false))
+                .on_esc(Message::OpenUrlChoice(false));
+                let content = Modal::new(&mut self.reaction_picker_modal, content, move |state| {
+                    state.view(theme).map(Message::ReactionPickerMsg)
+                })
+                .style(theme)
+                .backdrop(Message::ReactionPickerMsg(reaction_picker::Message::Close))
+                .on_esc(Message::ReactionPickerMsg(reaction_picker::Message::Close));
                 Modal::new(&mut self.image_viewer_modal, content, move |state| {
                     state.view(theme).map(Message::ImageViewMessage)
                 })
@@ -571,18 +981,6 @@ impl MainScreen {
         client: &mut Client,
         thumbnail_cache: &ThumbnailCache,
     ) -> Command<super::Message> {
-        fn scroll_to_bottom(client: &mut Client, guild_id: u64, channel_id: u64) {
-            if let Some((disp, looking_at_message)) = client
-                .guilds
-                .get_mut(&guild_id)
-                .map(|guild| guild.channels.get_mut(&channel_id))
-                .flatten()
-                .map(|channel| (channel.messages.len(), &mut channel.looking_at_message))
-            {
-                *looking_at_message = disp.saturating_sub(1);
-            }
-        }
-
         match msg {
             Message::QuickSwitch => {
                 self.quick_switcher_modal
@@ -616,64 +1014,8 @@ impl MainScreen {
                     return cmd;
                 }
                 quick_switcher::Message::SearchTermChanged(new_term) => {
-                    let guild = |pattern: &str| {
-                        let matcher = fuzzy_matcher::skim::SkimMatcherV2::default();
-                        let mut guilds = client
-                            .guilds
-                            .iter()
-                            .map(|(id, g)| (*id, g.name.as_str()))
-                            .flat_map(|(id, name)| {
-                                Some((matcher.fuzzy(name, pattern, false)?.0, id, name))
-                            })
-                            .collect::<Vec<_>>();
-                        guilds.sort_unstable_by_key(|(score, _, _)| *score);
-                        guilds
-                            .into_iter()
-                            .rev()
-                            .map(|(_, id, name)| quick_switcher::SearchResult::Guild {
-                                id,
-                                name: name.to_string(),
-                            })
-                            .collect::<Vec<_>>()
-                    };
-
-                    let channel = |pattern: &str| {
-                        let matcher = fuzzy_matcher::skim::SkimMatcherV2::default();
-                        let mut channels = client
-                            .guilds
-                            .iter()
-                            .flat_map(|(gid, g)| {
-                                g.channels
-                                    .iter()
-                                    .map(move |(cid, c)| (*gid, *cid, c.name.as_str()))
-                                    .flat_map(|(gid, cid, name)| {
-                                        Some((
-                                            matcher.fuzzy(name, pattern, false)?.0,
-                                            gid,
-                                            cid,
-                                            name,
-                                        ))
-                                    })
-                            })
-                            .collect::<Vec<_>>();
-                        channels.sort_unstable_by_key(|(score, _, _, _)| *score);
-                        channels
-                            .into_iter()
-                            .rev()
-                            .map(
-                                |(_, gid, cid, name)| quick_switcher::SearchResult::Channel {
-                                    guild_id: gid,
-                                    id: cid,
-                                    name: name.to_string(),
-                                },
-                            )
-                            .collect()
-                    };
-
-                    let term_trimmed = new_term.trim();
-                    if term_trimmed.is_empty() {
-                        self.quick_switcher_modal.inner_mut().results = self
-                            .guild_last_channels
+                    let results = if new_term.trim().is_empty() {
+                        self.guild_last_channels
                             .iter()
                             .map(|(gid, cid)| quick_switcher::SearchResult::Channel {
                                 guild_id: *gid,
@@ -683,30 +1025,35 @@ impl MainScreen {
                                     .map(|c| c.name.clone())
                                     .unwrap_or_else(|| "unknown".to_string()),
                             })
-                            .collect();
-                    } else if let Some(pattern) = new_term.strip_prefix("*").map(str::trim) {
-                        self.quick_switcher_modal.inner_mut().results = guild(pattern);
-                    } else if let Some(pattern) = new_term.strip_prefix("#").map(str::trim) {
-                        self.quick_switcher_modal.inner_mut().results = channel(pattern);
+                            .collect()
                     } else {
-                        self.quick_switcher_modal.inner_mut().results = guild(term_trimmed);
-                        let mut channels = channel(term_trimmed);
-                        self.quick_switcher_modal
-                            .inner_mut()
-                            .results
-                            .append(&mut channels);
-                    }
+                        quick_switch::search(&client.guilds, &new_term)
+                    };
+                    self.quick_switcher_modal.inner_mut().results = results;
                     self.quick_switcher_modal.inner_mut().search_value = new_term;
                 }
             },
             Message::ChangeMode(mode) => {
+                if let Mode::EditingMessage(mid) = mode {
+                    // Only our own messages can be edited
+                    let user_id = client.user_id;
+                    let is_own = self
+                        .current_guild_id
+                        .zip(self.current_channel_id)
+                        .map(|(gid, cid)| client.get_message(gid, cid, mid))
+                        .flatten()
+                        .map_or(false, |msg| Some(msg.sender) == user_id);
+                    if !is_own {
+                        return Command::none();
+                    }
+                }
                 if let (Mode::EditMessage, Mode::EditingMessage(mid)) = (self.mode, mode) {
                     if let (Some(gid), Some(cid)) = (self.current_guild_id, self.current_channel_id)
                     {
                         self.composer_state.focus();
                         if let Some(msg) = client
                             .get_channel(gid, cid)
-                            .map(|c| c.messages.iter_mut().rev().find(|m| m.id.id() == Some(mid)))
+                            .map(|c| c.messages.by_id(MessageId::Ack(mid)))
                             .flatten()
                         {
                             self.message = msg.content.clone();
@@ -728,6 +1075,18 @@ impl MainScreen {
             Message::OpenUrl(url) => {
                 open::that_in_background(url);
             }
+            Message::ConfirmOpenUrl(url) => {
+                self.open_url_modal.inner_mut().set_url(url);
+                self.open_url_modal.show(true);
+            }
+            Message::OpenUrlChoice(confirm) => {
+                self.open_url_modal.show(false);
+                let url = self.open_url_modal.inner_mut().url.take();
+
+                if let (true, Some(url)) = (confirm, url) {
+                    return self.update(Message::OpenUrl(url), client, thumbnail_cache);
+                }
+            }
             Message::OpenImageView { handle, path } => {
                 self.image_viewer_modal.show(true);
                 self.image_viewer_modal.inner_mut().image_handle = Some((handle, path));
@@ -755,91 +1114,468 @@ impl MainScreen {
 
                 return cmd;
             }
+            Message::DeleteMessage(message_id) => {
+                self.mode = Mode::Normal;
+                self.delete_message_modal.inner_mut().message_id = Some(message_id);
+                self.delete_message_modal.show(true);
+            }
+            Message::DeleteMessageChoice(confirm) => {
+                self.delete_message_modal.show(false);
+                let message_id = self.delete_message_modal.inner_mut().message_id.take();
+
+                if let (true, Some(message_id), Some(guild_id), Some(channel_id)) = (
+                    confirm,
+                    message_id,
+                    self.current_guild_id,
+                    self.current_channel_id,
+                ) {
+                    if self.reply_to == Some(message_id) {
+                        self.reply_to = None;
+                    }
+                    if let Some(cmd) = client.delete_msg_cmd(guild_id, channel_id, message_id) {
+                        return cmd;
+                    }
+                }
+            }
             Message::LogoutChoice(confirm) => {
                 self.logout_modal.show(false);
                 return self.logout_modal.inner_mut().update(confirm, client);
             }
-            Message::MessageHistoryScrolled {
-                prev_scroll_perc,
-                scroll_perc,
-            } => {
+            Message::Reconnect => {
+                return Command::perform(async {}, |_| super::Message::Reconnect);
+            }
+            Message::RetryFailed(message_id) => {
+                if let Some(cmd) = client
+                    .outbox_entry_for(message_id)
+                    .map(|transaction_id| client.retry_outbox_cmd(transaction_id))
+                    .flatten()
+                {
+                    return cmd;
+                }
+            }
+            Message::DiscardFailed(message_id) => {
+                if let Some(transaction_id) = client.outbox_entry_for(message_id) {
+                    client.discard_outbox(transaction_id);
+                }
+            }
+            Message::ToggleReaction { message_id, emote } => {
                 if let (Some(guild_id), Some(channel_id)) =
                     (self.current_guild_id, self.current_channel_id)
                 {
-                    if scroll_perc < 0.01 && scroll_perc <= prev_scroll_perc {
-                        if let Some((
-                            oldest_msg_id,
-                            disp,
-                            loading_messages_history,
-                            looking_at_message,
-                        )) = client
-                            .get_channel(guild_id, channel_id)
-                            .map(|channel| {
-                                Some((
-                                    channel.messages.first().map(|m| m.id.id()).flatten(),
-                                    channel.messages.len(),
-                                    &mut channel.loading_messages_history,
-                                    &mut channel.looking_at_message,
-                                ))
-                            })
-                            .flatten()
-                        {
-                            if *looking_at_message == disp.saturating_sub(1) {
-                                *looking_at_message = disp.saturating_sub(SHOWN_MSGS_LIMIT + 1);
-                            } else {
-                                *looking_at_message = looking_at_message.saturating_sub(1);
+                    if let Some(cmd) =
+                        client.toggle_reaction_cmd(guild_id, channel_id, message_id, emote)
+                    {
+                        return cmd;
+                    }
+                }
+            }
+            Message::ReplyTo(message_id) => {
+                self.mode = Mode::Normal;
+                self.reply_to = Some(message_id);
+                self.composer_state.focus();
+            }
+            Message::CancelReply => {
+                self.reply_to = None;
+            }
+            Message::JumpToMessage(message_id) => {
+                if let (Some(guild_id), Some(channel_id)) =
+                    (self.current_guild_id, self.current_channel_id)
+                {
+                    let pos = client.get_channel(guild_id, channel_id).map(|channel| {
+                        let message_count = channel.messages.len();
+                        let pos = channel.messages.position(MessageId::Ack(message_id));
+                        (message_count, pos)
+                    });
+                    if let Some((message_count, Some(pos))) = pos {
+                        // Put the message near the bottom of the shown range, since the
+                        // history snaps to the bottom
+                        self.set_looking_at_message(
+                            guild_id,
+                            channel_id,
+                            message_count,
+                            (pos + JUMP_CONTEXT_MSGS).saturating_sub(SHOWN_MSGS_LIMIT),
+                        );
+                    }
+                    let pos = pos.map(|(_, pos)| pos);
+
+                    match pos {
+                        Some(Some(_)) => self.jump_target = None,
+                        Some(None) => {
+                            // Load older messages until we find it, see `on_history_loaded`
+                            self.jump_target = Some(message_id);
+                            if let Some(cmd) = client.fetch_history_cmd(guild_id, channel_id) {
+                                return cmd;
                             }
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Message::PinMessage { message_id, pin } => {
+                if let (Some(guild_id), Some(channel_id)) =
+                    (self.current_guild_id, self.current_channel_id)
+                {
+                    return client.pin_msg_cmd(guild_id, channel_id, message_id, pin);
+                }
+            }
+            Message::ToggleCodeBlock { message_id, index } => {
+                if !self.expanded_code_blocks.remove(&(message_id, index)) {
+                    self.expanded_code_blocks.insert((message_id, index));
+                }
+            }
+            Message::CopyToClipboard(_) => {}
+            Message::PinnedMessagesMsg(msg) => match msg {
+                pinned_messages::Message::Loaded {
+                    guild_id,
+                    channel_id,
+                    messages,
+                } => {
+                    client.set_pinned_messages(guild_id, channel_id, &messages);
 
-                            if *looking_at_message < 2 && !*loading_messages_history {
-                                *loading_messages_history = true;
-                                let inner = client.inner().clone();
-                                return Command::perform(
-                                    async move {
-                                        channel::get_channel_messages(
-                                            &inner,
-                                            GetChannelMessages::new(guild_id, channel_id)
-                                                .before_message(oldest_msg_id.unwrap_or_default()),
-                                        )
-                                        .await
-                                        .map_or_else(
-                                            |err| super::Message::Error(Box::new(err.into())),
-                                            |response| super::Message::GetEventsBackwardsResponse {
-                                                messages: response.messages,
-                                                reached_top: response.reached_top,
-                                                guild_id,
-                                                channel_id,
-                                            },
+                    // The user may have switched channels while we were loading
+                    if (self.current_guild_id, self.current_channel_id)
+                        == (Some(guild_id), Some(channel_id))
+                    {
+                        let pinned_messages = self.pinned_messages_modal.inner_mut();
+                        pinned_messages.loading = false;
+                        pinned_messages.messages = messages
+                            .into_iter()
+                            .flat_map(|message| {
+                                let sender_name = message.overrides.as_ref().map_or_else(
+                                    || {
+                                        client.members.get(&message.sender).map_or_else(
+                                            || String::from("unknown"),
+                                            |member| member.username.clone(),
                                         )
                                     },
-                                    |result| result,
+                                    |overrides| overrides.name.clone(),
                                 );
-                            }
+
+                                Some(PinnedMessage {
+                                    message_id: message.id.id()?,
+                                    sender_name,
+                                    content: message.content,
+                                    timestamp: message.timestamp,
+                                })
+                            })
+                            .collect();
+                    }
+                }
+                pinned_messages::Message::Jump(message_id) => {
+                    self.pinned_messages_modal.show(false);
+                    return self.update(
+                        Message::JumpToMessage(message_id),
+                        client,
+                        thumbnail_cache,
+                    );
+                }
+                pinned_messages::Message::Close => {
+                    self.pinned_messages_modal.show(false);
+                }
+            },
+            Message::ShowToast(id) => {
+                self.toasts.insert(0, (id, Instant::now()));
+                self.toasts.truncate(TOASTS_LIMIT);
+            }
+            Message::DismissToast(id) => {
+                self.toasts.retain(|(toast_id, _)| *toast_id != id);
+            }
+            Message::OpenNotification(id) => {
+                self.toasts.retain(|(toast_id, _)| *toast_id != id);
+                let (guild_id, channel_id, message_id) = match client.notifications.get(id) {
+                    Some(notification) => (
+                        notification.guild_id,
+                        notification.channel_id,
+                        notification.message_id,
+                    ),
+                    None => return Command::none(),
+                };
+
+                return self.open_message(
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    client,
+                    thumbnail_cache,
+                );
+            }
+            Message::OpenSearch => {
+                let search = self.message_search_modal.inner_mut();
+                search.guild_options = std::iter::once(ScopeChoice {
+                    id: None,
+                    name: "All guilds".to_string(),
+                })
+                .chain(client.guilds.iter().map(|(guild_id, guild)| ScopeChoice {
+                    id: Some(*guild_id),
+                    name: guild.name.clone(),
+                }))
+                .collect();
+                search.guild = search.guild_options.first().cloned();
+                search.channel_options.clear();
+                search.channel = None;
+                self.message_search_modal.show(true);
+                self.run_search(client);
+            }
+            Message::MessageSearchMsg(msg) => {
+                let search = self.message_search_modal.inner_mut();
+                match msg {
+                    message_search::Message::TextChanged(value) => search.text_value = value,
+                    message_search::Message::SenderChanged(value) => search.sender_value = value,
+                    message_search::Message::GuildSelected(choice) => {
+                        // Channels can only be picked once we know the guild
+                        search.channel_options = choice
+                            .id
+                            .map(|guild_id| client.guilds.get(&guild_id))
+                            .flatten()
+                            .map_or_else(Vec::new, |guild| {
+                                std::iter::once(ScopeChoice {
+                                    id: None,
+                                    name: "All channels".to_string(),
+                                })
+                                .chain(
+                                    guild
+                                        .channels
+                                        .iter()
+                                        .filter(|(_, channel)| !channel.is_category)
+                                        .map(|(channel_id, channel)| ScopeChoice {
+                                            id: Some(*channel_id),
+                                            name: format!("#{}", channel.name),
+                                        }),
+                                )
+                                .collect()
+                            });
+                        search.channel = search.channel_options.first().cloned();
+                        search.guild = Some(choice);
+                    }
+                    message_search::Message::ChannelSelected(choice) => {
+                        search.channel = Some(choice)
+                    }
+                    message_search::Message::FromChanged(value) => search.from_value = value,
+                    message_search::Message::ToChanged(value) => search.to_value = value,
+                    message_search::Message::HasAttachmentToggled(value) => {
+                        search.has_attachment = value
+                    }
+                    message_search::Message::Jump {
+                        guild_id,
+                        channel_id,
+                        message_id,
+                    } => {
+                        self.message_search_modal.show(false);
+                        return self.open_message(
+                            guild_id,
+                            channel_id,
+                            message_id,
+                            client,
+                            thumbnail_cache,
+                        );
+                    }
+                    message_search::Message::Close => {
+                        self.message_search_modal.show(false);
+                        return Command::none();
+                    }
+                }
+                self.run_search(client);
+            }
+            Message::NotificationSettingsMsg(msg) => {
+                let settings = self.notification_settings_modal.inner_mut();
+                let (guild_id, channel_id) = match settings.guild_id {
+                    Some(guild_id) => (guild_id, settings.channel_id),
+                    None => return Command::none(),
+                };
+                let now = chrono::Utc::now().naive_utc();
+
+                fn apply_mute(
+                    rules: &mut notification_rules::ScopeRules,
+                    choice: MuteChoice,
+                    now: chrono::NaiveDateTime,
+                ) {
+                    match choice {
+                        MuteChoice::NotMuted => rules.unmute(),
+                        MuteChoice::For(hours) => {
+                            rules.mute(Some(now + chrono::Duration::hours(hours)))
                         }
-                    } else if scroll_perc > 0.99 && scroll_perc >= prev_scroll_perc {
-                        if let Some((disp, looking_at_message)) =
-                            client.get_channel(guild_id, channel_id).map(|channel| {
-                                (channel.messages.len(), &mut channel.looking_at_message)
+                        MuteChoice::Forever => rules.mute(None),
+                        MuteChoice::Until(until) => rules.mute(Some(until)),
+                    }
+                }
+                fn to_level(choice: LevelChoice) -> Option<notification_rules::NotifyLevel> {
+                    match choice {
+                        LevelChoice::Inherit => None,
+                        LevelChoice::Level(level) => Some(level),
+                    }
+                }
+
+                match msg {
+                    notification_settings::Message::GuildLevel(choice) => client
+                        .update_notification_rules(|rules| {
+                            rules.guild_mut(guild_id).level = to_level(choice)
+                        }),
+                    notification_settings::Message::GuildMute(choice) => client
+                        .update_notification_rules(|rules| {
+                            apply_mute(rules.guild_mut(guild_id), choice, now)
+                        }),
+                    notification_settings::Message::ChannelLevel(choice) => {
+                        if let Some(channel_id) = channel_id {
+                            client.update_notification_rules(|rules| {
+                                rules.channel_mut(guild_id, channel_id).level = to_level(choice)
+                            });
+                        }
+                    }
+                    notification_settings::Message::ChannelMute(choice) => {
+                        if let Some(channel_id) = channel_id {
+                            client.update_notification_rules(|rules| {
+                                apply_mute(rules.channel_mut(guild_id, channel_id), choice, now)
+                            });
+                        }
+                    }
+                    notification_settings::Message::KeywordsChanged(value) => {
+                        settings.keywords_value = value;
+                    }
+                    notification_settings::Message::SaveKeywords => {
+                        let keywords = parse_keywords(&settings.keywords_value);
+                        client.update_notification_rules(|rules| rules.set_keywords(keywords));
+                    }
+                    notification_settings::Message::Close => {
+                        // Keywords are saved when closing too, so they aren't lost without Enter
+                        let keywords = parse_keywords(&settings.keywords_value);
+                        if keywords != client.notification_rules.keywords() {
+                            client.update_notification_rules(|rules| rules.set_keywords(keywords));
+                        }
+                        self.notification_settings_modal.show(false);
+                    }
+                }
+                self.refresh_notification_settings(client);
+            }
+            Message::NotificationCenterMsg(msg) => match msg {
+                notification_center::Message::Open(id) => {
+                    self.notification_center_modal.show(false);
+                    return self.update(Message::OpenNotification(id), client, thumbnail_cache);
+                }
+                notification_center::Message::ClearAll => {
+                    client.notifications.clear();
+                    self.notification_center_modal
+                        .inner_mut()
+                        .notifications
+                        .clear();
+                    self.toasts.clear();
+                }
+                notification_center::Message::Close => {
+                    self.notification_center_modal.show(false);
+                }
+            },
+            Message::OpenReactionPicker(message_id) => {
+                self.reaction_picker_modal.inner_mut().message_id = Some(message_id);
+                self.reaction_picker_modal.show(true);
+            }
+            Message::ReactionPickerMsg(msg) => match msg {
+                reaction_picker::Message::EmoteChanged(new_emote) => {
+                    self.reaction_picker_modal.inner_mut().emote_value = new_emote;
+                }
+                reaction_picker::Message::Picked(emote) => {
+                    let picker = self.reaction_picker_modal.inner_mut();
+                    let message_id = picker.message_id.take();
+                    picker.emote_value.clear();
+                    self.reaction_picker_modal.show(false);
+
+                    if let Some(message_id) = message_id {
+                        // Picking an emote we already reacted with shouldn't remove it
+                        let already_reacted = self
+                            .current_guild_id
+                            .zip(self.current_channel_id)
+                            .map(|(guild_id, channel_id)| {
+                                client.get_message(guild_id, channel_id, message_id)
                             })
-                        {
-                            if *looking_at_message > disp.saturating_sub(SHOWN_MSGS_LIMIT) {
-                                *looking_at_message = disp.saturating_sub(1);
-                            } else {
-                                *looking_at_message =
-                                    looking_at_message.saturating_add(1).min(disp);
+                            .flatten()
+                            .map_or(false, |message| {
+                                message
+                                    .reactions
+                                    .iter()
+                                    .any(|reaction| reaction.emote == emote && reaction.reacted)
+                            });
+
+                        if !already_reacted {
+                            return self.update(
+                                Message::ToggleReaction { message_id, emote },
+                                client,
+                                thumbnail_cache,
+                            );
+                        }
+                    }
+                }
+                reaction_picker::Message::Close => {
+                    let picker = self.reaction_picker_modal.inner_mut();
+                    picker.message_id = None;
+                    picker.emote_value.clear();
+                    self.reaction_picker_modal.show(false);
+                }
+            },
+            Message::MessageHistoryScrolled {
+                prev_scroll_perc,
+                scroll_perc,
+            } => {
+                if let (Some(guild_id), Some(channel_id)) =
+                    (self.current_guild_id, self.current_channel_id)
+                {
+                    let disp = match client.get_channel(guild_id, channel_id) {
+                        Some(channel) => channel.messages.len(),
+                        None => return Command::none(),
+                    };
+                    let looking_at_message = self.looking_at_message(guild_id, channel_id, disp);
+                    if scroll_perc < 0.01 && scroll_perc <= prev_scroll_perc {
+                        let looking_at_message = if looking_at_message == disp.saturating_sub(1) {
+                            disp.saturating_sub(SHOWN_MSGS_LIMIT + 1)
+                        } else {
+                            looking_at_message.saturating_sub(1)
+                        };
+                        self.set_looking_at_message(guild_id, channel_id, disp, looking_at_message);
+                        if looking_at_message < 2 {
+                            if let Some(cmd) = client.fetch_history_cmd(guild_id, channel_id) {
+                                return cmd;
                             }
                         }
+                    } else if scroll_perc > 0.99 && scroll_perc >= prev_scroll_perc {
+                        self.set_looking_at_message(
+                            guild_id,
+                            channel_id,
+                            disp,
+                            looking_at_message.saturating_add(1),
+                        );
                     }
                 }
             }
             Message::SelectedMember(user_id) => {
                 tracing::trace!("member: {}", user_id);
             }
-            Message::SelectedChannelMenuOption(option) => {
-                if let "New Channel" = option.as_str() {
+            Message::SelectedChannelMenuOption(option) => match option.as_str() {
+                "New Channel" => {
                     self.create_channel_modal.show(true);
                     return self.update(Message::ChangeMode(Mode::Normal), client, thumbnail_cache);
                 }
-            }
+                "Notification Settings" => {
+                    let settings = self.notification_settings_modal.inner_mut();
+                    settings.guild_id = self.current_guild_id;
+                    settings.channel_id = self.current_channel_id;
+                    settings.keywords_value = client.notification_rules.keywords().join(", ");
+                    self.refresh_notification_settings(client);
+                    self.notification_settings_modal.show(true);
+                }
+                "Pinned Messages" => {
+                    if let (Some(guild_id), Some(channel_id)) =
+                        (self.current_guild_id, self.current_channel_id)
+                    {
+                        let pinned_messages = self.pinned_messages_modal.inner_mut();
+                        pinned_messages.messages.clear();
+                        pinned_messages.loading = true;
+                        self.pinned_messages_modal.show(true);
+
+                        let cmd = client.fetch_pinned_messages_cmd(guild_id, channel_id);
+                        let cmd2 =
+                            self.update(Message::ChangeMode(Mode::Normal), client, thumbnail_cache);
+                        return Command::batch(vec![cmd, cmd2]);
+                    }
+                }
+                _ => {}
+            },
             Message::SelectedMenuOption(option) => match option.as_str() {
                 "Logout" => {
                     self.logout_modal.show(true);
@@ -852,7 +1588,30 @@ impl MainScreen {
                         )))
                     })
                 }
-                _ => {}
+                "Add Account" => {
+                    return Command::perform(async {}, |_| super::Message::AddAccount);
+                }
+                "Search Messages" => {
+                    return self.update(Message::OpenSearch, client, thumbnail_cache);
+                }
+                option if option.starts_with("Notifications") => {
+                    self.notification_center_modal.inner_mut().notifications =
+                        client.notifications.iter().cloned().collect();
+                    self.notification_center_modal.show(true);
+                    client.notifications.mark_all_read();
+                }
+                option => {
+                    if let Some((_, user_id)) = self
+                        .account_options
+                        .iter()
+                        .find(|(account_option, _)| account_option == option)
+                    {
+                        let user_id = *user_id;
+                        return Command::perform(async {}, move |_| {
+                            super::Message::SwitchAccount(user_id)
+                        });
+                    }
+                }
             },
             Message::ComposerMessageChanged(new_msg) => {
                 self.message = new_msg;
@@ -870,7 +1629,7 @@ impl MainScreen {
                         || typing.map_or(false, |(_, _, since)| since.elapsed().as_secs() >= 5)
                     {
                         *typing = Some((guild_id, channel_id, Instant::now()));
-                        let inner = client.inner().clone();
+                        let inner = client.inner_for_guild(guild_id).clone();
                         return Command::perform(
                             async move { chat::typing(&inner, Typing::new(guild_id, channel_id)).await },
                             |result| {
@@ -888,7 +1647,7 @@ impl MainScreen {
                     (self.current_guild_id, self.current_channel_id)
                 {
                     if sent_channel_id == channel_id {
-                        scroll_to_bottom(client, guild_id, channel_id);
+                        self.looking_at_message.remove(&(guild_id, channel_id));
                         self.event_history_state.scroll_to_bottom();
                     }
                 }
@@ -923,7 +1682,11 @@ impl MainScreen {
                         |result| result.unwrap_or_else(|err| super::Message::Error(Box::new(err))),
                     )
                 } else {
-                    let inner = client.inner().clone();
+                    // Files that were sent in a guild are hosted on the guild's homeserver
+                    let inner = self
+                        .current_guild_id
+                        .map_or_else(|| client.inner(), |id| client.inner_for_guild(id))
+                        .clone();
                     Command::perform(
                         async move {
                             use harmony_rust_sdk::client::error::ClientError as InnerClientError;
@@ -965,30 +1728,26 @@ impl MainScreen {
                             self.message.drain(..).collect::<String>().trim().into();
                         if let Some(msg) = client
                             .get_channel(guild_id, channel_id)
-                            .map(|c| {
-                                c.messages
-                                    .iter_mut()
-                                    .find(|m| m.id.id() == Some(message_id))
-                            })
+                            .map(|c| c.messages.by_id_mut(MessageId::Ack(message_id)))
                             .flatten()
                         {
                             msg.being_edited = Some(new_content.clone());
                         }
                         self.mode = Mode::Normal;
-                        return client.edit_msg_cmd(guild_id, channel_id, message_id, new_content);
+                        if let Some(cmd) =
+                            client.edit_msg_cmd(guild_id, channel_id, message_id, new_content)
+                        {
+                            return cmd;
+                        }
                     } else if let Mode::Normal = self.mode {
                         let message = IcyMessage {
                             content: self.message.drain(..).collect::<String>().trim().into(),
                             sender: client.user_id.unwrap(),
+                            reply_to: self.reply_to.take(),
                             ..Default::default()
                         };
-                        if let Some(cmd) = client.send_msg_cmd(
-                            guild_id,
-                            channel_id,
-                            Duration::from_secs(0),
-                            message,
-                        ) {
-                            scroll_to_bottom(client, guild_id, channel_id);
+                        if let Some(cmd) = client.send_msg_cmd(guild_id, channel_id, message) {
+                            self.looking_at_message.remove(&(guild_id, channel_id));
                             self.event_history_state.scroll_to_bottom();
                             return cmd;
                         }
@@ -1001,7 +1760,7 @@ impl MainScreen {
                 guild_id,
                 channel_id,
             } => {
-                let inner = client.inner().clone();
+                let inner = client.inner_for_guild(guild_id).clone();
                 let content_store = client.content_store_arc();
                 let sender = client.user_id.unwrap();
 
@@ -1073,7 +1832,6 @@ impl MainScreen {
                                 sender,
                                 ..Default::default()
                             },
-                            retry_after: Duration::from_secs(0),
                             guild_id,
                             channel_id,
                         })
@@ -1093,103 +1851,83 @@ impl MainScreen {
             Message::GuildChanged(guild_id) => {
                 self.mode = Mode::Normal;
                 self.message.clear();
+                self.reply_to = None;
+                self.jump_target = None;
                 self.current_guild_id = Some(guild_id);
+                let offline = client.offline;
+                let mut cmds = Vec::with_capacity(3);
+                // Try logging into the guild's homeserver again, in case it failed before
+                if let Some(homeserver) = client
+                    .guilds
+                    .get(&guild_id)
+                    .map(|guild| guild.homeserver.clone())
+                    .flatten()
+                {
+                    if !offline {
+                        cmds.extend(client.federate_cmd(homeserver));
+                    }
+                }
                 if let Some(guild) = client.get_guild(guild_id) {
-                    if guild.channels.is_empty() {
-                        let inner = client.inner().clone();
-
-                        return Command::perform(
-                            async move {
-                                let guildid = GuildId::new(guild_id);
-                                let channels_list =
-                                    get_guild_channels(&inner, guildid).await?.channels;
-                                let mut events = Vec::with_capacity(channels_list.len());
-                                for channel in channels_list {
-                                    events.push(Event::CreatedChannel(ChannelCreated {
-                                        guild_id,
-                                        channel_id: channel.channel_id,
-                                        is_category: channel.is_category,
-                                        name: channel.channel_name,
-                                        metadata: channel.metadata,
-                                        ..Default::default()
-                                    }));
-                                }
-
-                                let members = get_guild_members(&inner, guildid).await?.members;
-                                events.reserve(members.len());
-                                for member_id in members {
-                                    events.push(Event::JoinedMember(MemberJoined {
-                                        guild_id,
-                                        member_id,
-                                    }));
-                                }
-
-                                Ok(events)
-                            },
-                            |result| {
-                                result.map_or_else(
-                                    |err| super::Message::Error(Box::new(err)),
-                                    super::Message::EventsReceived,
-                                )
-                            },
-                        );
-                    } else {
+                    if let Some((first_channel_id, _)) = guild.channels.first() {
                         self.current_channel_id = self
                             .guild_last_channels
                             .get(&guild_id)
                             .copied()
-                            .or_else(|| Some(*guild.channels.first().unwrap().0));
+                            .or(Some(*first_channel_id));
+                    }
+
+                    let needs_permissions = guild.permissions.is_empty()
+                        || self
+                            .current_channel_id
+                            .map(|id| guild.channels.get(&id))
+                            .flatten()
+                            .map_or(false, |channel| channel.permissions.is_empty());
+
+                    if needs_permissions && !offline {
+                        cmds.push(client.fetch_permissions_cmd(
+                            guild_id,
+                            self.current_channel_id.into_iter().collect(),
+                        ));
                     }
                 }
+                cmds.extend(client.fetch_guild_cmd(guild_id));
+                return Command::batch(cmds);
             }
             Message::ChannelChanged(channel_id) => {
                 self.mode = Mode::Normal;
                 self.message.clear();
+                self.reply_to = None;
+                self.jump_target = None;
                 self.current_channel_id = Some(channel_id);
                 self.guild_last_channels
                     .insert(self.current_guild_id.unwrap(), channel_id);
-                if let Some((disp, disp_at)) = self
-                    .current_guild_id
-                    .map(|guild_id| client.get_channel(guild_id, channel_id))
-                    .flatten()
-                    .map(|channel| (channel.messages.len(), &mut channel.looking_at_message))
-                {
-                    if *disp_at >= disp.saturating_sub(SHOWN_MSGS_LIMIT) {
-                        *disp_at = disp.saturating_sub(1);
+                let offline = client.offline;
+                let guild_id = self.current_guild_id.unwrap();
+                let mut cmds = Vec::with_capacity(3);
+                if let Some(channel) = client.get_channel(guild_id, channel_id) {
+                    let needs_permissions = channel.permissions.is_empty();
+                    let needs_pins = channel.pinned_messages.is_none();
+                    let disp = channel.messages.len();
+                    if self.looking_at_message(guild_id, channel_id, disp) == disp.saturating_sub(1)
+                    {
                         self.event_history_state.scroll_to_bottom();
                     }
-                    if disp == 0 {
-                        let inner = client.inner().clone();
-                        let guild_id = self.current_guild_id.unwrap();
-                        return Command::perform(
-                            async move {
-                                let messages = get_channel_messages(
-                                    &inner,
-                                    GetChannelMessages::new(guild_id, channel_id),
-                                )
-                                .await?
-                                .messages;
-                                let events = messages
-                                    .into_iter()
-                                    .map(|msg| {
-                                        Event::SentMessage(Box::new(MessageSent {
-                                            message: Some(msg),
-                                            ..Default::default()
-                                        }))
-                                    })
-                                    .rev()
-                                    .collect();
-                                Ok(events)
-                            },
-                            |result| {
-                                result.map_or_else(
-                                    |err| super::Message::Error(Box::new(err)),
-                                    super::Message::EventsReceived,
-                                )
-                            },
-                        );
+                    if needs_permissions && !offline {
+                        cmds.push(client.fetch_permissions_cmd(guild_id, vec![channel_id]));
+                    }
+                    // Pins are shown on the messages, not only in the pinned messages panel
+                    if needs_pins && !offline {
+                        cmds.push(client.fetch_pinned_messages_cmd(guild_id, channel_id));
                     }
                 }
+                cmds.extend(client.fetch_channel_cmd(guild_id, channel_id));
+                self.new_messages_divider = client
+                    .get_channel(guild_id, channel_id)
+                    .filter(|channel| channel.unread > 0)
+                    .map(|channel| channel.last_read)
+                    .flatten();
+                client.mark_channel_read(guild_id, channel_id);
+                return Command::batch(cmds);
             }
         }
 
@@ -1214,12 +1952,22 @@ impl MainScreen {
                     key_code: KeyCode::K,
                     modifiers: keyboard::Modifiers { control: true, .. },
                 }) => Some(super::Message::MainScreen(Message::QuickSwitch)),
+                Event::Keyboard(keyboard::Event::KeyReleased {
+                    key_code: KeyCode::F,
+                    modifiers: keyboard::Modifiers { control: true, .. },
+                }) => Some(super::Message::MainScreen(Message::OpenSearch)),
                 Event::Keyboard(keyboard::Event::KeyReleased {
                     key_code: KeyCode::E,
                     modifiers: keyboard::Modifiers { control: true, .. },
                 }) => Some(super::Message::MainScreen(Message::ChangeMode(
                     Mode::EditMessage,
                 ))),
+                Event::Keyboard(keyboard::Event::KeyReleased {
+                    key_code: KeyCode::D,
+                    modifiers: keyboard::Modifiers { control: true, .. },
+                }) => Some(super::Message::MainScreen(Message::ChangeMode(
+                    Mode::DeleteMessage,
+                ))),
                 _ => None,
             }
         }
@@ -1227,8 +1975,196 @@ impl MainScreen {
         iced_native::subscription::events_with(filter_events)
     }
 
+    /// Switches to the channel of a message and jumps to the message.
+    fn open_message(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        client: &mut Client,
+        thumbnail_cache: &ThumbnailCache,
+    ) -> Command<super::Message> {
+        let mut cmds = Vec::with_capacity(3);
+        if self.current_guild_id != Some(guild_id) {
+            cmds.push(self.update(Message::GuildChanged(guild_id), client, thumbnail_cache));
+        }
+        if self.current_channel_id != Some(channel_id) {
+            cmds.push(self.update(Message::ChannelChanged(channel_id), client, thumbnail_cache));
+        }
+        cmds.push(self.update(Message::JumpToMessage(message_id), client, thumbnail_cache));
+        Command::batch(cmds)
+    }
+
+    /// Searches messages with the filters of the search panel and shows the results.
+    fn run_search(&mut self, client: &Client) {
+        let search = self.message_search_modal.inner_mut();
+
+        let parse_date = |value: &str| {
+            let value = value.trim();
+            if value.is_empty() {
+                Ok(None)
+            } else {
+                chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map(Some)
+            }
+        };
+        let (from, to) = match (parse_date(&search.from_value), parse_date(&search.to_value)) {
+            (Ok(from), Ok(to)) => (from, to),
+            _ => {
+                search.error_text = "Dates should look like 2021-03-25".to_string();
+                search.results.clear();
+                return;
+            }
+        };
+        search.error_text.clear();
+
+        let query = SearchQuery {
+            text: search.text_value.clone(),
+            sender: search.sender_value.clone(),
+            guild_id: search.guild.as_ref().map(|choice| choice.id).flatten(),
+            channel_id: search.channel.as_ref().map(|choice| choice.id).flatten(),
+            from,
+            to,
+            has_attachment: search.has_attachment,
+        };
+
+        search.results = client
+            .search(&query)
+            .into_iter()
+            .map(|result| {
+                let guild = client.guilds.get(&result.guild_id);
+                let location = format!(
+                    "#{} ({})",
+                    guild
+                        .map(|guild| guild.channels.get(&result.channel_id))
+                        .flatten()
+                        .map_or("unknown", |channel| channel.name.as_str()),
+                    guild.map_or("unknown", |guild| guild.name.as_str()),
+                );
+                ShownResult {
+                    guild_id: result.guild_id,
+                    channel_id: result.channel_id,
+                    message_id: result.message_id,
+                    sender_name: client
+                        .members
+                        .get(&result.sender)
+                        .map_or_else(|| String::from("unknown"), |member| member.username.clone()),
+                    location,
+                    timestamp: result.timestamp,
+                    snippet: result.snippet,
+                }
+            })
+            .collect();
+    }
+
+    /// Updates what the notification settings panel shows from the current rules.
+    fn refresh_notification_settings(&mut self, client: &Client) {
+        let settings = self.notification_settings_modal.inner_mut();
+        let guild_id = match settings.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+        let guild = client.guilds.get(&guild_id);
+
+        settings.guild_name = guild.map_or_else(String::new, |guild| guild.name.clone());
+        settings.guild_rules = client
+            .notification_rules
+            .guild(guild_id)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(channel_id) = settings.channel_id {
+            settings.channel_name = guild
+                .map(|guild| guild.channels.get(&channel_id))
+                .flatten()
+                .map_or_else(String::new, |channel| channel.name.clone());
+            settings.channel_rules = client
+                .notification_rules
+                .channel(guild_id, channel_id)
+                .cloned()
+                .unwrap_or_default();
+        }
+    }
+
+    /// Returns the guild and channel that are shown, if any.
+    pub fn current_channel(&self) -> Option<(u64, u64)> {
+        Some((self.current_guild_id?, self.current_channel_id?))
+    }
+
+    /// Returns the index of the first shown message of a channel with `message_count` messages,
+    /// or the index of the last message if the latest messages are shown.
+    fn looking_at_message(&self, guild_id: u64, channel_id: u64, message_count: usize) -> usize {
+        self.looking_at_message
+            .get(&(guild_id, channel_id))
+            .copied()
+            .filter(|pos| *pos < message_count.saturating_sub(SHOWN_MSGS_LIMIT))
+            .unwrap_or_else(|| message_count.saturating_sub(1))
+    }
+
+    /// Shows the messages of a channel starting at `pos`, or the latest messages if there aren't
+    /// enough messages after it.
+    fn set_looking_at_message(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_count: usize,
+        pos: usize,
+    ) {
+        if pos < message_count.saturating_sub(SHOWN_MSGS_LIMIT) {
+            self.looking_at_message.insert((guild_id, channel_id), pos);
+        } else {
+            self.looking_at_message.remove(&(guild_id, channel_id));
+        }
+    }
+
+    /// Whether the latest messages of a channel in the current guild are shown.
+    pub fn shows_latest_messages(&self, client: &Client, channel_id: u64) -> bool {
+        let guild_id = match self.current_guild_id {
+            Some(guild_id) => guild_id,
+            None => return false,
+        };
+        let message_count = client
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.channels.get(&channel_id))
+            .map_or(0, |channel| channel.messages.len());
+        self.looking_at_message(guild_id, channel_id, message_count)
+            == message_count.saturating_sub(1)
+    }
+
+    /// Called when older messages of a channel are loaded, to continue jumping to a message.
+    pub fn on_history_loaded(
+        &mut self,
+        client: &mut Client,
+        thumbnail_cache: &ThumbnailCache,
+        guild_id: u64,
+        channel_id: u64,
+        reached_top: bool,
+    ) -> Command<super::Message> {
+        if (self.current_guild_id, self.current_channel_id) != (Some(guild_id), Some(channel_id)) {
+            return Command::none();
+        }
+
+        let message_id = match self.jump_target.take() {
+            Some(message_id) => message_id,
+            None => return Command::none(),
+        };
+        let is_loaded = client
+            .get_channel(guild_id, channel_id)
+            .map_or(false, |channel| {
+                channel.messages.contains(MessageId::Ack(message_id))
+            });
+
+        if reached_top && !is_loaded {
+            self.error_text = String::from("Couldn't find the message, it may have been deleted");
+            Command::none()
+        } else {
+            self.update(Message::JumpToMessage(message_id), client, thumbnail_cache)
+        }
+    }
+
     pub fn on_error(&mut self, error: ClientError) -> Command<super::Message> {
         self.error_text = error.to_string();
+        self.jump_target = None;
+        self.pinned_messages_modal.inner_mut().loading = false;
         self.logout_modal.show(false);
 
         Command::batch(vec![
@@ -1237,3 +2173,13 @@ impl MainScreen {
         ])
     }
 }
+
+/// Splits the comma separated keywords the user entered.
+fn parse_keywords(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|keyword| !keyword.is_empty())
+        .map(str::to_string)
+        .collect()
+}
@@ -124,7 +124,7 @@ impl ChannelCreationModal {
                 self.channel_creation_state = ChannelState::Creating {
                     name: channel_name.clone(),
                 };
-                let inner = client.inner().clone();
+                let inner = client.inner_for_guild(guild_id).clone();
 
                 return (
                     Command::perform(
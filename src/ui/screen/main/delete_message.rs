@@ -0,0 +1,53 @@
+use crate::{
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{Theme, DEF_SIZE, ERROR_COLOR},
+    },
+};
+
+pub type Message = bool;
+
+#[derive(Debug, Default)]
+pub struct DeleteMessageModal {
+    delete_approve_but_state: button::State,
+    delete_cancel_but_state: button::State,
+    /// The message that will be deleted if the user confirms.
+    pub message_id: Option<u64>,
+}
+
+impl DeleteMessageModal {
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        let make_button = |state, confirm| {
+            let text = if confirm { "Yes" } else { "No" };
+
+            label_button!(state, text)
+                .style(theme)
+                .on_press(confirm)
+                .width(length!(= 80))
+        };
+
+        let delete_confirm_panel = column(vec![
+            label!("Do you want to delete this message?")
+                .size(DEF_SIZE + 2)
+                .into(),
+            label!("This can't be undone.")
+                .color(ERROR_COLOR)
+                .size(DEF_SIZE + 2)
+                .into(),
+            row(vec![
+                make_button(&mut self.delete_approve_but_state, true).into(),
+                space!(w = 200).into(),
+                make_button(&mut self.delete_cancel_but_state, false).into(),
+            ])
+            .into(),
+        ])
+        .spacing(12);
+
+        Container::new(delete_confirm_panel)
+            .style(theme.round())
+            .center_x()
+            .center_y()
+            .into()
+    }
+}
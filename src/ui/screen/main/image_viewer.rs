@@ -1,9 +1,8 @@
 use std::path::PathBuf;
 
 use crate::{
-    client::content::ImageHandle,
     label_button, length, space,
-    ui::{component::*, style::Theme},
+    ui::{component::*, style::Theme, thumbnail::ImageHandle},
 };
 
 use iced::image::{viewer, Viewer};
@@ -61,8 +61,12 @@ impl LogoutModal {
             let inner = client.inner().clone();
             Command::perform(
                 async move {
-                    let result =
-                        Client::logout(inner, content_store.session_file().to_path_buf()).await;
+                    let result = Client::logout(
+                        inner,
+                        content_store.session_file().to_path_buf(),
+                        content_store.cache_dir().to_path_buf(),
+                    )
+                    .await;
 
                     result.map_or_else(
                         |err| super::super::Message::Error(Box::new(err)),
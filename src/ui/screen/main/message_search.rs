@@ -0,0 +1,251 @@
+use std::fmt::{self, Display, Formatter};
+
+use chrono::NaiveDateTime;
+use iced::Checkbox;
+
+use crate::{
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{
+            Theme, ALT_COLOR, DEF_SIZE, ERROR_COLOR, MESSAGE_SIZE, MESSAGE_TIMESTAMP_SIZE, PADDING,
+            SPACING,
+        },
+    },
+};
+
+/// A guild or channel to search in, `id` being `None` for all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeChoice {
+    pub id: Option<u64>,
+    pub name: String,
+}
+
+impl Display for ScopeChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShownResult {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub sender_name: String,
+    /// Where the message was sent, like `#general (Guild)`.
+    pub location: String,
+    pub timestamp: NaiveDateTime,
+    pub snippet: String,
+}
+
+fn date_input<'a>(
+    theme: Theme,
+    state: &'a mut text_input::State,
+    placeholder: &str,
+    value: &str,
+    on_change: fn(String) -> Message,
+) -> TextInput<'a, Message> {
+    TextInput::new(state, placeholder, value, on_change)
+        .padding(PADDING / 4)
+        .size(MESSAGE_SIZE)
+        .width(length!(= 140))
+        .style(theme)
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    TextChanged(String),
+    SenderChanged(String),
+    GuildSelected(ScopeChoice),
+    ChannelSelected(ScopeChoice),
+    FromChanged(String),
+    ToChanged(String),
+    HasAttachmentToggled(bool),
+    /// Sent when the user wants to see a result in its channel.
+    Jump {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub struct MessageSearchModal {
+    text_state: text_input::State,
+    sender_state: text_input::State,
+    guild_state: pick_list::State<ScopeChoice>,
+    channel_state: pick_list::State<ScopeChoice>,
+    from_state: text_input::State,
+    to_state: text_input::State,
+    results_list_state: scrollable::State,
+    results_buts_state: Vec<button::State>,
+    close_but_state: button::State,
+    pub text_value: String,
+    pub sender_value: String,
+    pub guild_options: Vec<ScopeChoice>,
+    pub guild: Option<ScopeChoice>,
+    pub channel_options: Vec<ScopeChoice>,
+    pub channel: Option<ScopeChoice>,
+    pub from_value: String,
+    pub to_value: String,
+    pub has_attachment: bool,
+    pub results: Vec<ShownResult>,
+    pub error_text: String,
+}
+
+impl MessageSearchModal {
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        self.text_state.focus();
+        self.results_buts_state
+            .resize_with(self.results.len(), Default::default);
+
+        let search_bar = TextInput::new(
+            &mut self.text_state,
+            "Search messages",
+            &self.text_value,
+            Message::TextChanged,
+        )
+        .padding(PADDING / 2)
+        .size(DEF_SIZE + 4)
+        .style(theme);
+
+        let filters = row(vec![
+            TextInput::new(
+                &mut self.sender_state,
+                "From user",
+                &self.sender_value,
+                Message::SenderChanged,
+            )
+            .padding(PADDING / 4)
+            .size(MESSAGE_SIZE)
+            .width(length!(= 160))
+            .style(theme)
+            .into(),
+            PickList::new(
+                &mut self.guild_state,
+                self.guild_options.clone(),
+                self.guild.clone(),
+                Message::GuildSelected,
+            )
+            .width(length!(= 160))
+            .style(theme)
+            .into(),
+            PickList::new(
+                &mut self.channel_state,
+                self.channel_options.clone(),
+                self.channel.clone(),
+                Message::ChannelSelected,
+            )
+            .width(length!(= 160))
+            .style(theme)
+            .into(),
+            date_input(
+                theme,
+                &mut self.from_state,
+                "After YYYY-MM-DD",
+                &self.from_value,
+                Message::FromChanged,
+            )
+            .into(),
+            date_input(
+                theme,
+                &mut self.to_state,
+                "Before YYYY-MM-DD",
+                &self.to_value,
+                Message::ToChanged,
+            )
+            .into(),
+            Checkbox::new(
+                self.has_attachment,
+                "Has attachment",
+                Message::HasAttachmentToggled,
+            )
+            .size(MESSAGE_SIZE)
+            .style(theme)
+            .into(),
+        ])
+        .align_items(Align::Center)
+        .padding(0);
+
+        let mut results_list = Scrollable::new(&mut self.results_list_state)
+            .align_items(Align::Start)
+            .height(length!(+))
+            .width(length!(+))
+            .spacing(SPACING * 2)
+            .style(theme);
+
+        if !self.error_text.is_empty() {
+            results_list = results_list.push(label!(&self.error_text).color(ERROR_COLOR));
+        } else if self.results.is_empty() {
+            results_list = results_list.push(label!("No messages found").color(ALT_COLOR));
+        }
+
+        for (result, state) in self.results.iter().zip(self.results_buts_state.iter_mut()) {
+            let content = column(vec![
+                row(vec![
+                    label!("[{}]", result.sender_name).size(MESSAGE_SIZE).into(),
+                    label!(&result.location)
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(ALT_COLOR)
+                        .into(),
+                    label!(result.timestamp.format("%d %B %Y %H:%M").to_string())
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(ALT_COLOR)
+                        .into(),
+                ])
+                .align_items(Align::Center)
+                .padding(0)
+                .into(),
+                label!(&result.snippet).size(MESSAGE_SIZE).into(),
+            ])
+            .padding(0)
+            .spacing(SPACING);
+
+            results_list = results_list.push(
+                Button::new(state, content)
+                    .on_press(Message::Jump {
+                        guild_id: result.guild_id,
+                        channel_id: result.channel_id,
+                        message_id: result.message_id,
+                    })
+                    .width(length!(+))
+                    .style(theme.secondary()),
+            );
+        }
+
+        let panel = column(vec![
+            row(vec![
+                search_bar.width(length!(+)).into(),
+                label_button!(&mut self.close_but_state, "Close")
+                    .style(theme)
+                    .on_press(Message::Close)
+                    .width(length!(= 80))
+                    .into(),
+            ])
+            .align_items(Align::Center)
+            .padding(0)
+            .into(),
+            filters.into(),
+            results_list.into(),
+        ])
+        .padding(PADDING / 2);
+
+        Row::with_children(vec![
+            space!(w % 1).into(),
+            Column::with_children(vec![
+                space!(h % 1).into(),
+                Container::new(panel)
+                    .style(theme.round())
+                    .height(length!(%8))
+                    .into(),
+                space!(h % 1).into(),
+            ])
+            .width(length!(%8))
+            .into(),
+            space!(w % 1).into(),
+        ])
+        .into()
+    }
+}
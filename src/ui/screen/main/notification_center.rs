@@ -0,0 +1,119 @@
+use crate::{
+    client::notification::Notification,
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{
+            Theme, ALT_COLOR, DEF_SIZE, MESSAGE_SIZE, MESSAGE_TIMESTAMP_SIZE, PADDING, SPACING,
+        },
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Sent when the user wants to see the message of a notification.
+    Open(u64),
+    ClearAll,
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub struct NotificationCenterModal {
+    notifications_list_state: scrollable::State,
+    notifications_buts_state: Vec<button::State>,
+    clear_but_state: button::State,
+    close_but_state: button::State,
+    pub notifications: Vec<Notification>,
+}
+
+impl NotificationCenterModal {
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        self.notifications_buts_state
+            .resize_with(self.notifications.len(), Default::default);
+
+        let mut notifications_list = Scrollable::new(&mut self.notifications_list_state)
+            .align_items(Align::Start)
+            .height(length!(+))
+            .width(length!(+))
+            .spacing(SPACING * 2)
+            .style(theme);
+
+        if self.notifications.is_empty() {
+            notifications_list = notifications_list
+                .push(label!("You don't have any notifications").color(ALT_COLOR));
+        }
+
+        for (notification, state) in self
+            .notifications
+            .iter()
+            .zip(self.notifications_buts_state.iter_mut())
+        {
+            let title = label!(&notification.title).size(MESSAGE_SIZE);
+            let content = column(vec![
+                row(vec![
+                    if notification.read {
+                        title.color(ALT_COLOR).into()
+                    } else {
+                        title.into()
+                    },
+                    label!(notification.timestamp.format("%d %B %Y %H:%M").to_string())
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(ALT_COLOR)
+                        .into(),
+                ])
+                .align_items(Align::Center)
+                .padding(0)
+                .into(),
+                label!(&notification.body).size(MESSAGE_SIZE).into(),
+            ])
+            .padding(0)
+            .spacing(SPACING);
+
+            notifications_list = notifications_list.push(
+                Button::new(state, content)
+                    .on_press(Message::Open(notification.id))
+                    .width(length!(+))
+                    .style(theme.secondary()),
+            );
+        }
+
+        let panel = column(vec![
+            row(vec![
+                label!("Notifications")
+                    .size(DEF_SIZE + 2)
+                    .width(length!(+))
+                    .into(),
+                label_button!(&mut self.clear_but_state, "Clear")
+                    .style(theme)
+                    .on_press(Message::ClearAll)
+                    .width(length!(= 80))
+                    .into(),
+                label_button!(&mut self.close_but_state, "Close")
+                    .style(theme)
+                    .on_press(Message::Close)
+                    .width(length!(= 80))
+                    .into(),
+            ])
+            .align_items(Align::Center)
+            .into(),
+            notifications_list.into(),
+        ])
+        .padding(PADDING / 2);
+
+        Row::with_children(vec![
+            space!(w % 2).into(),
+            Column::with_children(vec![
+                space!(h % 2).into(),
+                Container::new(panel)
+                    .style(theme.round())
+                    .height(length!(%6))
+                    .into(),
+                space!(h % 2).into(),
+            ])
+            .width(length!(%6))
+            .into(),
+            space!(w % 2).into(),
+        ])
+        .into()
+    }
+}
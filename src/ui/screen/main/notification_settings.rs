@@ -0,0 +1,231 @@
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+
+use crate::{
+    client::notification_rules::{NotifyLevel, ScopeRules},
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{Theme, ALT_COLOR, DEF_SIZE, MESSAGE_SIZE, PADDING, SPACING},
+    },
+};
+
+/// Level of a guild or channel, which can be left to the parent scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelChoice {
+    Inherit,
+    Level(NotifyLevel),
+}
+
+impl LevelChoice {
+    const ALL: [LevelChoice; 4] = [
+        LevelChoice::Inherit,
+        LevelChoice::Level(NotifyLevel::ALL[0]),
+        LevelChoice::Level(NotifyLevel::ALL[1]),
+        LevelChoice::Level(NotifyLevel::ALL[2]),
+    ];
+}
+
+impl From<Option<NotifyLevel>> for LevelChoice {
+    fn from(level: Option<NotifyLevel>) -> Self {
+        level.map_or(LevelChoice::Inherit, LevelChoice::Level)
+    }
+}
+
+impl Display for LevelChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelChoice::Inherit => f.write_str("Default"),
+            LevelChoice::Level(level) => Display::fmt(level, f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuteChoice {
+    NotMuted,
+    /// Mute for the given amount of hours.
+    For(i64),
+    Forever,
+    /// Only used to show the current mute, in UTC like the mutes of the rules.
+    Until(NaiveDateTime),
+}
+
+impl MuteChoice {
+    const ALL: [MuteChoice; 5] = [
+        MuteChoice::NotMuted,
+        MuteChoice::For(1),
+        MuteChoice::For(8),
+        MuteChoice::For(24),
+        MuteChoice::Forever,
+    ];
+
+    fn current(rules: &ScopeRules, now: NaiveDateTime) -> Self {
+        if !rules.is_muted(now) {
+            MuteChoice::NotMuted
+        } else {
+            rules
+                .muted_until
+                .map_or(MuteChoice::Forever, MuteChoice::Until)
+        }
+    }
+}
+
+impl Display for MuteChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MuteChoice::NotMuted => f.write_str("Not muted"),
+            MuteChoice::For(1) => f.write_str("Mute for 1 hour"),
+            MuteChoice::For(hours) => write!(f, "Mute for {} hours", hours),
+            MuteChoice::Forever => f.write_str("Muted until unmuted"),
+            MuteChoice::Until(until) => {
+                let until = Local.from_utc_datetime(until);
+                write!(f, "Muted until {}", until.format("%d %B %H:%M"))
+            }
+        }
+    }
+}
+
+/// Builds the row with the level and mute of a guild or channel.
+#[allow(clippy::clippy::too_many_arguments)]
+fn scope_row<'a>(
+    theme: Theme,
+    name: String,
+    level_state: &'a mut pick_list::State<LevelChoice>,
+    level: LevelChoice,
+    on_level: fn(LevelChoice) -> Message,
+    mute_state: &'a mut pick_list::State<MuteChoice>,
+    mute: MuteChoice,
+    on_mute: fn(MuteChoice) -> Message,
+) -> Row<'a, Message> {
+    row(vec![
+        label!(name).size(MESSAGE_SIZE).width(length!(+)).into(),
+        PickList::new(level_state, &LevelChoice::ALL[..], Some(level), on_level)
+            .width(length!(= 180))
+            .style(theme)
+            .into(),
+        PickList::new(mute_state, &MuteChoice::ALL[..], Some(mute), on_mute)
+            .width(length!(= 240))
+            .style(theme)
+            .into(),
+    ])
+    .align_items(Align::Center)
+    .padding(0)
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    GuildLevel(LevelChoice),
+    ChannelLevel(LevelChoice),
+    GuildMute(MuteChoice),
+    ChannelMute(MuteChoice),
+    KeywordsChanged(String),
+    SaveKeywords,
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub struct NotificationSettingsModal {
+    guild_level_state: pick_list::State<LevelChoice>,
+    channel_level_state: pick_list::State<LevelChoice>,
+    guild_mute_state: pick_list::State<MuteChoice>,
+    channel_mute_state: pick_list::State<MuteChoice>,
+    keywords_state: text_input::State,
+    close_but_state: button::State,
+    pub guild_id: Option<u64>,
+    pub channel_id: Option<u64>,
+    pub guild_name: String,
+    pub channel_name: String,
+    pub guild_rules: ScopeRules,
+    pub channel_rules: ScopeRules,
+    /// Highlight keywords, separated by commas.
+    pub keywords_value: String,
+}
+
+impl NotificationSettingsModal {
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        let now = chrono::Utc::now().naive_utc();
+
+        let mut widgets = vec![
+            row(vec![
+                label!("Notification settings")
+                    .size(DEF_SIZE + 2)
+                    .width(length!(+))
+                    .into(),
+                label_button!(&mut self.close_but_state, "Close")
+                    .style(theme)
+                    .on_press(Message::Close)
+                    .width(length!(= 80))
+                    .into(),
+            ])
+            .align_items(Align::Center)
+            .into(),
+            scope_row(
+                theme,
+                self.guild_name.clone(),
+                &mut self.guild_level_state,
+                self.guild_rules.level.into(),
+                Message::GuildLevel,
+                &mut self.guild_mute_state,
+                MuteChoice::current(&self.guild_rules, now),
+                Message::GuildMute,
+            )
+            .into(),
+        ];
+
+        if self.channel_id.is_some() {
+            widgets.push(
+                scope_row(
+                    theme,
+                    format!("#{}", self.channel_name),
+                    &mut self.channel_level_state,
+                    self.channel_rules.level.into(),
+                    Message::ChannelLevel,
+                    &mut self.channel_mute_state,
+                    MuteChoice::current(&self.channel_rules, now),
+                    Message::ChannelMute,
+                )
+                .into(),
+            );
+        }
+
+        widgets.push(
+            label!("Highlight keywords, separated by commas. Put regexes between slashes.")
+                .size(MESSAGE_SIZE)
+                .color(ALT_COLOR)
+                .into(),
+        );
+        widgets.push(
+            TextInput::new(
+                &mut self.keywords_state,
+                "urgent, /deploy(ed|ing)?/",
+                &self.keywords_value,
+                Message::KeywordsChanged,
+            )
+            .padding(PADDING / 2)
+            .size(MESSAGE_SIZE)
+            .style(theme)
+            .on_submit(Message::SaveKeywords)
+            .into(),
+        );
+
+        let panel = column(widgets).spacing(SPACING * 2).padding(PADDING / 2);
+
+        Row::with_children(vec![
+            space!(w % 2).into(),
+            Column::with_children(vec![
+                space!(h % 3).into(),
+                Container::new(panel)
+                    .style(theme.round())
+                    .height(length!(%4))
+                    .into(),
+                space!(h % 3).into(),
+            ])
+            .width(length!(%6))
+            .into(),
+            space!(w % 2).into(),
+        ])
+        .into()
+    }
+}
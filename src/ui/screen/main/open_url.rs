@@ -0,0 +1,79 @@
+use harmony_rust_sdk::api::exports::hrpc::url::Url;
+
+use crate::{
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{Theme, ALT_COLOR, DEF_SIZE, ERROR_COLOR, MESSAGE_SIZE},
+    },
+};
+
+pub type Message = bool;
+
+#[derive(Debug, Default)]
+pub struct OpenUrlModal {
+    open_approve_but_state: button::State,
+    open_cancel_but_state: button::State,
+    /// The URL that will be opened if the user confirms.
+    pub url: Option<String>,
+    /// Host of the URL, `None` if the URL can't be opened.
+    domain: Option<String>,
+}
+
+impl OpenUrlModal {
+    /// Sets the URL to confirm.
+    ///
+    /// The domain is taken from the parsed URL, so link text that looks like another URL can't
+    /// hide where the link goes. Internationalized domains are shown in their ASCII form, which
+    /// gives away lookalike characters.
+    pub fn set_url(&mut self, url: String) {
+        self.domain = Url::parse(&url)
+            .ok()
+            .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+            .map(|parsed| parsed.host_str().map(str::to_string))
+            .flatten();
+        self.url = Some(url);
+    }
+
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        let can_open = self.domain.is_some();
+        let make_button = |state, confirm| {
+            let text = if confirm { "Open" } else { "Cancel" };
+
+            let button = label_button!(state, text).style(theme).width(length!(= 80));
+            if confirm && !can_open {
+                button
+            } else {
+                button.on_press(confirm)
+            }
+        };
+
+        let title = match &self.domain {
+            Some(domain) => label!("Open a link to {}?", domain).size(DEF_SIZE + 2),
+            None => label!("Only web links can be opened.")
+                .color(ERROR_COLOR)
+                .size(DEF_SIZE + 2),
+        };
+
+        let open_confirm_panel = column(vec![
+            title.into(),
+            label!(self.url.as_deref().unwrap_or(""))
+                .color(ALT_COLOR)
+                .size(MESSAGE_SIZE)
+                .into(),
+            row(vec![
+                make_button(&mut self.open_approve_but_state, true).into(),
+                space!(w = 200).into(),
+                make_button(&mut self.open_cancel_but_state, false).into(),
+            ])
+            .into(),
+        ])
+        .spacing(12);
+
+        Container::new(open_confirm_panel)
+            .style(theme.round())
+            .center_x()
+            .center_y()
+            .into()
+    }
+}
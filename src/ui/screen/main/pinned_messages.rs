@@ -0,0 +1,127 @@
+use chrono::NaiveDateTime;
+
+use crate::{
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{
+            Theme, ALT_COLOR, DEF_SIZE, MESSAGE_SIZE, MESSAGE_TIMESTAMP_SIZE, PADDING, SPACING,
+        },
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PinnedMessage {
+    pub message_id: u64,
+    pub sender_name: String,
+    pub content: String,
+    pub timestamp: NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Sent when the pinned messages of a channel are fetched.
+    Loaded {
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<crate::client::message::Message>,
+    },
+    /// Sent when the user wants to see a pinned message in the timeline.
+    Jump(u64),
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub struct PinnedMessagesModal {
+    messages_list_state: scrollable::State,
+    messages_buts_state: Vec<button::State>,
+    close_but_state: button::State,
+    pub messages: Vec<PinnedMessage>,
+    pub loading: bool,
+}
+
+impl PinnedMessagesModal {
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        self.messages_buts_state
+            .resize_with(self.messages.len(), Default::default);
+
+        let mut messages_list = Scrollable::new(&mut self.messages_list_state)
+            .align_items(Align::Start)
+            .height(length!(+))
+            .width(length!(+))
+            .spacing(SPACING * 2)
+            .style(theme);
+
+        if self.loading {
+            messages_list = messages_list.push(label!("Loading pinned messages..."));
+        } else if self.messages.is_empty() {
+            messages_list =
+                messages_list.push(label!("There are no pinned messages here").color(ALT_COLOR));
+        }
+
+        for (message, state) in self
+            .messages
+            .iter()
+            .zip(self.messages_buts_state.iter_mut())
+        {
+            let content = column(vec![
+                row(vec![
+                    label!("[{}]", message.sender_name)
+                        .size(MESSAGE_SIZE)
+                        .into(),
+                    label!(message.timestamp.format("%d %B %Y %H:%M").to_string())
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(ALT_COLOR)
+                        .into(),
+                ])
+                .align_items(Align::Center)
+                .padding(0)
+                .into(),
+                label!(&message.content).size(MESSAGE_SIZE).into(),
+            ])
+            .padding(0)
+            .spacing(SPACING);
+
+            messages_list = messages_list.push(
+                Button::new(state, content)
+                    .on_press(Message::Jump(message.message_id))
+                    .width(length!(+))
+                    .style(theme.secondary()),
+            );
+        }
+
+        let panel = column(vec![
+            row(vec![
+                label!("Pinned messages")
+                    .size(DEF_SIZE + 2)
+                    .width(length!(+))
+                    .into(),
+                label_button!(&mut self.close_but_state, "Close")
+                    .style(theme)
+                    .on_press(Message::Close)
+                    .width(length!(= 80))
+                    .into(),
+            ])
+            .align_items(Align::Center)
+            .into(),
+            messages_list.into(),
+        ])
+        .padding(PADDING / 2);
+
+        Row::with_children(vec![
+            space!(w % 2).into(),
+            Column::with_children(vec![
+                space!(h % 2).into(),
+                Container::new(panel)
+                    .style(theme.round())
+                    .height(length!(%6))
+                    .into(),
+                space!(h % 2).into(),
+            ])
+            .width(length!(%6))
+            .into(),
+            space!(w % 2).into(),
+        ])
+        .into()
+    }
+}
@@ -1,3 +1,5 @@
+pub use crate::client::quick_switch::SearchResult;
+
 use crate::{
     label_button, length, space,
     ui::{
@@ -13,19 +15,6 @@ pub enum Message {
     SearchTermChanged(String),
 }
 
-#[derive(Debug, Clone)]
-pub enum SearchResult {
-    Guild {
-        id: u64,
-        name: String,
-    },
-    Channel {
-        guild_id: u64,
-        id: u64,
-        name: String,
-    },
-}
-
 #[derive(Debug, Default)]
 pub struct QuickSwitcherModal {
     search_state: text_input::State,
@@ -0,0 +1,87 @@
+use crate::{
+    label, label_button, length, space,
+    ui::{
+        component::*,
+        style::{Theme, DEF_SIZE, PADDING, SPACING},
+    },
+};
+
+/// Emotes that can be picked with a single click.
+pub const QUICK_REACTIONS: [&str; 8] = ["👍", "👎", "😄", "🎉", "😕", "❤️", "🚀", "👀"];
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    EmoteChanged(String),
+    /// Sent when the user picks an emote to react with.
+    Picked(String),
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub struct ReactionPickerModal {
+    emote_input_state: text_input::State,
+    quick_buts_state: [button::State; QUICK_REACTIONS.len()],
+    react_but_state: button::State,
+    close_but_state: button::State,
+    pub emote_value: String,
+    /// The message that will be reacted to.
+    pub message_id: Option<u64>,
+}
+
+impl ReactionPickerModal {
+    pub fn view(&mut self, theme: Theme) -> Element<Message> {
+        let emote = self.emote_value.trim().to_string();
+
+        let mut emote_input = TextInput::new(
+            &mut self.emote_input_state,
+            "Type an emoji...",
+            &self.emote_value,
+            Message::EmoteChanged,
+        )
+        .padding(PADDING / 2)
+        .size(DEF_SIZE + 4)
+        .width(length!(= 200))
+        .style(theme);
+        let mut react = label_button!(&mut self.react_but_state, "React").style(theme);
+
+        if !emote.is_empty() {
+            emote_input = emote_input.on_submit(Message::Picked(emote.clone()));
+            react = react.on_press(Message::Picked(emote));
+        }
+
+        let quick_reactions = QUICK_REACTIONS
+            .iter()
+            .zip(self.quick_buts_state.iter_mut())
+            .map(|(emote, state)| {
+                Button::new(state, fill_container(label!(*emote).size(DEF_SIZE + 4)))
+                    .style(theme)
+                    .on_press(Message::Picked(emote.to_string()))
+                    .into()
+            })
+            .collect();
+
+        let picker_panel = column(vec![
+            label!("Add a reaction").size(DEF_SIZE + 2).into(),
+            row(quick_reactions).spacing(SPACING).into(),
+            row(vec![
+                emote_input.into(),
+                react.width(length!(= 80)).into(),
+                space!(w = 40).into(),
+                label_button!(&mut self.close_but_state, "Close")
+                    .style(theme)
+                    .on_press(Message::Close)
+                    .width(length!(= 80))
+                    .into(),
+            ])
+            .spacing(SPACING)
+            .into(),
+        ])
+        .spacing(12);
+
+        Container::new(picker_panel)
+            .style(theme.round())
+            .center_x()
+            .center_y()
+            .into()
+    }
+}
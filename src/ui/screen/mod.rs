@@ -1,3 +1,4 @@
+pub mod client_cmds;
 pub mod guild_discovery;
 pub mod login;
 pub mod main;
@@ -8,38 +9,32 @@ pub use main::MainScreen;
 
 use crate::{
     client::{
-        content::{ContentStore, ImageHandle, ThumbnailCache},
+        cache::write_cache_files,
+        content::ContentStore,
         error::ClientError,
+        event_stream::{EventStreamUpdates, StreamUpdate},
+        federation::ForeignHomeserver,
         message::{Message as IcyMessage, MessageId},
-        Client, PostProcessEvent, Session,
+        Client, PostProcessEvent,
+    },
+    ui::{
+        component::event_history::SHOWN_MSGS_LIMIT,
+        style::Theme,
+        thumbnail::{ImageHandle, ThumbnailCache},
     },
-    ui::style::Theme,
 };
+use client_cmds::ClientCmds;
 
 use harmony_rust_sdk::{
-    api::{
-        chat::{
-            event::{Event, GuildAddedToList, GuildUpdated, ProfileUpdated},
-            GetGuildListRequest,
-        },
-        exports::hrpc::url::Url,
-    },
-    client::{
-        api::{
-            auth::AuthStepResponse,
-            chat::{
-                guild::{get_guild, get_guild_list},
-                profile::{get_user, get_user_bulk},
-                EventSource, GuildId, UserId,
-            },
-            harmonytypes::Message as HarmonyMessage,
-            rest::FileId,
-        },
-        EventsSocket,
-    },
+    api::{chat::event::Event, exports::hrpc::url::Url},
+    client::api::{auth::AuthStepResponse, harmonytypes::Message as HarmonyMessage, rest::FileId},
 };
 use iced::{executor, Application, Command, Element, Subscription};
-use std::{sync::Arc, time::Duration};
+use indexmap::IndexMap;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+/// How often the local cache is written to disk.
+const SAVE_CACHE_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub enum Message {
@@ -49,8 +44,30 @@ pub enum Message {
     PopScreen,
     PushScreen(Box<Screen>),
     Logout(Box<Screen>),
+    /// Sent when logging into an account is complete, or an account was restored from its
+    /// session file.
     LoginComplete(Option<Client>),
     ClientCreated(Client),
+    /// Sent when the user wants to log into another account.
+    AddAccount,
+    /// Sent when the user wants to see another account they are logged into.
+    SwitchAccount(u64),
+    /// A message that belongs to the account with the given user ID.
+    ///
+    /// Messages produced by commands are tagged with the account that was shown when the
+    /// command was created, so they reach the right account even after switching.
+    Account {
+        user_id: u64,
+        message: Box<Message>,
+    },
+    /// Sent when the user wants to try connecting to the homeserver again.
+    Reconnect,
+    /// Sent periodically to write changed parts of the local cache to disk.
+    SaveCache,
+    /// Sent when the user closes the window, the local cache is saved before we exit.
+    CloseRequested,
+    /// Sent when we are ready to exit.
+    Exit,
     Nothing,
     DownloadedThumbnail {
         thumbnail_url: FileId,
@@ -58,9 +75,20 @@ pub enum Message {
         open: bool,
     },
     EventsReceived(Vec<Event>),
-    SocketEvent {
-        socket: Box<EventsSocket>,
-        event: Option<harmony_rust_sdk::client::error::ClientResult<Event>>,
+    /// Something happened on the event stream of our homeserver, or of a homeserver that hosts
+    /// some of our guilds.
+    EventStream {
+        updates: Box<EventStreamUpdates>,
+        update: Option<StreamUpdate>,
+    },
+    /// Sent when we logged into a homeserver that hosts some of our guilds.
+    Federated {
+        homeserver: Url,
+        foreign: Box<ForeignHomeserver>,
+    },
+    FederationFailed {
+        homeserver: Url,
+        err: Box<ClientError>,
     },
     GetEventsBackwardsResponse {
         messages: Vec<HarmonyMessage>,
@@ -68,6 +96,14 @@ pub enum Message {
         guild_id: u64,
         channel_id: u64,
     },
+    /// Messages that were missed in a channel while we weren't connected.
+    Backfill {
+        guild_id: u64,
+        channel_id: u64,
+        messages: Vec<HarmonyMessage>,
+        gaps: Vec<u64>,
+        reached_top: bool,
+    },
     MessageSent {
         message_id: u64,
         transaction_id: u64,
@@ -76,7 +112,6 @@ pub enum Message {
     },
     SendMessage {
         message: IcyMessage,
-        retry_after: Duration,
         guild_id: u64,
         channel_id: u64,
     },
@@ -84,7 +119,41 @@ pub enum Message {
         guild_id: u64,
         channel_id: u64,
         message_id: u64,
-        err: Option<Box<ClientError>>,
+        transaction_id: u64,
+    },
+    /// Sent when sending an outbox entry fails.
+    OutboxAttemptFailed {
+        transaction_id: u64,
+        err: Box<ClientError>,
+    },
+    /// Sent when a message is pinned or unpinned by us.
+    MessagePinned {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        pinned: bool,
+    },
+    /// Sent when deleting a message fails, so it can be put back.
+    MessageDeleteFailed {
+        guild_id: u64,
+        channel_id: u64,
+        message: Box<IcyMessage>,
+        err: Box<ClientError>,
+    },
+    /// Sent when fetching a guild, or the latest messages of a channel, fails.
+    FetchFailed {
+        guild_id: u64,
+        channel_id: Option<u64>,
+        err: Box<ClientError>,
+    },
+    /// Sent when the homeserver rejects adding or removing one of our reactions.
+    ReactionFailed {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        emote: String,
+        added: bool,
+        err: Box<ClientError>,
     },
     /// Sent whenever an error occurs.
     Error(Box<ClientError>),
@@ -163,14 +232,67 @@ impl ScreenStack {
     }
 }
 
+/// What the account switcher shows about an account.
+#[derive(Debug, Clone)]
+pub struct AccountSummary {
+    pub user_id: u64,
+    pub name: String,
+    /// How many messages were received since the account was last shown.
+    pub unread: usize,
+}
+
+/// An account we are logged into, but isn't the one being shown.
+///
+/// Its event stream is kept alive, and its messages are handled by swapping it with the shown
+/// account for a moment.
+struct BackgroundAccount {
+    client: Client,
+    screen: Box<MainScreen>,
+    unread: usize,
+}
+
+impl BackgroundAccount {
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            screen: Box::new(MainScreen::default()),
+            unread: 0,
+        }
+    }
+
+    fn summary(&self) -> AccountSummary {
+        let user_id = self.client.user_id.unwrap_or_default();
+        let username = self
+            .client
+            .members
+            .get(&user_id)
+            .map_or_else(|| String::from("unknown"), |member| member.username.clone());
+        let homeserver = self.client.inner().homeserver_url();
+
+        AccountSummary {
+            user_id,
+            name: format!(
+                "{}@{}",
+                username,
+                homeserver.host_str().unwrap_or("unknown")
+            ),
+            unread: self.unread,
+        }
+    }
+}
+
 pub struct ScreenManager {
     theme: Theme,
     screens: ScreenStack,
     client: Option<Client>,
+    /// The client that is used while logging into an account.
+    login_client: Option<Client>,
+    accounts: Vec<BackgroundAccount>,
+    /// Whether a message of a background account is being handled.
+    in_background: bool,
     content_store: Arc<ContentStore>,
     thumbnail_cache: ThumbnailCache,
-    sources_to_add: Vec<EventSource>,
-    socket_reset: bool,
+    should_exit: bool,
 }
 
 impl ScreenManager {
@@ -179,44 +301,23 @@ impl ScreenManager {
             theme: Theme::default(),
             screens: ScreenStack::new(Screen::Login(LoginScreen::new(content_store.clone()))),
             client: None,
+            login_client: None,
+            accounts: Vec::new(),
+            in_background: false,
             content_store,
             thumbnail_cache: ThumbnailCache::default(),
-            sources_to_add: vec![],
-            socket_reset: false,
+            should_exit: false,
         }
     }
 
     fn process_post_event(&mut self, post: PostProcessEvent) -> Command<Message> {
         if let Some(client) = self.client.as_mut() {
             match post {
-                PostProcessEvent::FetchThumbnail(id) => {
-                    return make_thumbnail_command(client, id, &self.thumbnail_cache);
-                }
-                PostProcessEvent::FetchProfile(user_id) => {
-                    let inner = client.inner().clone();
-                    return Command::perform(
-                        async move {
-                            let profile = get_user(&inner, UserId::new(user_id)).await?;
-                            let event = Event::ProfileUpdated(ProfileUpdated {
-                                user_id,
-                                new_avatar: profile.user_avatar,
-                                new_status: profile.user_status,
-                                new_username: profile.user_name,
-                                is_bot: profile.is_bot,
-                                update_is_bot: true,
-                                update_status: true,
-                                update_avatar: true,
-                                update_username: true,
-                            });
-                            Ok(vec![event])
-                        },
-                        |result| {
-                            result.map_or_else(
-                                |err| Message::Error(Box::new(err)),
-                                Message::EventsReceived,
-                            )
-                        },
-                    );
+                PostProcessEvent::FetchThumbnail { guild_id, id } => {
+                    return make_thumbnail_command(client, guild_id, id, &self.thumbnail_cache);
+                }
+                PostProcessEvent::FetchProfile { guild_id, user_id } => {
+                    return client.fetch_profiles_cmd(guild_id, vec![user_id]);
                 }
                 PostProcessEvent::GoToFirstMsgOnChannel(channel_id) => {
                     if let Some(Screen::Main(screen)) = self
@@ -225,6 +326,10 @@ impl ScreenManager {
                         .iter_mut()
                         .find(|screen| matches!(screen, Screen::Main(_)))
                     {
+                        // Don't pull the user away from older messages they scrolled up to
+                        if !screen.shows_latest_messages(client, channel_id) {
+                            return Command::none();
+                        }
                         return screen.update(
                             main::Message::ScrollToBottom(channel_id),
                             client,
@@ -233,34 +338,71 @@ impl ScreenManager {
                     }
                 }
                 PostProcessEvent::FetchGuildData(guild_id) => {
-                    let inner = client.inner().clone();
-                    return Command::perform(
-                        async move {
-                            let guild_data = get_guild(&inner, GuildId::new(guild_id)).await?;
-                            let event = Event::EditedGuild(GuildUpdated {
-                                guild_id,
-                                metadata: guild_data.metadata,
-                                name: guild_data.guild_name,
-                                picture: guild_data.guild_picture,
-                                update_name: true,
-                                update_picture: true,
-                                update_metadata: true,
-                            });
-                            Ok(vec![event])
-                        },
-                        |result| {
-                            result.map_or_else(
-                                |err| Message::Error(Box::new(err)),
-                                Message::EventsReceived,
-                            )
-                        },
-                    );
+                    return client
+                        .fetch_guild_data_cmd(guild_id)
+                        .unwrap_or_else(Command::none);
+                }
+                PostProcessEvent::FetchPermissions {
+                    guild_id,
+                    channel_ids,
+                } => {
+                    return client.fetch_permissions_cmd(guild_id, channel_ids);
+                }
+                PostProcessEvent::Federate(homeserver) => {
+                    return client
+                        .federate_cmd(homeserver)
+                        .unwrap_or_else(Command::none);
+                }
+                PostProcessEvent::Notify(notification) => {
+                    let main_screen = self.screens.stack.iter_mut().find_map(|screen| {
+                        if let Screen::Main(screen) = screen {
+                            Some(screen)
+                        } else {
+                            None
+                        }
+                    });
+
+                    // Don't notify about messages the user is looking at
+                    let is_shown = !self.in_background
+                        && main_screen.as_ref().map(|screen| screen.current_channel())
+                            == Some(Some((notification.guild_id, notification.channel_id)));
+                    if !is_shown {
+                        let id = client.notifications.push(*notification);
+                        // Toasts of background accounts would never be seen
+                        if let (false, Some(screen)) = (self.in_background, main_screen) {
+                            return screen.update(
+                                main::Message::ShowToast(id),
+                                client,
+                                &self.thumbnail_cache,
+                            );
+                        }
+                    }
                 }
                 PostProcessEvent::Nothing => {}
             }
         }
         Command::none()
     }
+
+    /// Takes the changed parts of the local caches of every account we are logged into.
+    fn take_dirty_cache(&mut self) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        let background_clients = self.accounts.iter_mut().map(|account| &mut account.client);
+        for client in self.client.iter_mut().chain(background_clients) {
+            match client.take_dirty_cache() {
+                Ok(client_files) => files.extend(client_files),
+                Err(err) => tracing::error!("couldn't serialize the local cache: {}", err),
+            }
+        }
+        files
+    }
+
+    /// (Re)opens the event stream, everything else is fetched once it's connected.
+    fn connect(&mut self) -> Command<Message> {
+        self.client
+            .as_mut()
+            .map_or_else(Command::none, ClientCmds::connect_events_cmd)
+    }
 }
 
 impl Application for ScreenManager {
@@ -271,32 +413,25 @@ impl Application for ScreenManager {
     fn new(content_store: Self::Flags) -> (Self, Command<Self::Message>) {
         let content_store = Arc::new(content_store);
         let mut manager = ScreenManager::new(content_store.clone());
-        let cmd = if content_store.session_file().exists() {
-            let session_file = content_store.session_file().to_path_buf();
+        let session_files = content_store.session_files();
+
+        let cmd = if session_files.is_empty() {
+            Command::none()
+        } else {
             if let Screen::Login(screen) = manager.screens.current_mut() {
                 screen.waiting = true;
             }
-            Command::perform(
-                async move {
-                    let session_raw = tokio::fs::read(session_file).await?;
-                    let session: Session = toml::de::from_slice(&session_raw)
-                        .map_err(|_| ClientError::MissingLoginInfo)?;
-                    Client::new(
-                        session.homeserver.parse::<Url>().unwrap(),
-                        Some(session.into()),
-                        content_store.clone(),
-                    )
-                    .await
-                },
-                |result| {
-                    result.map_or_else(
-                        |err| Message::Error(err.into()),
-                        |client| Message::LoginComplete(Some(client)),
-                    )
-                },
-            )
-        } else {
-            Command::none()
+            Command::batch(session_files.into_iter().map(|session_file| {
+                Command::perform(
+                    Client::restore_session(session_file, content_store.clone()),
+                    |result| {
+                        result.map_or_else(
+                            |err| Message::Error(err.into()),
+                            |client| Message::LoginComplete(Some(client)),
+                        )
+                    },
+                )
+            }))
         };
         (manager, cmd)
     }
@@ -306,7 +441,61 @@ impl Application for ScreenManager {
     }
 
     fn update(&mut self, msg: Self::Message, clip: &mut iced::Clipboard) -> Command<Self::Message> {
-        if let Some(client) = self.client.as_mut() {
+        let shown_user_id = self.client.as_ref().map(|client| client.user_id).flatten();
+
+        match msg {
+            Message::Account { user_id, message } if shown_user_id != Some(user_id) => {
+                self.update_background(user_id, *message, clip)
+            }
+            Message::Account { message, .. } => self.update(*message, clip),
+            msg => tag_account_cmd(self.handle_message(msg, clip), shown_user_id),
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let time_sub = iced::time::every(Duration::from_secs(5)).map(|_| Message::Nothing);
+        let cache_sub = iced::time::every(SAVE_CACHE_INTERVAL).map(|_| Message::SaveCache);
+        let close_sub = iced_native::subscription::events_with(|ev, _status| match ev {
+            iced_native::Event::Window(iced_native::window::Event::CloseRequested) => {
+                Some(Message::CloseRequested)
+            }
+            _ => None,
+        });
+        let main_sub = self.screens.current().subscription();
+
+        Subscription::batch(vec![time_sub, cache_sub, close_sub, main_sub])
+    }
+
+    fn should_exit(&self) -> bool {
+        self.should_exit
+    }
+
+    fn view(&mut self) -> Element<Self::Message> {
+        match self.screens.current_mut() {
+            Screen::Login(screen) => screen.view(self.theme).map(Message::LoginScreen),
+            Screen::Main(screen) => screen
+                .view(
+                    self.theme,
+                    self.client.as_ref().unwrap(), // This will not panic cause [ref:client_set_before_main_view]
+                    &self.thumbnail_cache,
+                    &self
+                        .accounts
+                        .iter()
+                        .map(BackgroundAccount::summary)
+                        .collect::<Vec<_>>(),
+                )
+                .map(Message::MainScreen),
+            Screen::GuildDiscovery(screen) => screen
+                .view(self.theme, self.client.as_ref().unwrap()) // This will not panic cause [ref:client_set_before_main_view]
+                .map(Message::GuildDiscovery),
+        }
+    }
+}
+
+impl ScreenManager {
+    fn handle_message(&mut self, msg: Message, clip: &mut iced::Clipboard) -> Command<Message> {
+        let background_clients = self.accounts.iter_mut().map(|account| &mut account.client);
+        for client in self.client.iter_mut().chain(background_clients) {
             for member in client.members.values_mut() {
                 member.typing_in_channel = member
                     .typing_in_channel
@@ -316,11 +505,13 @@ impl Application for ScreenManager {
 
         match msg {
             Message::Nothing => {}
+            Message::Account { message, .. } => return self.update(*message, clip),
             Message::LoginScreen(msg) => {
                 if let Screen::Login(screen) = self.screens.current_mut() {
-                    return screen.update(self.client.as_ref(), msg, &self.content_store);
+                    return screen.update(self.login_client.as_ref(), msg, &self.content_store);
                 }
             }
+            Message::MainScreen(main::Message::CopyToClipboard(text)) => clip.write(text),
             Message::MainScreen(msg) => {
                 if let (Screen::Main(screen), Some(client)) =
                     (self.screens.current_mut(), &mut self.client)
@@ -336,8 +527,8 @@ impl Application for ScreenManager {
                 }
             }
             Message::ClientCreated(client) => {
-                self.client = Some(client);
-                let inner = self.client.as_ref().unwrap().inner().clone();
+                let inner = client.inner().clone();
+                self.login_client = Some(client);
                 return Command::perform(
                     async move {
                         inner.begin_auth().await?;
@@ -351,113 +542,132 @@ impl Application for ScreenManager {
                     },
                 );
             }
-            Message::SocketEvent { mut socket, event } => {
-                if self.client.is_some() {
-                    let mut cmds = Vec::with_capacity(2);
-
-                    if let Some(ev) = event {
-                        let cmd = match ev {
-                            Ok(ev) => self.update(Message::EventsReceived(vec![ev]), clip),
-                            Err(err) => self.update(Message::Error(Box::new(err.into())), clip),
-                        };
-                        cmds.push(cmd);
-                    }
+            Message::EventStream { updates, update } => {
+                // The stream was replaced by a new one, which has its own updates
+                let update = match update {
+                    Some(update) => update,
+                    None => return Command::none(),
+                };
 
-                    if !self.socket_reset {
-                        let subs = self.sources_to_add.drain(..).collect::<Vec<_>>();
-                        cmds.push(Command::perform(
-                            async move {
-                                for sub in subs {
-                                    if let Err(err) = socket.add_source(sub).await {
-                                        tracing::error!("can't sub to source: {}", err);
-                                    }
-                                }
-                                let event = socket.get_event().await;
-                                Message::SocketEvent { socket, event }
-                            },
-                            |msg| msg,
-                        ));
-                    } else {
-                        let client = self.client.as_ref().unwrap();
-                        let sources = client.subscribe_to();
-                        let inner = client.inner().clone();
-                        cmds.push(Command::perform(
-                            async move { inner.subscribe_events(sources).await },
-                            |result| {
-                                result.map_or_else(
-                                    |err| Message::Error(Box::new(err.into())),
-                                    |socket| Message::SocketEvent {
-                                        socket: socket.into(),
-                                        event: None,
-                                    },
-                                )
-                            },
-                        ));
-                        self.socket_reset = false;
+                let cmd = match update {
+                    StreamUpdate::Event(event) => {
+                        self.update(Message::EventsReceived(vec![event]), clip)
                     }
-
+                    StreamUpdate::State(state) => {
+                        self.client.as_mut().map_or_else(Command::none, |client| {
+                            client.stream_state_changed_cmd(updates.homeserver(), state)
+                        })
+                    }
+                };
+                let next = Command::perform(updates.next(), Message::from);
+                return Command::batch(vec![cmd, next]);
+            }
+            Message::Federated {
+                homeserver,
+                foreign,
+            } => {
+                if let Some(client) = self.client.as_mut() {
+                    let guild_ids = client.federation_complete(homeserver.clone(), *foreign);
+                    let mut cmds = guild_ids
+                        .into_iter()
+                        .map(|guild_id| {
+                            self.process_post_event(PostProcessEvent::FetchGuildData(guild_id))
+                        })
+                        .collect::<Vec<_>>();
+                    cmds.extend(
+                        self.client
+                            .as_mut()
+                            .unwrap()
+                            .connect_foreign_events_cmd(homeserver),
+                    );
                     return Command::batch(cmds);
                 }
             }
+            Message::FederationFailed { homeserver, err } => {
+                tracing::error!("couldn't log into {}: {}", homeserver.as_str(), err);
+                if let Some(client) = self.client.as_mut() {
+                    client.federation_failed(&homeserver);
+                }
+                // Our own homeserver may still be reachable, so don't treat this as a connection
+                // error, which would take us offline
+                return self.update(
+                    Message::Error(Box::new(ClientError::Custom(format!(
+                        "couldn't log into {}, guilds hosted there won't work",
+                        homeserver.as_str()
+                    )))),
+                    clip,
+                );
+            }
             Message::LoginComplete(maybe_client) => {
-                if let Some(client) = maybe_client {
+                let fresh_login = maybe_client.is_none();
+                let mut client = match maybe_client.or_else(|| self.login_client.take()) {
+                    Some(client) => client,
+                    None => return Command::none(),
+                };
+                let user_id = client.auth_status().session().unwrap().user_id;
+                client.user_id = Some(user_id);
+                if fresh_login {
+                    // Now that we know the account, use its own session file and cache
+                    let homeserver = client.inner().homeserver_url().clone();
+                    client.set_content_store(Arc::new(
+                        self.content_store.for_account(&homeserver, user_id),
+                    ));
+                }
+
+                let already_logged_in = self
+                    .client
+                    .iter()
+                    .chain(self.accounts.iter().map(|account| &account.client))
+                    .any(|client| client.user_id == Some(user_id));
+                if already_logged_in {
+                    if fresh_login {
+                        self.screens.pop();
+                        self.switch_account(user_id);
+                    }
+                    return Command::none();
+                }
+
+                if self.client.is_none() {
                     self.client = Some(client); // This is the only place we set a main screen [tag:client_set_before_main_view]
+                    self.screens
+                        .push(Screen::Main(Box::new(MainScreen::default())));
+                    return self.start_client();
                 }
-                self.screens
-                    .push(Screen::Main(Box::new(MainScreen::default())));
 
-                let client = self.client.as_mut().unwrap();
-                let sources = client.subscribe_to();
-                let inner = client.inner().clone();
-                let ws_cmd = Command::perform(
-                    async move { inner.subscribe_events(sources).await },
-                    |result| {
-                        result.map_or_else(
-                            |err| Message::Error(Box::new(err.into())),
-                            |socket| Message::SocketEvent {
-                                socket: socket.into(),
-                                event: None,
-                            },
-                        )
-                    },
-                );
-                let inner = client.inner().clone();
-                client.user_id = Some(inner.auth_status().session().unwrap().user_id);
-                let self_id = client.user_id.unwrap();
-                let init = Command::perform(
-                    async move {
-                        let self_profile = get_user(&inner, UserId::new(self_id)).await?;
-                        let guilds = get_guild_list(&inner, GetGuildListRequest {}).await?.guilds;
-                        let mut events = guilds
-                            .into_iter()
-                            .map(|guild| {
-                                Event::GuildAddedToList(GuildAddedToList {
-                                    guild_id: guild.guild_id,
-                                    homeserver: guild.host,
-                                })
-                            })
-                            .collect::<Vec<_>>();
-                        events.push(Event::ProfileUpdated(ProfileUpdated {
-                            update_avatar: true,
-                            update_is_bot: true,
-                            update_status: true,
-                            update_username: true,
-                            is_bot: self_profile.is_bot,
-                            new_avatar: self_profile.user_avatar,
-                            new_status: self_profile.user_status,
-                            new_username: self_profile.user_name,
-                            user_id: self_id,
-                        }));
-                        Ok(events)
-                    },
-                    |result| {
-                        result.map_or_else(
-                            |err| Message::Error(Box::new(err)),
-                            Message::EventsReceived,
-                        )
-                    },
-                );
-                return Command::batch(vec![ws_cmd, init]);
+                // We are already showing an account, so this one starts out in the background
+                self.accounts.push(BackgroundAccount::new(client));
+                let cmd = self.with_background_account(self.accounts.len() - 1, Self::start_client);
+                if fresh_login {
+                    // Pop the login screen and show the account the user just logged into
+                    self.screens.pop();
+                    self.switch_account(user_id);
+                }
+                return cmd;
+            }
+            Message::AddAccount => {
+                self.login_client = None;
+                let mut screen = LoginScreen::new(self.content_store.clone());
+                screen.adding_account = true;
+                self.screens.push(Screen::Login(screen));
+            }
+            Message::SwitchAccount(user_id) => {
+                self.switch_account(user_id);
+            }
+            Message::Reconnect => {
+                return self.connect();
+            }
+            Message::SaveCache => {
+                let files = self.take_dirty_cache();
+                if !files.is_empty() {
+                    return save_cache_cmd(files, Message::Nothing);
+                }
+            }
+            Message::CloseRequested => {
+                // Whatever changed since the last save would be lost otherwise
+                return save_cache_cmd(self.take_dirty_cache(), Message::Exit);
+            }
+            Message::Exit => {
+                self.should_exit = true;
             }
             Message::PopScreen => {
                 self.screens.pop();
@@ -467,8 +677,14 @@ impl Application for ScreenManager {
             }
             Message::Logout(screen) => {
                 self.client = None;
-                self.socket_reset = false;
-                self.screens.clear(*screen);
+                if self.accounts.is_empty() {
+                    self.screens.clear(*screen);
+                } else {
+                    // Show the next account we are still logged into
+                    let account = self.accounts.remove(0);
+                    self.client = Some(account.client);
+                    self.screens.clear(Screen::Main(account.screen));
+                }
             }
             Message::MessageSent {
                 message_id,
@@ -476,56 +692,119 @@ impl Application for ScreenManager {
                 guild_id,
                 channel_id,
             } => {
-                if let Some(msg) = self
+                if let Some(channel) = self
                     .client
                     .as_mut()
                     .map(|client| client.get_channel(guild_id, channel_id))
                     .flatten()
-                    .map(|channel| {
-                        channel
-                            .messages
-                            .iter_mut()
-                            .find(|msg| msg.id.transaction_id() == Some(transaction_id))
-                    })
-                    .flatten()
                 {
-                    msg.id = MessageId::Ack(message_id);
+                    channel.ack_message(transaction_id, message_id);
+                }
+
+                if let Some(client) = self.client.as_mut() {
+                    client.outbox_done(transaction_id);
                 }
             }
             Message::MessageEdited {
                 guild_id,
                 channel_id,
                 message_id,
-                err,
+                transaction_id,
             } => {
                 let client = self.client.as_mut().unwrap();
+                client.outbox_done(transaction_id);
 
                 if let Some(msg) = client
                     .get_channel(guild_id, channel_id)
-                    .map(|c| {
-                        c.messages
-                            .iter_mut()
-                            .find(|m| m.id.id() == Some(message_id))
-                    })
+                    .map(|c| c.messages.by_id_mut(MessageId::Ack(message_id)))
                     .flatten()
                 {
                     msg.being_edited = None;
                 }
+            }
+            Message::OutboxAttemptFailed {
+                transaction_id,
+                err,
+            } => {
+                if let Some(client) = self.client.as_mut() {
+                    match client.outbox_attempt_failed_cmd(transaction_id) {
+                        Some(cmd) => return cmd,
+                        // We gave up on this entry, let the user know why
+                        None if client.outbox.contains_key(&transaction_id) => {
+                            return self.update(Message::Error(err), clip);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Message::MessagePinned {
+                guild_id,
+                channel_id,
+                message_id,
+                pinned,
+            } => {
+                if let Some(client) = self.client.as_mut() {
+                    client.set_message_pinned(guild_id, channel_id, message_id, pinned);
+                    // Someone else may have changed the pins too
+                    return client.fetch_pinned_messages_cmd(guild_id, channel_id);
+                }
+            }
+            Message::MessageDeleteFailed {
+                guild_id,
+                channel_id,
+                message,
+                err,
+            } => {
+                if let Some(client) = self.client.as_mut() {
+                    client.restore_message(guild_id, channel_id, *message);
+                }
 
-                if let Some(err) = err {
-                    return self.update(Message::Error(err), clip);
+                return self.update(Message::Error(err), clip);
+            }
+            Message::FetchFailed {
+                guild_id,
+                channel_id,
+                err,
+            } => {
+                if let Some(client) = self.client.as_mut() {
+                    client.fetch_failed(guild_id, channel_id);
+                }
+
+                return self.update(Message::Error(err), clip);
+            }
+            Message::ReactionFailed {
+                guild_id,
+                channel_id,
+                message_id,
+                emote,
+                added,
+                err,
+            } => {
+                // Revert what we showed optimistically
+                if let Some(message) = self
+                    .client
+                    .as_mut()
+                    .map(|client| client.get_message(guild_id, channel_id, message_id))
+                    .flatten()
+                {
+                    if added {
+                        message.remove_reaction(&emote, true);
+                    } else {
+                        message.add_reaction(&emote, true);
+                    }
                 }
+
+                return self.update(Message::Error(err), clip);
             }
             Message::SendMessage {
                 message,
-                retry_after,
                 guild_id,
                 channel_id,
             } => {
                 if let Some(cmd) = self
                     .client
                     .as_mut()
-                    .map(|c| c.send_msg_cmd(guild_id, channel_id, retry_after, message))
+                    .map(|c| c.send_msg_cmd(guild_id, channel_id, message))
                     .flatten()
                 {
                     return cmd;
@@ -556,65 +835,81 @@ impl Application for ScreenManager {
 
                     let mut cmds = Vec::with_capacity(processed.len());
 
-                    for sub in processed.iter().flat_map(|post| {
+                    for guild_id in processed.iter().flat_map(|post| {
                         if let PostProcessEvent::FetchGuildData(id) = post {
-                            Some(EventSource::Guild(*id))
+                            Some(*id)
                         } else {
                             None
                         }
                     }) {
-                        self.sources_to_add.push(sub);
+                        self.client.as_mut().unwrap().subscribe_guild(guild_id);
                     }
 
-                    let mut fetch_users = Vec::with_capacity(64);
+                    // New messages in the channel that is shown are read right away
+                    if !self.in_background {
+                        if let Some((guild_id, channel_id)) =
+                            self.screens.stack.iter().find_map(|screen| match screen {
+                                Screen::Main(screen) => screen.current_channel(),
+                                _ => None,
+                            })
+                        {
+                            self.client
+                                .as_mut()
+                                .unwrap()
+                                .mark_channel_read(guild_id, channel_id);
+                        }
+                    }
+
+                    // Profiles are fetched in bulk, from the homeserver of the guild
+                    let mut fetch_users = IndexMap::<u64, Vec<u64>>::new();
 
                     for post in processed {
-                        if let PostProcessEvent::FetchProfile(user_id) = post {
-                            fetch_users.push(user_id);
+                        if let PostProcessEvent::FetchProfile { guild_id, user_id } = post {
+                            fetch_users.entry(guild_id).or_default().push(user_id);
                         } else {
                             cmds.push(self.process_post_event(post));
                         }
                     }
 
-                    for chunk in fetch_users.chunks(64).map(|c| c.to_vec()) {
-                        if !chunk.is_empty() {
-                            let inner = self.client.as_ref().unwrap().inner().clone();
-                            let fetch_users_cmd = Command::perform(
-                                async move {
-                                    let profiles = get_user_bulk(&inner, chunk.clone()).await?;
-                                    Ok(profiles
-                                        .users
-                                        .into_iter()
-                                        .zip(chunk.into_iter())
-                                        .map(|(profile, user_id)| {
-                                            Event::ProfileUpdated(ProfileUpdated {
-                                                user_id,
-                                                new_avatar: profile.user_avatar,
-                                                new_status: profile.user_status,
-                                                new_username: profile.user_name,
-                                                is_bot: profile.is_bot,
-                                                update_is_bot: true,
-                                                update_status: true,
-                                                update_avatar: true,
-                                                update_username: true,
-                                            })
-                                        })
-                                        .collect::<Vec<_>>())
-                                },
-                                |result| {
-                                    result.map_or_else(
-                                        |err| Message::Error(Box::new(err)),
-                                        Message::EventsReceived,
-                                    )
-                                },
-                            );
-                            cmds.push(fetch_users_cmd);
+                    let client = self.client.as_ref().unwrap();
+                    for (guild_id, user_ids) in fetch_users {
+                        for chunk in user_ids.chunks(64) {
+                            cmds.push(client.fetch_profiles_cmd(guild_id, chunk.to_vec()));
                         }
                     }
 
                     return Command::batch(cmds);
                 }
             }
+            Message::Backfill {
+                guild_id,
+                channel_id,
+                messages,
+                gaps,
+                reached_top,
+            } => {
+                let client = match self.client.as_mut() {
+                    Some(client) => client,
+                    None => return Command::none(),
+                };
+                let posts =
+                    client.process_backfill(guild_id, channel_id, messages, gaps, reached_top);
+
+                // Like new messages, missed messages in the channel that is shown are read
+                let shown = self.screens.stack.iter().find_map(|screen| match screen {
+                    Screen::Main(screen) => screen.current_channel(),
+                    _ => None,
+                });
+                if !self.in_background && shown == Some((guild_id, channel_id)) {
+                    client.mark_channel_read(guild_id, channel_id);
+                }
+
+                let cmds = posts
+                    .into_iter()
+                    .map(|post| self.process_post_event(post))
+                    .collect::<Vec<_>>();
+                return Command::batch(cmds);
+            }
             Message::GetEventsBackwardsResponse {
                 messages,
                 reached_top,
@@ -637,7 +932,22 @@ impl Application for ScreenManager {
                     Vec::new()
                 };
 
-                let cmds = posts.into_iter().map(|post| self.process_post_event(post));
+                let mut cmds = posts
+                    .into_iter()
+                    .map(|post| self.process_post_event(post))
+                    .collect::<Vec<_>>();
+
+                if let (Screen::Main(screen), Some(client)) =
+                    (self.screens.current_mut(), &mut self.client)
+                {
+                    cmds.push(screen.on_history_loaded(
+                        client,
+                        &self.thumbnail_cache,
+                        guild_id,
+                        channel_id,
+                        reached_top,
+                    ));
+                }
 
                 return Command::batch(cmds);
             }
@@ -645,16 +955,19 @@ impl Application for ScreenManager {
                 let err_disp = err.to_string();
                 tracing::error!("{}\n{:?}", err_disp, err);
 
-                if matches!(
-                    &*err,
-                    ClientError::Internal(harmony_rust_sdk::client::error::ClientError::Internal(
-                        harmony_rust_sdk::api::exports::hrpc::client::ClientError::SocketError(_)
-                    ))
-                ) {
-                    self.socket_reset = true;
-                }
-
-                if err_disp.contains("invalid-session") || err_disp.contains("connect error") {
+                let is_connect_error = err_disp.contains("connect error");
+                let adding_account = matches!(
+                    self.screens.current(),
+                    Screen::Login(screen) if screen.adding_account
+                );
+                if is_connect_error
+                    && (self.in_background || matches!(self.screens.current(), Screen::Main(_)))
+                {
+                    // Keep showing the cached data while the homeserver is unreachable, the
+                    // event stream tells whether we are offline
+                } else if self.in_background || adding_account {
+                    // Don't log out of an account because of another account's error
+                } else if err_disp.contains("invalid-session") || is_connect_error {
                     self.update(
                         Message::Logout(
                             Screen::Login(LoginScreen::new(self.content_store.clone())).into(),
@@ -663,45 +976,177 @@ impl Application for ScreenManager {
                     );
                 }
 
+                // The screens belong to the shown account
+                if self.in_background {
+                    return Command::none();
+                }
                 return self.screens.current_mut().on_error(*err);
             }
         }
         Command::none()
     }
 
-    fn subscription(&self) -> Subscription<Self::Message> {
-        let time_sub = iced::time::every(Duration::from_secs(5)).map(|_| Message::Nothing);
-        let main_sub = self.screens.current().subscription();
+    /// Shows what was restored from the cache, resumes the outbox and connects to the
+    /// homeserver.
+    fn start_client(&mut self) -> Command<Message> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Command::none(),
+        };
 
-        Subscription::batch(vec![time_sub, main_sub])
+        // Show whatever we restored from the cache while we are connecting
+        let posts = client.cached_thumbnails(SHOWN_MSGS_LIMIT);
+        let mut cmds = posts
+            .into_iter()
+            .map(|post| self.process_post_event(post))
+            .collect::<Vec<_>>();
+        // Continue sending whatever was left in the outbox
+        cmds.extend(self.client.as_ref().unwrap().resume_outbox_cmds());
+        cmds.push(self.connect());
+        Command::batch(cmds)
     }
 
-    fn view(&mut self) -> Element<Self::Message> {
-        match self.screens.current_mut() {
-            Screen::Login(screen) => screen.view(self.theme).map(Message::LoginScreen),
-            Screen::Main(screen) => screen
-                .view(
-                    self.theme,
-                    self.client.as_ref().unwrap(), // This will not panic cause [ref:client_set_before_main_view]
-                    &self.thumbnail_cache,
-                )
-                .map(Message::MainScreen),
-            Screen::GuildDiscovery(screen) => screen
-                .view(self.theme, self.client.as_ref().unwrap()) // This will not panic cause [ref:client_set_before_main_view]
-                .map(Message::GuildDiscovery),
+    fn account_position(&self, user_id: u64) -> Option<usize> {
+        self.accounts
+            .iter()
+            .position(|account| account.client.user_id == Some(user_id))
+    }
+
+    /// Exchanges the shown account with a background account.
+    fn swap_account(&mut self, account: &mut BackgroundAccount) {
+        if let Some(client) = self.client.as_mut() {
+            std::mem::swap(client, &mut account.client);
+        }
+        if let Some(Screen::Main(screen)) = self
+            .screens
+            .stack
+            .iter_mut()
+            .find(|screen| matches!(screen, Screen::Main(_)))
+        {
+            std::mem::swap(screen, &mut account.screen);
+        }
+    }
+
+    fn switch_account(&mut self, user_id: u64) {
+        if let Some(pos) = self.account_position(user_id) {
+            let mut account = self.accounts.remove(pos);
+            self.swap_account(&mut account);
+            // This is now the account we were showing, so everything in it was seen
+            account.unread = 0;
+            self.accounts.insert(pos, account);
+        }
+    }
+
+    /// Runs `f` as if the background account at `pos` was the shown account.
+    fn with_background_account(
+        &mut self,
+        pos: usize,
+        f: impl FnOnce(&mut Self) -> Command<Message>,
+    ) -> Command<Message> {
+        let mut account = self.accounts.remove(pos);
+        let user_id = account.client.user_id;
+
+        self.swap_account(&mut account);
+        self.in_background = true;
+        let cmd = f(self);
+        self.in_background = false;
+        self.swap_account(&mut account);
+
+        self.accounts.insert(pos, account);
+        tag_account_cmd(cmd, user_id)
+    }
+
+    fn update_background(
+        &mut self,
+        user_id: u64,
+        msg: Message,
+        clip: &mut iced::Clipboard,
+    ) -> Command<Message> {
+        // The account may have been logged out since
+        let pos = match self.account_position(user_id) {
+            Some(pos) => pos,
+            None => return Command::none(),
+        };
+
+        match msg {
+            // Only the shown account can change the screens
+            Message::LoginScreen(_)
+            | Message::MainScreen(_)
+            | Message::GuildDiscovery(_)
+            | Message::PopScreen
+            | Message::PushScreen(_) => Command::none(),
+            Message::Logout(_) => {
+                self.accounts.remove(pos);
+                Command::none()
+            }
+            msg => {
+                let is_unread = |event: &Event| match event {
+                    Event::SentMessage(message_sent) => message_sent
+                        .message
+                        .as_ref()
+                        .map_or(false, |message| message.author_id != user_id),
+                    _ => false,
+                };
+                self.accounts[pos].unread += match &msg {
+                    Message::EventStream {
+                        update: Some(StreamUpdate::Event(event)),
+                        ..
+                    } => is_unread(event) as usize,
+                    Message::EventsReceived(events) => {
+                        events.iter().filter(|event| is_unread(event)).count()
+                    }
+                    _ => 0,
+                };
+
+                self.with_background_account(pos, |manager| manager.handle_message(msg, clip))
+            }
         }
     }
 }
 
+/// Makes the messages produced by `cmd` go to the given account.
+fn tag_account_cmd(cmd: Command<Message>, user_id: Option<u64>) -> Command<Message> {
+    match user_id {
+        Some(user_id) => cmd.map(move |message| match message {
+            Message::Account { .. } | Message::Nothing => message,
+            message => Message::Account {
+                user_id,
+                message: Box::new(message),
+            },
+        }),
+        None => cmd,
+    }
+}
+
+/// Writes cache files to disk, producing `then` once they are written.
+fn save_cache_cmd(files: Vec<(PathBuf, Vec<u8>)>, then: Message) -> Command<Message> {
+    Command::perform(
+        async move {
+            if let Err(err) = write_cache_files(files).await {
+                tracing::error!("couldn't save the local cache: {}", err);
+            }
+            then
+        },
+        |message| message,
+    )
+}
+
 fn make_thumbnail_command(
     client: &Client,
+    guild_id: Option<u64>,
     thumbnail_url: FileId,
     thumbnail_cache: &ThumbnailCache,
 ) -> Command<Message> {
     if !thumbnail_cache.has_thumbnail(&thumbnail_url) {
         let content_path = client.content_store().content_path(&thumbnail_url);
 
-        let inner = client.inner().clone();
+        // Files are hosted on the homeserver of the guild they were seen in
+        let inner = guild_id
+            .map_or_else(
+                || client.inner(),
+                |guild_id| client.inner_for_guild(guild_id),
+            )
+            .clone();
 
         Command::perform(
             async move {
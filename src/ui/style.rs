@@ -1,7 +1,7 @@
-use crate::color;
+use crate::{client, color};
 use iced::{
     button, checkbox, container, pick_list, progress_bar, radio, rule, scrollable, slider,
-    text_input, Color,
+    text_input, Color, Font,
 };
 
 pub const DEF_SIZE: u16 = 20;
@@ -19,12 +19,26 @@ pub const ALT_COLOR: Color = color!(. 0.5, 0.5, 0.5);
 
 pub const AVATAR_WIDTH: u16 = 32;
 
+/// Converts a color from the client to an iced color.
+pub fn iced_color(color: client::color::Color) -> Color {
+    color!(color.r, color.g, color.b, color.a)
+}
+
+/// Font used for code in messages.
+pub const MONOSPACE: Font = Font::External {
+    name: "DejaVu Sans Mono",
+    bytes: include_bytes!("../DejaVuSansMono.ttf"),
+};
+
 #[derive(Debug, Clone, Copy)]
 pub struct Theme {
     dark: bool,
     secondary: bool,
     round: bool,
     embed: bool,
+    highlight: bool,
+    code: bool,
+    quote: bool,
 }
 
 impl Theme {
@@ -39,8 +53,9 @@ impl Theme {
         color!(209, 50, 113),
     ];
 
-    pub const fn calculate_sender_color(&self, name_len: usize) -> Color {
-        Theme::SENDER_COLORS[name_len % Theme::SENDER_COLORS.len()]
+    /// Color for the name of a user that doesn't have a colored role.
+    pub const fn calculate_sender_color(&self, user_id: u64) -> Color {
+        Theme::SENDER_COLORS[(user_id % Theme::SENDER_COLORS.len() as u64) as usize]
     }
 
     pub const fn secondary(mut self) -> Self {
@@ -58,6 +73,32 @@ impl Theme {
         self
     }
 
+    pub const fn highlight(mut self) -> Self {
+        self.highlight = true;
+        self
+    }
+
+    /// Style for code spans and code blocks.
+    pub const fn code(mut self) -> Self {
+        self.code = true;
+        self
+    }
+
+    /// Style for the bar next to a quote.
+    pub const fn quote(mut self) -> Self {
+        self.quote = true;
+        self
+    }
+
+    /// Name of the syntect theme used to highlight code.
+    pub const fn code_highlight_theme(&self) -> &'static str {
+        if self.dark {
+            "base16-ocean.dark"
+        } else {
+            "InspiredGitHub"
+        }
+    }
+
     pub fn with_border_color(self, color: Color) -> Box<dyn container::StyleSheet> {
         struct TempBorderColor(container::Style);
 
@@ -81,6 +122,9 @@ impl Default for Theme {
             secondary: false,
             round: false,
             embed: false,
+            highlight: false,
+            code: false,
+            quote: false,
         }
     }
 }
@@ -88,7 +132,11 @@ impl Default for Theme {
 impl From<Theme> for Box<dyn container::StyleSheet> {
     fn from(theme: Theme) -> Self {
         if theme.dark {
-            if theme.secondary {
+            if theme.code {
+                dark::CodeContainer.into()
+            } else if theme.quote {
+                dark::QuoteBarContainer.into()
+            } else if theme.secondary {
                 if theme.round {
                     dark::BrightRoundContainer.into()
                 } else {
@@ -132,7 +180,9 @@ impl From<Theme> for Box<dyn text_input::StyleSheet> {
 impl From<Theme> for Box<dyn button::StyleSheet> {
     fn from(theme: Theme) -> Self {
         if theme.dark {
-            if theme.secondary {
+            if theme.highlight {
+                dark::HighlightButton.into()
+            } else if theme.secondary {
                 dark::DarkButton.into()
             } else if theme.embed {
                 dark::EmbedButton.into()
@@ -215,6 +265,16 @@ impl From<Theme> for Box<dyn iced_aw::modal::StyleSheet> {
     }
 }
 
+impl From<Theme> for Box<dyn iced_aw::card::StyleSheet> {
+    fn from(theme: Theme) -> Self {
+        if theme.dark {
+            dark::Card.into()
+        } else {
+            Default::default()
+        }
+    }
+}
+
 mod light {
     use crate::color;
     use iced::{button, Color, Vector};
@@ -248,7 +308,7 @@ mod dark {
         button, checkbox, container, pick_list, progress_bar, radio, rule, scrollable, slider,
         text_input, Color,
     };
-    use iced_aw::modal;
+    use iced_aw::{card, modal};
 
     const DARK_BG: Color = color!(0x36, 0x39, 0x3F);
     const BRIGHT_BG: Color = color!(0x44, 0x48, 0x4F);
@@ -268,6 +328,26 @@ mod dark {
         }
     }
 
+    pub struct Card;
+
+    impl card::StyleSheet for Card {
+        fn active(&self) -> card::Style {
+            card::Style {
+                background: DARK_BG.into(),
+                border_radius: 8.0,
+                border_width: 2.0,
+                border_color: ACCENT,
+                head_background: BRIGHT_BG.into(),
+                head_text_color: Color::WHITE,
+                body_background: DARK_BG.into(),
+                body_text_color: Color::WHITE,
+                foot_background: DARK_BG.into(),
+                foot_text_color: Color::WHITE,
+                close_color: Color::WHITE,
+            }
+        }
+    }
+
     pub struct Container;
 
     impl container::StyleSheet for Container {
@@ -306,6 +386,31 @@ mod dark {
         }
     }
 
+    pub struct CodeContainer;
+
+    impl container::StyleSheet for CodeContainer {
+        fn style(&self) -> container::Style {
+            container::Style {
+                background: color!(0x2F, 0x31, 0x36).into(),
+                border_color: BRIGHT_BG,
+                border_radius: 4.0,
+                border_width: 1.0,
+                ..Container.style()
+            }
+        }
+    }
+
+    pub struct QuoteBarContainer;
+
+    impl container::StyleSheet for QuoteBarContainer {
+        fn style(&self) -> container::Style {
+            container::Style {
+                background: color!(0x8E, 0x92, 0x97).into(),
+                ..Container.style()
+            }
+        }
+    }
+
     pub struct BrightContainer;
 
     impl container::StyleSheet for BrightContainer {
@@ -453,6 +558,30 @@ mod dark {
         }
     }
 
+    pub struct HighlightButton;
+
+    impl button::StyleSheet for HighlightButton {
+        fn active(&self) -> button::Style {
+            button::Style {
+                border_width: 1.0,
+                border_color: Color::WHITE,
+                ..DarkButton.hovered()
+            }
+        }
+
+        fn hovered(&self) -> button::Style {
+            self.active()
+        }
+
+        fn pressed(&self) -> button::Style {
+            DarkButton.pressed()
+        }
+
+        fn disabled(&self) -> button::Style {
+            self.active()
+        }
+    }
+
     pub struct EmbedButton;
 
     impl button::StyleSheet for EmbedButton {
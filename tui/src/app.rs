@@ -0,0 +1,988 @@
+//! State of the terminal frontend, and how keys and client outputs change it.
+//!
+//! Operations of [`Client`] are spawned on the runtime, and their outputs come back to
+//! [`App::handle`] through the same channel as the keys, so the state is only changed in one
+//! place like in the GUI.
+
+use crate::input::LineInput;
+
+use crust_client::{
+    error::ClientError,
+    event_stream::StreamUpdate,
+    message::{Message, MessageId},
+    permission,
+    quick_switch::{self, SearchResult},
+    search::{SearchQuery, SearchResult as MessageSearchResult},
+    Client, Output, PostProcessEvent,
+};
+use harmony_rust_sdk::api::chat::event::Event;
+
+use crossterm::event::{Event as TermEvent, KeyCode, KeyEvent, KeyModifiers};
+use indexmap::IndexMap;
+use std::future::Future;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Debug)]
+pub enum AppEvent {
+    Term(TermEvent),
+    Output(Output),
+}
+
+/// Same modes as the main screen of the GUI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    EditingMessage(u64),
+    EditMessage,
+    DeleteMessage,
+    Normal,
+}
+
+/// The pane that gets the keys that aren't shortcuts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Guilds,
+    Channels,
+    Composer,
+}
+
+/// A popup that gets all keys that aren't shortcuts while it's shown.
+#[derive(Debug)]
+pub enum Overlay {
+    QuickSwitch {
+        input: LineInput,
+        results: Vec<SearchResult>,
+        selected: usize,
+    },
+    Search {
+        input: LineInput,
+        results: Vec<MessageSearchResult>,
+        selected: usize,
+    },
+    ConfirmDelete(u64),
+}
+
+pub struct App {
+    pub client: Client,
+    tx: UnboundedSender<AppEvent>,
+    pub current_guild_id: Option<u64>,
+    pub current_channel_id: Option<u64>,
+    /// The channel that was last open in every guild, most recently opened last.
+    pub guild_last_channels: IndexMap<u64, u64>,
+    pub focus: Focus,
+    pub mode: Mode,
+    pub composer: LineInput,
+    pub overlay: Option<Overlay>,
+    /// Position of the cursor in the guild list.
+    pub guild_cursor: usize,
+    /// Position of the cursor in the channel list.
+    pub channel_cursor: usize,
+    /// Message selected for editing or deleting, or jumped to from the search.
+    pub selected_message: Option<MessageId>,
+    /// Whether the timeline should scroll to the selected message when it's drawn next.
+    pub reveal_selected: bool,
+    /// ID of the last read message when the current channel was opened.
+    pub new_messages_divider: Option<u64>,
+    /// How many rows the timeline is scrolled up from its bottom.
+    pub scroll: usize,
+    /// Rows of the timeline that were built when it was last drawn.
+    pub timeline_rows: usize,
+    /// Whether the rows that were built start at the first message of the channel.
+    pub timeline_has_top: bool,
+    /// Rows of the timeline pane when it was last drawn.
+    pub timeline_height: usize,
+    pub error_text: String,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(client: Client, tx: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            client,
+            tx,
+            current_guild_id: None,
+            current_channel_id: None,
+            guild_last_channels: IndexMap::new(),
+            focus: Focus::Guilds,
+            mode: Mode::Normal,
+            composer: LineInput::default(),
+            overlay: None,
+            guild_cursor: 0,
+            channel_cursor: 0,
+            selected_message: None,
+            reveal_selected: false,
+            new_messages_divider: None,
+            scroll: 0,
+            timeline_rows: 0,
+            timeline_has_top: false,
+            timeline_height: 0,
+            error_text: String::new(),
+            quit: false,
+        }
+    }
+
+    /// Runs a future of the client, handing its output back to [`App::handle`].
+    fn spawn(&self, future: impl Future<Output = Output> + Send + 'static) {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            // The receiver is only gone if we are quitting
+            let _ = tx.send(AppEvent::Output(future.await));
+        });
+    }
+
+    /// Connects to the homeserver.
+    ///
+    /// The outbox restored from the cache is the one of the GUI, which sends it when it runs,
+    /// so it's dropped instead of sending its messages twice.
+    pub fn start(&mut self) {
+        let restored = self.client.outbox.keys().copied().collect::<Vec<_>>();
+        for transaction_id in restored {
+            self.client.discard_outbox(transaction_id);
+        }
+        self.connect();
+    }
+
+    /// Opens the event stream, everything else is fetched once it's connected.
+    fn connect(&mut self) {
+        self.spawn(self.client.connect_events());
+    }
+
+    pub fn current_channel(&self) -> Option<(u64, u64)> {
+        self.current_guild_id.zip(self.current_channel_id)
+    }
+
+    /// Returns the IDs of our guilds in the order they are listed, by name.
+    pub fn guild_ids(&self) -> Vec<u64> {
+        let mut guilds = self.client.guilds.iter().collect::<Vec<_>>();
+        guilds.sort_by(|(a_id, a), (b_id, b)| a.name.cmp(&b.name).then(a_id.cmp(b_id)));
+        guilds.into_iter().map(|(guild_id, _)| *guild_id).collect()
+    }
+
+    /// Returns the IDs of the channels of the current guild in the order they are listed.
+    pub fn channel_ids(&self) -> Vec<u64> {
+        self.current_guild_id
+            .map(|guild_id| self.client.guilds.get(&guild_id))
+            .flatten()
+            .map_or_else(Vec::new, |guild| guild.channels.keys().copied().collect())
+    }
+
+    pub fn handle(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Term(TermEvent::Key(key)) => self.handle_key(key),
+            AppEvent::Term(_) => {}
+            AppEvent::Output(output) => self.handle_output(output),
+        }
+    }
+
+    fn handle_output(&mut self, output: Output) {
+        match output {
+            Output::MessageSent {
+                message_id,
+                transaction_id,
+                guild_id,
+                channel_id,
+            } => {
+                if let Some(channel) = self.client.get_channel(guild_id, channel_id) {
+                    channel.ack_message(transaction_id, message_id);
+                }
+                self.client.outbox_done(transaction_id);
+            }
+            Output::MessageEdited {
+                guild_id,
+                channel_id,
+                message_id,
+                transaction_id,
+            } => {
+                self.client.outbox_done(transaction_id);
+                if let Some(message) = self.client.get_message(guild_id, channel_id, message_id) {
+                    message.being_edited = None;
+                }
+            }
+            Output::OutboxAttemptFailed {
+                transaction_id,
+                err,
+            } => match self.client.outbox_attempt_failed(transaction_id) {
+                Some(future) => self.spawn(future),
+                // We gave up on this entry, let the user know why
+                None if self.client.outbox.contains_key(&transaction_id) => self.show_error(*err),
+                None => {}
+            },
+            Output::MessagePinned {
+                guild_id,
+                channel_id,
+                message_id,
+                pinned,
+            } => {
+                self.client
+                    .set_message_pinned(guild_id, channel_id, message_id, pinned);
+            }
+            Output::PinnedMessages {
+                guild_id,
+                channel_id,
+                messages,
+            } => {
+                self.client
+                    .set_pinned_messages(guild_id, channel_id, &messages);
+            }
+            Output::MessageDeleteFailed {
+                guild_id,
+                channel_id,
+                message,
+                err,
+            } => {
+                self.client.restore_message(guild_id, channel_id, *message);
+                self.show_error(*err);
+            }
+            Output::FetchFailed {
+                guild_id,
+                channel_id,
+                err,
+            } => {
+                self.client.fetch_failed(guild_id, channel_id);
+                self.show_error(*err);
+            }
+            Output::ReactionFailed {
+                guild_id,
+                channel_id,
+                message_id,
+                emote,
+                added,
+                err,
+            } => {
+                // Revert what we showed optimistically
+                if let Some(message) = self.client.get_message(guild_id, channel_id, message_id) {
+                    if added {
+                        message.remove_reaction(&emote, true);
+                    } else {
+                        message.add_reaction(&emote, true);
+                    }
+                }
+                self.show_error(*err);
+            }
+            Output::History {
+                guild_id,
+                channel_id,
+                messages,
+                reached_top,
+            } => {
+                if let Some(channel) = self.client.get_channel(guild_id, channel_id) {
+                    channel.loading_messages_history = false;
+                }
+                let posts = self.client.process_get_message_history_response(
+                    guild_id,
+                    channel_id,
+                    messages,
+                    reached_top,
+                );
+                for post in posts {
+                    self.process_post_event(post);
+                }
+            }
+            Output::Backfill {
+                guild_id,
+                channel_id,
+                messages,
+                gaps,
+                reached_top,
+            } => {
+                let posts =
+                    self.client
+                        .process_backfill(guild_id, channel_id, messages, gaps, reached_top);
+                if self.current_channel() == Some((guild_id, channel_id)) {
+                    self.client.mark_channel_read(guild_id, channel_id);
+                }
+                for post in posts {
+                    self.process_post_event(post);
+                }
+            }
+            Output::Events(events) => self.handle_events(events),
+            Output::Federated {
+                homeserver,
+                foreign,
+            } => {
+                let guild_ids = self
+                    .client
+                    .federation_complete(homeserver.clone(), *foreign);
+                for guild_id in guild_ids {
+                    self.process_post_event(PostProcessEvent::FetchGuildData(guild_id));
+                }
+                if let Some(future) = self.client.connect_foreign_events(homeserver) {
+                    self.spawn(future);
+                }
+            }
+            Output::FederationFailed { homeserver, err } => {
+                tracing::error!("couldn't log into {}: {}", homeserver.as_str(), err);
+                self.client.federation_failed(&homeserver);
+                // Our own homeserver may still be reachable, so this doesn't take us offline
+                self.error_text = format!(
+                    "couldn't log into {}, guilds hosted there won't work",
+                    homeserver.as_str()
+                );
+            }
+            Output::EventStream { updates, update } => {
+                // The stream was replaced by a new one, which has its own updates
+                let update = match update {
+                    Some(update) => update,
+                    None => return,
+                };
+                match update {
+                    StreamUpdate::Event(event) => self.handle_events(vec![event]),
+                    StreamUpdate::State(state) => {
+                        let homeserver = updates.homeserver();
+                        for future in self.client.stream_state_changed(homeserver, state) {
+                            self.spawn(future);
+                        }
+                    }
+                }
+                self.spawn(updates.next());
+            }
+            Output::Error(err) => self.show_error(*err),
+            Output::Done => {}
+        }
+    }
+
+    fn handle_events(&mut self, events: Vec<Event>) {
+        let processed = events
+            .into_iter()
+            .flat_map(|event| self.client.process_event(event))
+            .collect::<Vec<_>>();
+
+        for post in &processed {
+            if let PostProcessEvent::FetchGuildData(guild_id) = post {
+                self.client.subscribe_guild(*guild_id);
+            }
+        }
+
+        // New messages in the channel that is shown are read right away
+        if let Some((guild_id, channel_id)) = self.current_channel() {
+            self.client.mark_channel_read(guild_id, channel_id);
+        }
+
+        // Profiles are fetched in bulk, from the homeserver of the guild
+        let mut fetch_users = IndexMap::<u64, Vec<u64>>::new();
+        for post in processed {
+            if let PostProcessEvent::FetchProfile { guild_id, user_id } = post {
+                fetch_users.entry(guild_id).or_default().push(user_id);
+            } else {
+                self.process_post_event(post);
+            }
+        }
+
+        for (guild_id, user_ids) in fetch_users {
+            for chunk in user_ids.chunks(64) {
+                self.spawn(self.client.fetch_profiles(guild_id, chunk.to_vec()));
+            }
+        }
+    }
+
+    fn process_post_event(&mut self, post: PostProcessEvent) {
+        match post {
+            // Images can't be shown in a terminal
+            PostProcessEvent::FetchThumbnail { .. } => {}
+            PostProcessEvent::FetchProfile { guild_id, user_id } => {
+                self.spawn(self.client.fetch_profiles(guild_id, vec![user_id]));
+            }
+            PostProcessEvent::GoToFirstMsgOnChannel(channel_id) => {
+                if self.current_channel_id == Some(channel_id) {
+                    self.scroll = 0;
+                }
+            }
+            PostProcessEvent::FetchGuildData(guild_id) => {
+                if let Some(future) = self.client.fetch_guild_data(guild_id) {
+                    self.spawn(future);
+                }
+            }
+            PostProcessEvent::FetchPermissions {
+                guild_id,
+                channel_ids,
+            } => {
+                self.spawn(self.client.fetch_permissions(guild_id, channel_ids));
+            }
+            PostProcessEvent::Federate(homeserver) => {
+                if let Some(future) = self.client.federate(homeserver) {
+                    self.spawn(future);
+                }
+            }
+            PostProcessEvent::Notify(notification) => {
+                // Don't notify about messages the user is looking at
+                let is_shown = self.current_channel()
+                    == Some((notification.guild_id, notification.channel_id));
+                if !is_shown {
+                    self.client.notifications.push(*notification);
+                }
+            }
+            PostProcessEvent::Nothing => {}
+        }
+    }
+
+    fn show_error(&mut self, err: ClientError) {
+        let err_disp = err.to_string();
+        tracing::error!("{}\n{:?}", err_disp, err);
+        self.error_text = err_disp;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        // Same shortcuts as `MainScreen::subscription` of the GUI
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('q') if ctrl => {
+                self.quit = true;
+                return;
+            }
+            KeyCode::Esc => {
+                self.overlay = None;
+                self.error_text.clear();
+                self.change_mode(Mode::Normal);
+                return;
+            }
+            KeyCode::Char('k') if ctrl => {
+                self.change_mode(Mode::Normal);
+                let mut overlay = Overlay::QuickSwitch {
+                    input: LineInput::default(),
+                    results: Vec::new(),
+                    selected: 0,
+                };
+                self.update_overlay_results(&mut overlay);
+                self.overlay = Some(overlay);
+                return;
+            }
+            KeyCode::Char('f') if ctrl => {
+                self.overlay = Some(Overlay::Search {
+                    input: LineInput::default(),
+                    results: Vec::new(),
+                    selected: 0,
+                });
+                return;
+            }
+            KeyCode::Char('e') if ctrl => {
+                self.overlay = None;
+                self.change_mode(Mode::EditMessage);
+                return;
+            }
+            KeyCode::Char('d') if ctrl => {
+                self.overlay = None;
+                self.change_mode(Mode::DeleteMessage);
+                return;
+            }
+            _ => {}
+        }
+
+        if let Some(overlay) = self.overlay.take() {
+            self.handle_overlay_key(overlay, key);
+            return;
+        }
+
+        match (self.mode, key.code) {
+            (Mode::EditMessage, KeyCode::Up) | (Mode::DeleteMessage, KeyCode::Up) => {
+                self.select_message(true);
+                return;
+            }
+            (Mode::EditMessage, KeyCode::Down) | (Mode::DeleteMessage, KeyCode::Down) => {
+                self.select_message(false);
+                return;
+            }
+            (Mode::EditMessage, KeyCode::Enter) => {
+                if let Some(message_id) = self.selected_message.map(|id| id.id()).flatten() {
+                    self.change_mode(Mode::EditingMessage(message_id));
+                }
+                return;
+            }
+            (Mode::DeleteMessage, KeyCode::Enter) => {
+                if let Some(message_id) = self.selected_message.map(|id| id.id()).flatten() {
+                    self.overlay = Some(Overlay::ConfirmDelete(message_id));
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        match key.code {
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Guilds => Focus::Channels,
+                    Focus::Channels => Focus::Composer,
+                    Focus::Composer => Focus::Guilds,
+                };
+            }
+            KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Guilds => Focus::Composer,
+                    Focus::Channels => Focus::Guilds,
+                    Focus::Composer => Focus::Channels,
+                };
+            }
+            KeyCode::PageUp => self.scroll_up(self.timeline_height.max(2) / 2),
+            KeyCode::PageDown => self.scroll_down(self.timeline_height.max(2) / 2),
+            KeyCode::Char('r') if ctrl => self.retry_failed(),
+            KeyCode::Char('x') if ctrl => self.discard_failed(),
+            _ => match self.focus {
+                Focus::Guilds => self.handle_guilds_key(key),
+                Focus::Channels => self.handle_channels_key(key),
+                Focus::Composer => self.handle_composer_key(key),
+            },
+        }
+    }
+
+    fn handle_guilds_key(&mut self, key: KeyEvent) {
+        let guild_ids = self.guild_ids();
+        match key.code {
+            KeyCode::Up => self.guild_cursor = self.guild_cursor.saturating_sub(1),
+            KeyCode::Down if self.guild_cursor + 1 < guild_ids.len() => self.guild_cursor += 1,
+            KeyCode::Enter => {
+                if let Some(guild_id) = guild_ids.get(self.guild_cursor) {
+                    self.select_guild(*guild_id);
+                    self.focus = Focus::Channels;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_channels_key(&mut self, key: KeyEvent) {
+        let channel_ids = self.channel_ids();
+        match key.code {
+            KeyCode::Up => self.channel_cursor = self.channel_cursor.saturating_sub(1),
+            KeyCode::Down if self.channel_cursor + 1 < channel_ids.len() => {
+                self.channel_cursor += 1
+            }
+            KeyCode::Enter => {
+                if let Some(channel_id) = channel_ids.get(self.channel_cursor) {
+                    self.select_channel(*channel_id);
+                    if self.current_channel_id == Some(*channel_id) {
+                        self.focus = Focus::Composer;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_composer_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.send_composer(),
+            KeyCode::Up if self.composer.is_empty() => self.scroll_up(1),
+            KeyCode::Down if self.composer.is_empty() => self.scroll_down(1),
+            _ => {
+                self.composer.handle_key(key);
+            }
+        }
+    }
+
+    fn handle_overlay_key(&mut self, mut overlay: Overlay, key: KeyEvent) {
+        if let Overlay::ConfirmDelete(message_id) = overlay {
+            if let (KeyCode::Char('y'), Some((guild_id, channel_id))) =
+                (key.code, self.current_channel())
+            {
+                if let Some(future) = self.client.delete_msg(guild_id, channel_id, message_id) {
+                    self.spawn(future);
+                }
+                self.change_mode(Mode::Normal);
+            }
+            // Any other key cancels
+            return;
+        }
+        if key.code == KeyCode::Enter {
+            self.open_overlay_result(overlay);
+            return;
+        }
+
+        let results_len = match &overlay {
+            Overlay::QuickSwitch { results, .. } => results.len(),
+            Overlay::Search { results, .. } => results.len(),
+            Overlay::ConfirmDelete(_) => 0,
+        };
+        let changed = match &mut overlay {
+            Overlay::QuickSwitch {
+                input, selected, ..
+            }
+            | Overlay::Search {
+                input, selected, ..
+            } => match key.code {
+                KeyCode::Up => {
+                    *selected = selected.saturating_sub(1);
+                    false
+                }
+                KeyCode::Down => {
+                    *selected = (*selected + 1).min(results_len.saturating_sub(1));
+                    false
+                }
+                _ => {
+                    let changed = input.handle_key(key);
+                    if changed {
+                        *selected = 0;
+                    }
+                    changed
+                }
+            },
+            Overlay::ConfirmDelete(_) => false,
+        };
+        if changed {
+            self.update_overlay_results(&mut overlay);
+        }
+
+        self.overlay = Some(overlay);
+    }
+
+    fn update_overlay_results(&mut self, overlay: &mut Overlay) {
+        match overlay {
+            Overlay::QuickSwitch { input, results, .. } => {
+                // Recently visited channels are shown until something is typed
+                *results = if input.text().trim().is_empty() {
+                    self.guild_last_channels
+                        .iter()
+                        .rev()
+                        .map(|(guild_id, channel_id)| SearchResult::Channel {
+                            guild_id: *guild_id,
+                            id: *channel_id,
+                            name: self
+                                .client
+                                .guilds
+                                .get(guild_id)
+                                .map(|guild| guild.channels.get(channel_id))
+                                .flatten()
+                                .map_or_else(|| "unknown".to_string(), |c| c.name.clone()),
+                        })
+                        .collect()
+                } else {
+                    quick_switch::search(&self.client.guilds, input.text())
+                };
+            }
+            Overlay::Search { input, results, .. } => {
+                *results = self.client.search(&SearchQuery {
+                    text: input.text().to_string(),
+                    ..Default::default()
+                });
+            }
+            Overlay::ConfirmDelete(_) => {}
+        }
+    }
+
+    fn open_overlay_result(&mut self, overlay: Overlay) {
+        match overlay {
+            Overlay::QuickSwitch {
+                results, selected, ..
+            } => match results.get(selected) {
+                Some(SearchResult::Guild { id, .. }) => self.select_guild(*id),
+                Some(SearchResult::Channel { guild_id, id, .. }) => {
+                    self.open_channel(*guild_id, *id)
+                }
+                None => {}
+            },
+            Overlay::Search {
+                results, selected, ..
+            } => {
+                if let Some(result) = results.get(selected) {
+                    self.open_channel(result.guild_id, result.channel_id);
+                    self.selected_message = Some(MessageId::Ack(result.message_id));
+                    self.reveal_selected = true;
+                }
+            }
+            Overlay::ConfirmDelete(_) => {}
+        }
+    }
+
+    fn open_channel(&mut self, guild_id: u64, channel_id: u64) {
+        if self.current_guild_id != Some(guild_id) {
+            self.select_guild(guild_id);
+        }
+        self.select_channel(channel_id);
+        self.focus = Focus::Composer;
+    }
+
+    fn select_guild(&mut self, guild_id: u64) {
+        self.change_mode(Mode::Normal);
+        self.composer.clear();
+        self.current_guild_id = Some(guild_id);
+        self.current_channel_id = None;
+        self.guild_cursor = self
+            .guild_ids()
+            .iter()
+            .position(|id| *id == guild_id)
+            .unwrap_or_default();
+        self.channel_cursor = 0;
+
+        let offline = self.client.offline;
+        // Try logging into the guild's homeserver again, in case it failed before
+        if let Some(homeserver) = self
+            .client
+            .guilds
+            .get(&guild_id)
+            .map(|guild| guild.homeserver.clone())
+            .flatten()
+        {
+            if !offline {
+                if let Some(future) = self.client.federate(homeserver) {
+                    self.spawn(future);
+                }
+            }
+        }
+
+        let channel_id = self
+            .guild_last_channels
+            .get(&guild_id)
+            .copied()
+            .or_else(|| {
+                self.client.guilds.get(&guild_id).and_then(|guild| {
+                    guild
+                        .channels
+                        .iter()
+                        .find(|(_, channel)| !channel.is_category)
+                        .map(|(channel_id, _)| *channel_id)
+                })
+            });
+        let needs_permissions = self
+            .client
+            .guilds
+            .get(&guild_id)
+            .map_or(false, |guild| guild.permissions.is_empty());
+        if needs_permissions && !offline {
+            self.spawn(self.client.fetch_permissions(guild_id, Vec::new()));
+        }
+        if let Some(future) = self.client.fetch_guild(guild_id) {
+            self.spawn(future);
+        }
+        if let Some(channel_id) = channel_id {
+            self.select_channel(channel_id);
+        }
+    }
+
+    fn select_channel(&mut self, channel_id: u64) {
+        let guild_id = match self.current_guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+        let is_category = self
+            .client
+            .get_channel(guild_id, channel_id)
+            .map_or(true, |channel| channel.is_category);
+        if is_category {
+            return;
+        }
+
+        self.change_mode(Mode::Normal);
+        self.composer.clear();
+        self.selected_message = None;
+        self.scroll = 0;
+        self.current_channel_id = Some(channel_id);
+        self.channel_cursor = self
+            .channel_ids()
+            .iter()
+            .position(|id| *id == channel_id)
+            .unwrap_or_default();
+        // Keep the most recently opened channel last
+        self.guild_last_channels.shift_remove(&guild_id);
+        self.guild_last_channels.insert(guild_id, channel_id);
+
+        let offline = self.client.offline;
+        let needs_permissions = self
+            .client
+            .get_channel(guild_id, channel_id)
+            .map_or(false, |channel| channel.permissions.is_empty());
+        if needs_permissions && !offline {
+            self.spawn(self.client.fetch_permissions(guild_id, vec![channel_id]));
+        }
+        if let Some(future) = self.client.fetch_channel(guild_id, channel_id) {
+            self.spawn(future);
+        }
+        self.new_messages_divider = self
+            .client
+            .get_channel(guild_id, channel_id)
+            .filter(|channel| channel.unread > 0)
+            .map(|channel| channel.last_read)
+            .flatten();
+        self.client.mark_channel_read(guild_id, channel_id);
+    }
+
+    fn change_mode(&mut self, mode: Mode) {
+        let user_id = self.client.user_id;
+        let current = self.current_channel();
+
+        if let Mode::EditingMessage(message_id) = mode {
+            // Only our own messages can be edited
+            let content = current
+                .map(|(guild_id, channel_id)| {
+                    self.client.get_message(guild_id, channel_id, message_id)
+                })
+                .flatten()
+                .filter(|message| Some(message.sender) == user_id)
+                .map(|message| message.content.clone());
+            match content {
+                Some(content) => {
+                    self.composer.set(content);
+                    self.focus = Focus::Composer;
+                }
+                None => return,
+            }
+        }
+        if let (Mode::EditingMessage(_), Mode::Normal) = (self.mode, mode) {
+            self.composer.clear();
+        }
+
+        self.mode = mode;
+        self.selected_message = None;
+        if matches!(mode, Mode::EditMessage | Mode::DeleteMessage) {
+            // Start from the latest message that can be picked
+            self.selected_message = self.selectable_messages().last().copied();
+            self.reveal_selected = true;
+        }
+    }
+
+    /// Returns the messages of the current channel that can be picked in the current mode,
+    /// oldest first.
+    fn selectable_messages(&self) -> Vec<MessageId> {
+        let user_id = self.client.user_id;
+        let channel = match self
+            .current_channel()
+            .map(|(guild_id, channel_id)| {
+                self.client
+                    .guilds
+                    .get(&guild_id)
+                    .map(|guild| guild.channels.get(&channel_id))
+            })
+            .flatten()
+            .flatten()
+        {
+            Some(channel) => channel,
+            None => return Vec::new(),
+        };
+        let can_delete_others = channel.has_perm(permission::DELETE_MESSAGES) == Some(true);
+
+        channel
+            .messages
+            .iter()
+            .filter(|message| message.id.is_ack())
+            .filter(|message| {
+                let is_own = Some(message.sender) == user_id;
+                match self.mode {
+                    Mode::EditMessage => is_own,
+                    Mode::DeleteMessage => is_own || can_delete_others,
+                    _ => false,
+                }
+            })
+            .map(|message| message.id)
+            .collect()
+    }
+
+    /// Moves the selection to the previous or next message that can be picked.
+    fn select_message(&mut self, previous: bool) {
+        let selectable = self.selectable_messages();
+        let pos = self
+            .selected_message
+            .map(|id| selectable.iter().position(|selectable| *selectable == id))
+            .flatten();
+
+        let new_pos = match pos {
+            Some(0) if previous => {
+                // There may be more of our messages further up
+                self.fetch_history();
+                Some(0)
+            }
+            Some(pos) if previous => Some(pos - 1),
+            Some(pos) => Some((pos + 1).min(selectable.len().saturating_sub(1))),
+            None => selectable.len().checked_sub(1),
+        };
+
+        self.selected_message = new_pos.map(|pos| selectable[pos]);
+        self.reveal_selected = true;
+    }
+
+    fn send_composer(&mut self) {
+        let (guild_id, channel_id) = match self.current_channel() {
+            Some(current) => current,
+            None => return,
+        };
+
+        // Only refuse if we know we can't send, not while we are still asking
+        let can_send = self
+            .client
+            .get_channel(guild_id, channel_id)
+            .map_or(false, |channel| {
+                channel.has_perm(permission::SEND_MESSAGES) != Some(false)
+            });
+        if !can_send {
+            self.error_text = "You don't have permission to send messages here".to_string();
+            return;
+        }
+
+        if self.composer.text().trim().is_empty() {
+            self.change_mode(Mode::Normal);
+            return;
+        }
+
+        let content = self.composer.take().trim().to_string();
+        match self.mode {
+            Mode::EditingMessage(message_id) => {
+                if let Some(message) = self.client.get_message(guild_id, channel_id, message_id) {
+                    message.being_edited = Some(content.clone());
+                }
+                self.mode = Mode::Normal;
+                if let Some(future) = self
+                    .client
+                    .edit_msg(guild_id, channel_id, message_id, content)
+                {
+                    self.spawn(future);
+                }
+            }
+            Mode::Normal => {
+                let message = Message {
+                    content,
+                    sender: self.client.user_id.unwrap_or_default(),
+                    ..Default::default()
+                };
+                if let Some(future) = self.client.send_msg(guild_id, channel_id, message) {
+                    self.scroll = 0;
+                    self.spawn(future);
+                }
+            }
+            Mode::EditMessage | Mode::DeleteMessage => {}
+        }
+    }
+
+    /// Returns the outbox entries of the current channel that we gave up sending.
+    fn failed_outbox_entries(&self) -> Vec<u64> {
+        let current = self.current_channel();
+        self.client
+            .outbox
+            .iter()
+            .filter(|(_, entry)| {
+                entry.failed && Some((entry.guild_id, entry.channel_id)) == current
+            })
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect()
+    }
+
+    fn retry_failed(&mut self) {
+        for transaction_id in self.failed_outbox_entries() {
+            if let Some(future) = self.client.retry_outbox(transaction_id) {
+                self.spawn(future);
+            }
+        }
+    }
+
+    fn discard_failed(&mut self) {
+        for transaction_id in self.failed_outbox_entries() {
+            self.client.discard_outbox(transaction_id);
+        }
+    }
+
+    fn fetch_history(&mut self) {
+        if let Some((guild_id, channel_id)) = self.current_channel() {
+            if let Some(future) = self.client.fetch_history(guild_id, channel_id) {
+                self.spawn(future);
+            }
+        }
+    }
+
+    fn scroll_up(&mut self, rows: usize) {
+        let max_scroll = self.timeline_rows.saturating_sub(self.timeline_height);
+        self.scroll = (self.scroll + rows).min(max_scroll);
+        // Load older messages once we reach the top
+        if self.scroll == max_scroll && self.timeline_has_top {
+            self.fetch_history();
+        }
+    }
+
+    fn scroll_down(&mut self, rows: usize) {
+        self.scroll = self.scroll.saturating_sub(rows);
+    }
+}
@@ -0,0 +1,143 @@
+//! A single line text input, used by the composer and the overlays.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Default)]
+pub struct LineInput {
+    text: String,
+    /// Byte offset of the cursor in `text`.
+    cursor: usize,
+}
+
+impl LineInput {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the text, putting the cursor at its end.
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Returns the text and clears the input.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(pos, _)| pos)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    /// Handles an editing key.
+    ///
+    /// Returns whether the text changed, keys that aren't for editing are ignored.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('u') if ctrl => {
+                self.text.replace_range(..self.cursor, "");
+                self.cursor = 0;
+                true
+            }
+            KeyCode::Char('w') if ctrl => {
+                let before = self.text[..self.cursor].trim_end();
+                let start = before
+                    .char_indices()
+                    .rev()
+                    .find(|(_, c)| c.is_whitespace())
+                    .map_or(0, |(pos, c)| pos + c.len_utf8());
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+                true
+            }
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+                true
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                let start = self.prev_boundary();
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+                true
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                let end = self.next_boundary();
+                self.text.replace_range(self.cursor..end, "");
+                true
+            }
+            KeyCode::Left => {
+                self.cursor = self.prev_boundary();
+                false
+            }
+            KeyCode::Right => {
+                self.cursor = self.next_boundary();
+                false
+            }
+            KeyCode::Home => {
+                self.cursor = 0;
+                false
+            }
+            KeyCode::End => {
+                self.cursor = self.text.len();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the part of the text that fits in `width` columns while keeping the cursor in
+    /// view, and the column of the cursor in it.
+    pub fn visible(&self, width: usize) -> (&str, usize) {
+        if width == 0 {
+            return ("", 0);
+        }
+
+        // Scroll horizontally so the cursor is on the last column at most
+        let mut start = self.cursor;
+        let mut cursor_x = 0;
+        for (pos, c) in self.text[..self.cursor].char_indices().rev() {
+            let c_width = c.width().unwrap_or(0);
+            if cursor_x + c_width >= width {
+                break;
+            }
+            cursor_x += c_width;
+            start = pos;
+        }
+
+        let mut end = self.cursor;
+        let mut used = cursor_x;
+        for c in self.text[self.cursor..].chars() {
+            let c_width = c.width().unwrap_or(0);
+            if used + c_width > width {
+                break;
+            }
+            used += c_width;
+            end += c.len_utf8();
+        }
+
+        (&self.text[start..end], cursor_x)
+    }
+}
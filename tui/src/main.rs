@@ -0,0 +1,138 @@
+//! Terminal frontend of Crust, for an account that was logged into with Crust.
+//!
+//! Shows guilds, channels and messages like the GUI, and reads its session and local cache.
+//! Nothing is written to the cache, so this can be used while the GUI is running, but messages
+//! that couldn't be sent yet are lost when quitting. Logs are written to the log file of Crust,
+//! since the terminal is taken by the interface.
+
+use app::{App, AppEvent};
+
+use crossterm::{
+    event,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use crust_client::{
+    content::ContentStore,
+    error::{ClientError, ClientResult},
+    Client, Session,
+};
+use std::{
+    fs::OpenOptions,
+    io::{self, Stdout},
+    process,
+    sync::Arc,
+};
+use structopt::StructOpt;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tracing_subscriber::EnvFilter;
+use tui::{backend::CrosstermBackend, Terminal};
+
+mod app;
+mod input;
+mod timeline;
+mod view;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "crust-tui",
+    about = "Chat on Harmony from a terminal, with an account that was logged into with Crust."
+)]
+struct Args {
+    /// User ID of the account to use, needed if you are logged into more than one account
+    #[structopt(long)]
+    account: Option<u64>,
+}
+
+type CrosstermTerminal = Terminal<CrosstermBackend<Stdout>>;
+
+fn setup_terminal() -> io::Result<CrosstermTerminal> {
+    terminal::enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    crossterm::execute!(stdout, EnterAlternateScreen)?;
+    Terminal::new(CrosstermBackend::new(stdout))
+}
+
+fn restore_terminal() {
+    // Nothing else can be done if these fail, the terminal is gone
+    let _ = terminal::disable_raw_mode();
+    let _ = crossterm::execute!(io::stdout(), LeaveAlternateScreen, crossterm::cursor::Show);
+}
+
+/// Reads terminal events on their own thread, since reading them blocks.
+fn spawn_input_reader(tx: UnboundedSender<AppEvent>) {
+    std::thread::spawn(move || loop {
+        match event::read() {
+            Ok(event) => {
+                if tx.send(AppEvent::Term(event)).is_err() {
+                    break;
+                }
+            }
+            Err(err) => {
+                tracing::error!("couldn't read terminal events: {}", err);
+                break;
+            }
+        }
+    });
+}
+
+async fn run(args: Args) -> ClientResult<()> {
+    let content_store = ContentStore::default();
+    content_store.create_req_dirs()?;
+
+    let log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(content_store.log_file())?;
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from("info")),
+        )
+        .with_ansi(false)
+        .with_writer(move || {
+            log_file
+                .try_clone()
+                .expect("couldn't clone the handle of the log file")
+        })
+        .init();
+
+    let session_file = Session::find_file(&content_store, args.account).await?;
+    let client = Client::restore_session(session_file, Arc::new(content_store)).await?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut app = App::new(client, tx.clone());
+    app.start();
+    spawn_input_reader(tx);
+
+    let mut terminal = setup_terminal()?;
+    // Don't leave the terminal unusable if we panic
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    let result = async {
+        while !app.quit {
+            terminal.draw(|f| view::draw(f, &mut app))?;
+            match rx.recv().await {
+                Some(event) => app.handle(event),
+                None => break,
+            }
+        }
+        Ok::<_, ClientError>(())
+    }
+    .await;
+
+    restore_terminal();
+    result
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::from_args();
+
+    if let Err(err) = run(args).await {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
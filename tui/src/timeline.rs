@@ -0,0 +1,442 @@
+//! Lines of the timeline, grouped like the event history of the GUI.
+//!
+//! Messages are grouped under the name of their sender. A new group starts when the sender
+//! changes, at the first unread message, or after five quiet minutes. Days are separated by
+//! their date.
+
+use crust_client::{
+    channel::Channel,
+    guild::Guild,
+    member::Members,
+    message::{Message, MessageId},
+    notification_rules::{mentions_user, NotificationRules},
+};
+use harmony_rust_sdk::api::harmonytypes::r#override::Reason;
+
+use chrono::Timelike;
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+use unicode_width::UnicodeWidthChar;
+
+/// How many characters of a replied message are quoted.
+const REPLY_QUOTE_LENGTH: usize = 80;
+/// Width of the column that has the time messages were sent at.
+const TIMESTAMP_WIDTH: usize = 6;
+
+pub const ALT_COLOR: Color = Color::Rgb(128, 128, 128);
+pub const ERROR_COLOR: Color = Color::Rgb(255, 0, 0);
+const DIM_COLOR: Color = Color::Rgb(160, 160, 160);
+const PENDING_COLOR: Color = Color::Rgb(200, 200, 200);
+const HIGHLIGHT_COLOR: Color = Color::Rgb(252, 210, 0);
+const NEW_MESSAGES_COLOR: Color = Color::Rgb(240, 71, 71);
+
+/// Same colors as the GUI, so users look the same in both.
+const SENDER_COLORS: [Color; 8] = [
+    Color::Rgb(109, 221, 24),
+    Color::Rgb(252, 210, 0),
+    Color::Rgb(204, 249, 255),
+    Color::Rgb(61, 219, 140),
+    Color::Rgb(221, 106, 53),
+    Color::Rgb(226, 34, 69),
+    Color::Rgb(9, 229, 56),
+    Color::Rgb(209, 50, 113),
+];
+
+/// A row of the timeline.
+pub struct TimelineLine {
+    pub spans: Spans<'static>,
+    /// Index of the message this row belongs to, in the messages of the channel.
+    pub message: Option<usize>,
+}
+
+/// What the timeline of a channel is built from.
+pub struct Timeline<'a> {
+    pub channel: &'a Channel,
+    pub guild: &'a Guild,
+    pub members: &'a Members,
+    pub notification_rules: &'a NotificationRules,
+    pub current_user_id: u64,
+    /// ID of the last read message when the channel was opened, the divider goes after it.
+    pub new_messages_divider: Option<u64>,
+    /// Message whose edit is being written in the composer.
+    pub editing: Option<u64>,
+    /// Message that is selected for editing or deleting, or that was jumped to.
+    pub selected: Option<MessageId>,
+}
+
+impl<'a> Timeline<'a> {
+    fn sender_color(&self, user_id: u64) -> Color {
+        self.guild.member_color(user_id).map_or_else(
+            || SENDER_COLORS[(user_id % SENDER_COLORS.len() as u64) as usize],
+            |color| Color::Rgb(color.r, color.g, color.b),
+        )
+    }
+
+    fn username(&self, user_id: u64) -> String {
+        self.members
+            .get(&user_id)
+            .map_or_else(String::default, |member| member.username.clone())
+    }
+
+    /// Returns the user shown as the sender of a message, and their username.
+    fn sender(&self, message: &Message) -> (u64, String) {
+        // Messages we are still sending don't have a sender yet
+        let user_id = if !message.id.is_ack() {
+            self.current_user_id
+        } else {
+            message.sender
+        };
+        (user_id, self.username(user_id))
+    }
+
+    fn reply_quote(&self, parent_id: u64) -> String {
+        let parent = self.channel.messages.by_id(MessageId::Ack(parent_id));
+        match parent {
+            Some(parent) => {
+                let parent_name = parent.overrides.as_ref().map_or_else(
+                    || self.username(parent.sender),
+                    |overrides| overrides.name.clone(),
+                );
+                let mut snippet = parent
+                    .content
+                    .chars()
+                    .take_while(|c| *c != '\n')
+                    .take(REPLY_QUOTE_LENGTH)
+                    .collect::<String>();
+                if snippet.len() < parent.content.len() {
+                    snippet.push('…');
+                }
+                format!("↪ {}: {}", parent_name, snippet)
+            }
+            None => "↪ (replied message isn't loaded)".to_string(),
+        }
+    }
+
+    fn content_style(&self, message: &Message) -> Style {
+        let current_username = self
+            .members
+            .get(&self.current_user_id)
+            .map(|member| member.username.as_str());
+        let is_highlighted = message.sender != self.current_user_id
+            && (mentions_user(&message.content, self.current_user_id, current_username)
+                || self.notification_rules.is_highlight(&message.content));
+
+        let style = if !message.id.is_ack() || message.being_edited.is_some() {
+            Style::default().fg(PENDING_COLOR)
+        } else if message.id.id().is_some() && message.id.id() == self.editing {
+            Style::default().fg(ERROR_COLOR)
+        } else if is_highlighted {
+            Style::default().fg(HIGHLIGHT_COLOR)
+        } else {
+            Style::default()
+        };
+
+        if self.selected == Some(message.id) {
+            style.add_modifier(Modifier::REVERSED)
+        } else {
+            style
+        }
+    }
+
+    /// Builds the rows of the messages of the channel from the one at index `first`, wrapped to
+    /// `width` columns.
+    ///
+    /// Messages are grouped like they would be if the whole channel was built, so building only
+    /// the messages that can be seen gives the same rows.
+    pub fn lines(&self, width: usize, first: usize) -> Vec<TimelineLine> {
+        let mut lines = Vec::new();
+        let messages = &self.channel.messages;
+        let window = messages.slice(first.saturating_sub(1)..messages.len());
+        let (mut last_timestamp, mut last_sender_id, mut last_sender_name, mut last_message_id) =
+            match (first, window.first()) {
+                (_, None) => return lines,
+                (0, Some(message)) => (message.timestamp, None, None, None),
+                // The message before the window decides how the first one is grouped
+                (_, Some(previous)) => {
+                    let (sender_id, sender_name) = self.sender(previous);
+                    (
+                        previous.timestamp,
+                        Some(sender_id),
+                        Some(display_name(previous, sender_name)),
+                        previous.id.id(),
+                    )
+                }
+            };
+        let window = if first == 0 {
+            &window[..]
+        } else {
+            &window[1..]
+        };
+
+        for (index, message) in (first..).zip(window.iter().copied()) {
+            let (id_to_use, name_to_use) = self.sender(message);
+            let override_reason = message
+                .overrides
+                .as_ref()
+                .map(|overrides| overrides.reason.as_ref())
+                .flatten()
+                .map(|reason| match reason {
+                    Reason::Bridge(_) => format!("bridged by {}", name_to_use),
+                    Reason::SystemMessage(_) => "system message".to_string(),
+                    Reason::UserDefined(reason) => reason.to_string(),
+                    Reason::Webhook(_) => format!("webhook by {}", name_to_use),
+                    Reason::SystemPlurality(_) => "plurality".to_string(),
+                });
+            let sender_display_name = display_name(message, name_to_use);
+
+            // Messages after the last read one are shown as a new group, below a divider
+            let is_first_unread =
+                self.new_messages_divider.is_some() && last_message_id == self.new_messages_divider;
+            if is_first_unread {
+                push_separator(&mut lines, "New messages".to_string(), NEW_MESSAGES_COLOR);
+            }
+
+            let is_new_day = message.timestamp.date() != last_timestamp.date();
+            if is_new_day {
+                push_separator(
+                    &mut lines,
+                    message.timestamp.format("[%d %B %Y]").to_string(),
+                    DIM_COLOR,
+                );
+            }
+
+            let is_sender_different = is_first_unread
+                || is_new_day
+                || last_sender_id != Some(id_to_use)
+                || last_sender_name.as_ref() != Some(&sender_display_name);
+            let is_quiet_gap = message.timestamp.signed_duration_since(last_timestamp)
+                > chrono::Duration::minutes(5);
+            if is_sender_different || is_quiet_gap {
+                if lines.last().map_or(false, |line| line.message.is_some()) {
+                    lines.push(TimelineLine {
+                        spans: Spans::default(),
+                        message: None,
+                    });
+                }
+
+                let mut header = vec![Span::styled(
+                    format!("[{}]", sender_display_name),
+                    Style::default()
+                        .fg(self.sender_color(id_to_use))
+                        .add_modifier(Modifier::BOLD),
+                )];
+                if let Some(reason) = override_reason {
+                    header.push(Span::styled(
+                        format!(" {}", reason),
+                        Style::default().fg(ALT_COLOR),
+                    ));
+                }
+                push_wrapped(&mut lines, String::new(), header, width, Some(index));
+            }
+
+            let dim = Style::default().fg(DIM_COLOR);
+
+            if let Some(parent_id) = message.reply_to {
+                push_wrapped(
+                    &mut lines,
+                    String::new(),
+                    vec![Span::styled(self.reply_quote(parent_id), dim)],
+                    width,
+                    Some(index),
+                );
+            }
+
+            let timestamp = if is_sender_different
+                || is_quiet_gap
+                || last_timestamp.minute() != message.timestamp.minute()
+            {
+                message.timestamp.format("%H:%M").to_string()
+            } else {
+                String::new()
+            };
+            let content_style = self.content_style(message);
+            let content = message
+                .being_edited
+                .as_deref()
+                .unwrap_or_else(|| message.content.as_str())
+                .replace('\t', "    ");
+            let mut content_lines = content.lines();
+            let first_line = content_lines.next().unwrap_or_default();
+            push_wrapped(
+                &mut lines,
+                timestamp,
+                vec![Span::styled(first_line.to_string(), content_style)],
+                width,
+                Some(index),
+            );
+            for line in content_lines {
+                push_wrapped(
+                    &mut lines,
+                    String::new(),
+                    vec![Span::styled(line.to_string(), content_style)],
+                    width,
+                    Some(index),
+                );
+            }
+
+            if message.failed_to_send {
+                push_wrapped(
+                    &mut lines,
+                    String::new(),
+                    vec![Span::styled(
+                        "Failed to send, Ctrl+R retries and Ctrl+X discards".to_string(),
+                        Style::default().fg(ERROR_COLOR),
+                    )],
+                    width,
+                    Some(index),
+                );
+            }
+
+            for attachment in &message.attachments {
+                push_wrapped(
+                    &mut lines,
+                    String::new(),
+                    vec![Span::styled(
+                        format!("[attachment] {}", attachment.name),
+                        Style::default().fg(Color::Cyan),
+                    )],
+                    width,
+                    Some(index),
+                );
+            }
+
+            for embed in &message.embeds {
+                let bar = Span::styled(
+                    "▍".to_string(),
+                    Style::default().fg(Color::Rgb(embed.color.r, embed.color.g, embed.color.b)),
+                );
+                let mut embed_lines = Vec::with_capacity(embed.fields.len() + 3);
+                if let Some(header) = embed.header.as_ref().filter(|h| !h.text.is_empty()) {
+                    embed_lines.push(Span::styled(header.text.clone(), dim));
+                }
+                embed_lines.push(Span::styled(
+                    embed.title.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ));
+                embed_lines.extend(embed.body.lines().map(|line| Span::raw(line.to_string())));
+                embed_lines.extend(embed.fields.iter().map(|field| {
+                    Span::raw(format!(
+                        "{} {}: {}",
+                        field.title, field.subtitle, field.body
+                    ))
+                }));
+                if let Some(footer) = embed.footer.as_ref().filter(|f| !f.text.is_empty()) {
+                    embed_lines.push(Span::styled(footer.text.clone(), dim));
+                }
+
+                for line in embed_lines {
+                    push_wrapped(
+                        &mut lines,
+                        String::new(),
+                        vec![bar.clone(), line],
+                        width,
+                        Some(index),
+                    );
+                }
+            }
+
+            if !message.reactions.is_empty() {
+                let reactions = message
+                    .reactions
+                    .iter()
+                    .map(|reaction| {
+                        let style = if reaction.reacted {
+                            Style::default().add_modifier(Modifier::BOLD)
+                        } else {
+                            dim
+                        };
+                        Span::styled(format!("{} {}  ", reaction.emote, reaction.count), style)
+                    })
+                    .collect();
+                push_wrapped(&mut lines, String::new(), reactions, width, Some(index));
+            }
+
+            last_sender_id = Some(id_to_use);
+            last_sender_name = Some(sender_display_name);
+            last_message_id = message.id.id();
+            last_timestamp = message.timestamp;
+        }
+
+        lines
+    }
+}
+
+/// Returns the name shown for the sender of a message, given the username of its sender.
+fn display_name(message: &Message, username: String) -> String {
+    message
+        .overrides
+        .as_ref()
+        .map_or(username, |overrides| overrides.name.clone())
+}
+
+fn push_separator(lines: &mut Vec<TimelineLine>, text: String, color: Color) {
+    lines.push(TimelineLine {
+        spans: Spans::from(Span::styled(
+            format!("── {} ──", text),
+            Style::default().fg(color),
+        )),
+        message: None,
+    });
+}
+
+/// Wraps spans after the timestamp column, putting `timestamp` in the column of the first row.
+fn push_wrapped(
+    lines: &mut Vec<TimelineLine>,
+    timestamp: String,
+    spans: Vec<Span<'static>>,
+    width: usize,
+    message: Option<usize>,
+) {
+    let column = format!("{:<width$}", timestamp, width = TIMESTAMP_WIDTH);
+    for (row_index, row) in wrap(spans, width.saturating_sub(TIMESTAMP_WIDTH))
+        .into_iter()
+        .enumerate()
+    {
+        let prefix = if row_index == 0 {
+            column.clone()
+        } else {
+            " ".repeat(TIMESTAMP_WIDTH)
+        };
+        let mut row_spans = Vec::with_capacity(row.len() + 1);
+        row_spans.push(Span::styled(prefix, Style::default().fg(DIM_COLOR)));
+        row_spans.extend(row);
+        lines.push(TimelineLine {
+            spans: Spans::from(row_spans),
+            message,
+        });
+    }
+}
+
+/// Splits spans into rows of at most `width` columns.
+fn wrap(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let width = width.max(1);
+    let mut rows = vec![Vec::new()];
+    let mut row_width = 0;
+
+    for span in spans {
+        let mut current = String::new();
+        for c in span.content.chars() {
+            let c_width = c.width().unwrap_or(0);
+            if row_width + c_width > width && row_width > 0 {
+                if !current.is_empty() {
+                    rows.last_mut()
+                        .unwrap()
+                        .push(Span::styled(std::mem::take(&mut current), span.style));
+                }
+                rows.push(Vec::new());
+                row_width = 0;
+            }
+            current.push(c);
+            row_width += c_width;
+        }
+        if !current.is_empty() {
+            rows.last_mut()
+                .unwrap()
+                .push(Span::styled(current, span.style));
+        }
+    }
+
+    rows
+}
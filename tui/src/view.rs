@@ -0,0 +1,528 @@
+//! Drawing the panes and popups of the terminal frontend.
+
+use crate::{
+    app::{App, Focus, Mode, Overlay},
+    input::LineInput,
+    timeline::{Timeline, ALT_COLOR, ERROR_COLOR},
+};
+
+use crust_client::{event_stream::ConnectionState, permission, quick_switch::SearchResult};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+const GUILD_LIST_WIDTH: u16 = 22;
+const CHANNEL_LIST_WIDTH: u16 = 26;
+const SHORTCUTS: &str =
+    "Tab focus  Ctrl+K switch  Ctrl+F search  Ctrl+E edit  Ctrl+D delete  PgUp/PgDn scroll  Ctrl+Q quit";
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Length(GUILD_LIST_WIDTH),
+                Constraint::Length(CHANNEL_LIST_WIDTH),
+                Constraint::Min(20),
+            ]
+            .as_ref(),
+        )
+        .split(rows[0]);
+    let main = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+        .split(columns[2]);
+
+    draw_guilds(f, app, columns[0]);
+    draw_channels(f, app, columns[1]);
+    draw_timeline(f, app, main[0]);
+    draw_composer(f, app, main[1]);
+    draw_status(f, app, rows[1]);
+    draw_overlay(f, app);
+}
+
+fn pane_block(title: String, focused: bool) -> Block<'static> {
+    let border_style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default().fg(ALT_COLOR)
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .title(title)
+}
+
+fn unread_badge(unread: usize, mentions: usize) -> Option<Span<'static>> {
+    if mentions > 0 {
+        Some(Span::styled(
+            format!(" @{}", mentions),
+            Style::default().fg(ERROR_COLOR),
+        ))
+    } else if unread > 0 {
+        Some(Span::styled(
+            format!(" {}", unread),
+            Style::default().fg(ALT_COLOR),
+        ))
+    } else {
+        None
+    }
+}
+
+fn list_state(selected: Option<usize>) -> ListState {
+    let mut state = ListState::default();
+    state.select(selected);
+    state
+}
+
+fn draw_guilds<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let guild_ids = app.guild_ids();
+    let focused = app.focus == Focus::Guilds && app.overlay.is_none();
+    app.guild_cursor = app.guild_cursor.min(guild_ids.len().saturating_sub(1));
+
+    let items = guild_ids
+        .iter()
+        .map(|guild_id| {
+            let guild = &app.client.guilds[guild_id];
+            let name = if guild.name.is_empty() {
+                guild_id.to_string()
+            } else {
+                guild.name.clone()
+            };
+            let style = if Some(*guild_id) == app.current_guild_id {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let (unread, mentions) = guild.unread_counts();
+            let mut spans = vec![Span::styled(name, style)];
+            spans.extend(unread_badge(unread, mentions));
+            ListItem::new(Spans::from(spans))
+        })
+        .collect::<Vec<_>>();
+
+    // The cursor follows the current guild while the list isn't focused
+    let selected = if focused {
+        Some(app.guild_cursor)
+    } else {
+        app.current_guild_id
+            .map(|current| guild_ids.iter().position(|id| *id == current))
+            .flatten()
+    };
+    let list = List::new(items)
+        .block(pane_block("Guilds".to_string(), focused))
+        .highlight_style(if focused {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        });
+    f.render_stateful_widget(
+        list,
+        area,
+        &mut list_state(selected.filter(|_| !guild_ids.is_empty())),
+    );
+}
+
+fn draw_channels<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let focused = app.focus == Focus::Channels && app.overlay.is_none();
+    let guilds = &app.client.guilds;
+    let guild = match app
+        .current_guild_id
+        .map(|guild_id| guilds.get(&guild_id))
+        .flatten()
+    {
+        Some(guild) => guild,
+        None => {
+            f.render_widget(
+                Paragraph::new("No guild selected")
+                    .style(Style::default().fg(ALT_COLOR))
+                    .block(pane_block("Channels".to_string(), focused)),
+                area,
+            );
+            return;
+        }
+    };
+    app.channel_cursor = app
+        .channel_cursor
+        .min(guild.channels.len().saturating_sub(1));
+
+    let items = guild
+        .channels
+        .iter()
+        .map(|(channel_id, channel)| {
+            // Same prefixes as the channel list of the GUI
+            let (prefix, mut style) = if channel.is_category {
+                ("+", Style::default().fg(ALT_COLOR))
+            } else {
+                ("#", Style::default())
+            };
+            if Some(*channel_id) == app.current_channel_id {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            let mut spans = vec![Span::styled(format!("{}{}", prefix, channel.name), style)];
+            spans.extend(unread_badge(channel.unread, channel.mentions));
+            ListItem::new(Spans::from(spans))
+        })
+        .collect::<Vec<_>>();
+
+    let selected = if focused {
+        Some(app.channel_cursor)
+    } else {
+        app.current_channel_id
+            .map(|current| guild.channels.get_index_of(&current))
+            .flatten()
+    };
+    let list = List::new(items)
+        .block(pane_block(guild.name.clone(), focused))
+        .highlight_style(if focused {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        });
+    f.render_stateful_widget(
+        list,
+        area,
+        &mut list_state(selected.filter(|_| !guild.channels.is_empty())),
+    );
+}
+
+fn draw_timeline<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let guilds = &app.client.guilds;
+    let channel = app
+        .current_channel()
+        .map(|(guild_id, channel_id)| {
+            let guild = guilds.get(&guild_id)?;
+            Some((guild, guild.channels.get(&channel_id)?))
+        })
+        .flatten();
+    let (guild, channel) = match channel {
+        Some(channel) => channel,
+        None => {
+            f.render_widget(
+                Paragraph::new("Pick a channel, or switch to one with Ctrl+K")
+                    .style(Style::default().fg(ALT_COLOR))
+                    .block(pane_block(String::new(), false)),
+                area,
+            );
+            return;
+        }
+    };
+
+    let title = if channel.loading_messages_history {
+        format!("#{} (loading older messages)", channel.name)
+    } else {
+        format!("#{}", channel.name)
+    };
+    let block = pane_block(title, false);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let editing = match app.mode {
+        Mode::EditingMessage(message_id) => Some(message_id),
+        _ => None,
+    };
+    let height = inner.height as usize;
+    let selected_index = app
+        .selected_message
+        .filter(|_| app.reveal_selected)
+        .map(|id| channel.messages.position(id))
+        .flatten();
+    // Every message takes at least one row, so only the messages that fill the rows we are
+    // scrolled up by, the pane and another pane to scroll into are built
+    let first = channel
+        .messages
+        .len()
+        .saturating_sub(app.scroll + height * 2)
+        .min(selected_index.unwrap_or(usize::MAX));
+    let lines = Timeline {
+        channel,
+        guild,
+        members: &app.client.members,
+        notification_rules: &app.client.notification_rules,
+        current_user_id: app.client.user_id.unwrap_or_default(),
+        new_messages_divider: app.new_messages_divider,
+        editing,
+        selected: app.selected_message,
+    }
+    .lines(inner.width as usize, first);
+
+    // Scroll just enough to show the whole selected message
+    if app.reveal_selected {
+        let index = selected_index;
+        let first = lines.iter().position(|line| line.message == index);
+        let last = lines.iter().rposition(|line| line.message == index);
+        if let (Some(_), Some(first), Some(last)) = (index, first, last) {
+            let view_end = lines.len() - app.scroll.min(lines.len());
+            let view_start = view_end.saturating_sub(height);
+            if last >= view_end {
+                app.scroll = lines.len() - (last + 1);
+            } else if first < view_start {
+                app.scroll = lines.len().saturating_sub(first + height);
+            }
+        }
+        app.reveal_selected = false;
+    }
+
+    app.scroll = app.scroll.min(lines.len().saturating_sub(height));
+    app.timeline_rows = lines.len();
+    app.timeline_has_top = first == 0;
+    app.timeline_height = height;
+
+    let end = lines.len() - app.scroll;
+    let start = end.saturating_sub(height);
+    // Pad the top, so the latest messages are at the bottom like in a chat
+    let mut rows = vec![Spans::default(); height - (end - start)];
+    rows.extend(
+        lines
+            .into_iter()
+            .skip(start)
+            .take(end - start)
+            .map(|line| line.spans),
+    );
+    f.render_widget(Paragraph::new(rows), inner);
+}
+
+fn draw_composer<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let focused = app.focus == Focus::Composer && app.overlay.is_none();
+    let channel = app
+        .current_channel()
+        .map(|(guild_id, channel_id)| {
+            app.client
+                .guilds
+                .get(&guild_id)
+                .map(|guild| guild.channels.get(&channel_id))
+        })
+        .flatten()
+        .flatten();
+    // Only hide the composer if we know we can't send, not while we are still asking
+    let can_send = channel.map_or(false, |c| {
+        c.has_perm(permission::SEND_MESSAGES) != Some(false)
+    });
+
+    let (title, hint) = match app.mode {
+        Mode::EditMessage => (
+            "Edit",
+            Some("Select a message to edit with Up/Down and press Enter..."),
+        ),
+        Mode::DeleteMessage => (
+            "Delete",
+            Some("Select a message to delete with Up/Down and press Enter..."),
+        ),
+        _ if channel.is_none() => ("Message", Some("No channel selected")),
+        _ if !can_send => (
+            "Message",
+            Some("You don't have permission to send messages here"),
+        ),
+        Mode::EditingMessage(_) => ("Editing a message, Esc cancels", None),
+        Mode::Normal => ("Message", None),
+    };
+
+    let block = pane_block(title.to_string(), focused);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    match hint {
+        Some(hint) => {
+            f.render_widget(
+                Paragraph::new(hint).style(Style::default().fg(ALT_COLOR)),
+                inner,
+            );
+        }
+        None => {
+            let (text, cursor_x) = app.composer.visible(inner.width as usize);
+            f.render_widget(Paragraph::new(text), inner);
+            if focused {
+                f.set_cursor(inner.x + cursor_x as u16, inner.y);
+            }
+        }
+    }
+}
+
+fn draw_status<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let mut spans = Vec::with_capacity(2);
+    if !app.error_text.is_empty() {
+        spans.push(Span::styled(
+            format!("{} (Esc dismisses)", app.error_text),
+            Style::default().fg(ERROR_COLOR),
+        ));
+    } else {
+        // Streams of other homeservers are only shown while something is wrong with them
+        let broken = std::iter::once((None, app.client.connection))
+            .chain(
+                app.client
+                    .foreign_connections()
+                    .map(|(homeserver, state)| (Some(homeserver), state)),
+            )
+            .filter(|(_, state)| *state != ConnectionState::Connected)
+            .collect::<Vec<_>>();
+        for (homeserver, state) in &broken {
+            let color = match state {
+                ConnectionState::Offline { .. } => ERROR_COLOR,
+                _ => Color::Yellow,
+            };
+            let text = match homeserver {
+                Some(homeserver) => {
+                    format!("{}: {}  ", homeserver.host_str().unwrap_or_default(), state)
+                }
+                None => format!("{}  ", state),
+            };
+            spans.push(Span::styled(text, Style::default().fg(color)));
+        }
+        if broken.is_empty() {
+            spans.push(Span::styled(SHORTCUTS, Style::default().fg(ALT_COLOR)));
+        }
+    }
+
+    let unread_notifications = app.client.notifications.unread();
+    if unread_notifications > 0 {
+        spans.push(Span::styled(
+            format!("  {} new notifications", unread_notifications),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
+/// Returns a rectangle of the given size at the center of `area`, shrunk to fit in it.
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn draw_overlay<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let size = f.size();
+    match &app.overlay {
+        Some(Overlay::ConfirmDelete(_)) => {
+            let area = centered_rect(44, 3, size);
+            f.render_widget(Clear, area);
+            f.render_widget(
+                Paragraph::new("Delete this message? y deletes, any other key cancels").block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(ERROR_COLOR))
+                        .title("Delete"),
+                ),
+                area,
+            );
+        }
+        Some(Overlay::QuickSwitch {
+            input,
+            results,
+            selected,
+        }) => {
+            let items = results
+                .iter()
+                .map(|result| match result {
+                    SearchResult::Guild { name, .. } => format!("*{}", name),
+                    SearchResult::Channel { guild_id, name, .. } => {
+                        let guild_name = app
+                            .client
+                            .guilds
+                            .get(guild_id)
+                            .map_or("unknown", |guild| guild.name.as_str());
+                        format!("#{}  {}", name, guild_name)
+                    }
+                })
+                .collect();
+            draw_picker(
+                f,
+                "Switch to (* for guilds, # for channels)",
+                input,
+                items,
+                *selected,
+                size,
+            );
+        }
+        Some(Overlay::Search {
+            input,
+            results,
+            selected,
+        }) => {
+            let items = results
+                .iter()
+                .map(|result| {
+                    let channel_name = app
+                        .client
+                        .guilds
+                        .get(&result.guild_id)
+                        .map(|guild| guild.channels.get(&result.channel_id))
+                        .flatten()
+                        .map_or("unknown", |channel| channel.name.as_str());
+                    let sender_name = app
+                        .client
+                        .members
+                        .get(&result.sender)
+                        .map_or("unknown", |member| member.username.as_str());
+                    format!(
+                        "{} #{} {}: {}",
+                        result.timestamp.format("%d %b %H:%M"),
+                        channel_name,
+                        sender_name,
+                        result.snippet.replace('\n', " ")
+                    )
+                })
+                .collect();
+            draw_picker(f, "Search messages", input, items, *selected, size);
+        }
+        None => {}
+    }
+}
+
+/// Draws a popup with an input and the results for it.
+fn draw_picker<B: Backend>(
+    f: &mut Frame<B>,
+    title: &str,
+    input: &LineInput,
+    items: Vec<String>,
+    selected: usize,
+    size: Rect,
+) {
+    let area = centered_rect(size.width * 2 / 3, size.height * 2 / 3, size);
+    f.render_widget(Clear, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan))
+        .title(title.to_string());
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(0)].as_ref())
+        .split(inner);
+
+    let (text, cursor_x) = input.visible(parts[0].width.saturating_sub(2) as usize);
+    f.render_widget(
+        Paragraph::new(Spans::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Cyan)),
+            Span::raw(text),
+        ])),
+        parts[0],
+    );
+    f.set_cursor(parts[0].x + 2 + cursor_x as u16, parts[0].y);
+
+    let has_items = !items.is_empty();
+    let list = List::new(items.into_iter().map(ListItem::new).collect::<Vec<_>>())
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(
+        list,
+        parts[1],
+        &mut list_state(Some(selected).filter(|_| has_items)),
+    );
+}